@@ -1,127 +1,897 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 
-use crate::script::{Script, ScriptLoader};
+use crate::script::{Script, ScriptLoader, ScriptStep, StepType};
+use crate::script::validate::has_errors;
 use crate::pty::TerminalController;
-use crate::media::{MediaRecorder, OutputFormat};
+use crate::media::{output_file_name, AsciicastRecorder, MediaConfig, MediaRecorder, OutputFormat, ThemeConfig};
+use crate::media::registry;
+use crate::media::screenshot::ScreenshotGenerator;
+use crate::media::replay::replay_cast;
+use crate::media::apng::save_apng;
+use crate::media::webp::save_webp;
+use crate::diff::{cast_text, screen_text, ScreenDiff};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageFormat};
+use crate::{Kla, StepObserver};
 
+/// Shortest and longest a recorded frame stays up when timed by the wall clock
+const RECORD_FRAME_DELAYS: (Duration, Duration) = (Duration::from_millis(20), Duration::from_secs(10));
+
+#[allow(clippy::too_many_arguments)]
 pub async fn record_command(
     script_path: PathBuf,
     output_dir: PathBuf,
-    format: String,
+    format: Vec<String>,
+    theme: Option<String>,
+    dry_run: bool,
+    update_snapshots: bool,
+    name_template: Option<String>,
+    size: Option<(u16, u16)>,
 ) -> Result<()> {
     println!("🎬 Recording script: {}", script_path.display());
     
     // Load script
-    let script = ScriptLoader::load_from_file(&script_path)
-        .with_context(|| format!("Failed to load script: {}", script_path.display()))?;
+    let mut script = if script_path.as_os_str() == "-" {
+        ScriptLoader::load_from_reader(std::io::stdin().lock()).context("Failed to load script from stdin")?
+    } else {
+        ScriptLoader::load_from_file(&script_path)
+            .with_context(|| format!("Failed to load script: {}", script_path.display()))?
+    };
+    if let Some(description) = &script.description {
+        println!("📖 {}", description);
+    }
+    if let Some((width, height)) = size {
+        script.settings.width = width;
+        script.settings.height = height;
+    }
+    
+    // Parse output formats
+    let output_formats = format
+        .iter()
+        .map(|format| OutputFormat::from_string(format))
+        .collect::<Result<Vec<_>>>()?;
     
-    // Parse output format
-    let output_format = OutputFormat::from_string(&format)?;
+    // Resolve the theme up front so a typo fails before anything runs
+    let theme = theme.unwrap_or_else(|| script.settings.theme.clone());
+    ThemeConfig::resolve(&theme)?;
+    
+    // Recordings replay at the speed the script really ran
+    let mut media_config = MediaConfig {
+        real_time_delays: Some(RECORD_FRAME_DELAYS),
+        ..MediaConfig::default()
+    };
+    if let Some(template) = name_template {
+        media_config.name_template = template;
+    }
+    
+    if dry_run {
+        let issues = script.validate();
+        for issue in &issues {
+            println!("  {}", issue);
+        }
+        if has_errors(&issues) {
+            anyhow::bail!("{} has errors", script_path.display());
+        }
+        
+        println!("🧪 Dry run, nothing will be executed:");
+        for line in describe_plan(&script, &output_dir, &output_formats, &media_config) {
+            println!("  {}", line);
+        }
+        return Ok(());
+    }
     
     // Create output directory
     std::fs::create_dir_all(&output_dir)
         .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
     
-    // Initialize terminal controller
-    let mut terminal = TerminalController::new(&script.settings)?;
-    
-    // Initialize media recorder
-    let mut recorder = MediaRecorder::new(output_format, &output_dir)?;
+    // Snapshots live next to the script, like the script's other fixtures
+    let script_dir = match script_path.parent() {
+        Some(dir) if script_path.as_os_str() != "-" => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let kla = Kla::new()
+        .settings(script.settings.clone())
+        .theme(theme)
+        .formats(output_formats)
+        .media_config(media_config)
+        .output_dir(&output_dir)
+        .snapshot_dir(script_dir.join("snapshots"))
+        .update_snapshots(update_snapshots);
     
     // Execute script
     println!("🚀 Executing {} steps...", script.steps.len());
     
-    for (i, step) in script.steps.iter().enumerate() {
-        println!("📝 Step {}/{}: {:?}", i + 1, script.steps.len(), step.step_type);
-        
-        match step.step_type {
-            crate::script::StepType::Command { ref text, wait } => {
-                terminal.execute_command(text).await?;
-                if let Some(duration) = wait {
-                    tokio::time::sleep(duration).await;
-                }
-            }
-            crate::script::StepType::Type { ref text, speed } => {
-                terminal.type_text(text, speed).await?;
-            }
-            crate::script::StepType::Screenshot { ref name } => {
-                let screenshot_path = output_dir.join(format!("{}.png", name));
-                recorder.take_screenshot(&terminal, &screenshot_path).await?;
-                println!("📸 Screenshot saved: {}", screenshot_path.display());
-            }
-            crate::script::StepType::RecordGif { duration, ref name } => {
-                let gif_path = output_dir.join(format!("{}.gif", name));
-                recorder.start_gif_recording(&terminal).await?;
-                tokio::time::sleep(duration).await;
-                recorder.stop_gif_recording(&gif_path).await?;
-                println!("🎞️ GIF saved: {}", gif_path.display());
-            }
-        }
+    let mut progress = ProgressPrinter { total: script.steps.len() };
+    let result = kla.execute_script_with_observer(&script, &mut progress).await?;
+    
+    for path in &result.screenshots {
+        println!("📸 Screenshot saved: {}", path.display());
+    }
+    for path in &result.recordings {
+        println!("🎞️ Recording saved: {}", path.display());
     }
     
     println!("✅ Recording complete! Output saved to: {}", output_dir.display());
     Ok(())
 }
 
-pub async fn screenshot_command(command: String, output: PathBuf) -> Result<()> {
+/// One line per step describing what a recording would do and where artifacts go
+fn describe_plan(script: &Script, output_dir: &Path, formats: &[OutputFormat], config: &MediaConfig) -> Vec<String> {
+    let script_name = script.file_name();
+    let mut lines: Vec<String> = script
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let output_path = |name: &str, extension: &str| {
+                output_dir.join(output_file_name(&config.name_template, name, &script_name, index + 1, extension))
+            };
+            let detail = match &step.step_type {
+                StepType::Command { stdin, screenshot, wait, retries, .. } => {
+                    let mut detail = String::new();
+                    if let Some(path) = stdin {
+                        detail.push_str(&format!(" < {}", path.display()));
+                    }
+                    if let Some(name) = screenshot {
+                        detail.push_str(&format!(" -> {}", output_path(name, "png").display()));
+                    }
+                    if *retries > 0 {
+                        detail.push_str(&format!(", up to {} retries", retries));
+                    }
+                    if let Some(wait) = wait {
+                        detail.push_str(&format!(", then wait {:?}", wait));
+                    }
+                    detail
+                }
+                StepType::Type { speed, .. } => format!(", {:?} per key", speed),
+                StepType::Screenshot { name, theme } => {
+                    format!(" -> {}{}", output_path(name, "png").display(), theme_note(theme))
+                }
+                StepType::Snapshot { name } => format!(" against snapshots/{}.txt", name),
+                StepType::RecordGif { duration, name, fps, theme } => {
+                    let paths: Vec<String> = OutputFormat::animation_formats(formats)
+                        .iter()
+                        .map(|format| output_path(name, format.animation_extension()).display().to_string())
+                        .collect();
+                    format!(" for {:?} at {}fps -> {}{}", duration, fps, paths.join(", "), theme_note(theme))
+                }
+                StepType::Annotate { duration, position, .. } => {
+                    format!(" at {} for {:?}", position.as_deref().unwrap_or("bottom"), duration)
+                }
+                StepType::Repeat { steps, delay, .. } => {
+                    let inner: Vec<String> = steps.iter().map(|step| step.step_type.summary()).collect();
+                    let delay = delay.map(|delay| format!(", {:?} apart", delay)).unwrap_or_default();
+                    format!(": {}{}", inner.join("; "), delay)
+                }
+                _ => String::new(),
+            };
+            let pane = step.pane.as_ref().map(|pane| format!(" in pane {}", pane)).unwrap_or_default();
+            let when = step.when.as_ref().map(|when| format!(" when screen matches /{}/", when)).unwrap_or_default();
+            format!("{}. {}{}{}{}", index + 1, step.step_type.summary(), pane, when, detail)
+        })
+        .collect();
+    
+    if formats.contains(&OutputFormat::Asciicast) {
+        let path = output_dir.join(format!("{}.{}", script.file_name(), OutputFormat::Asciicast.extension()));
+        lines.push(format!("session -> {}", path.display()));
+    }
+    for format in formats {
+        if let OutputFormat::Custom(extension) = format {
+            let path = output_dir.join(format!("{}.{}", script.file_name(), extension));
+            lines.push(format!("session -> {}", path.display()));
+        }
+    }
+    lines
+}
+
+/// Prints a progress line as each script step starts
+struct ProgressPrinter {
+    total: usize,
+}
+
+impl StepObserver for ProgressPrinter {
+    fn on_step_start(&mut self, index: usize, step: &ScriptStep) {
+        println!("📝 Step {}/{}: {}", index + 1, self.total, step.step_type.summary());
+        if let Some(note) = &step.note {
+            println!("   💬 {}", note);
+        }
+    }
+    
+    fn on_step_skipped(&mut self, _index: usize, step: &ScriptStep) {
+        println!("   ⏭️ Skipped: the screen does not match /{}/", step.when.as_deref().unwrap_or_default());
+    }
+}
+
+/// How a plan line mentions a step's own theme, if it has one
+fn theme_note(theme: &Option<String>) -> String {
+    theme.as_ref().map(|theme| format!(" (theme {})", theme)).unwrap_or_default()
+}
+
+pub async fn screenshot_command(
+    command: String,
+    output: PathBuf,
+    theme: String,
+    autofit: bool,
+    size: Option<(u16, u16)>,
+) -> Result<()> {
     println!("📸 Taking screenshot of command: {}", command);
     
+    ThemeConfig::resolve(&theme)?;
+    
     // Create a simple single-command script
-    let script = Script::single_command(&command)?;
+    let mut script = Script::single_command(&command)?;
+    if let Some((width, height)) = size {
+        script.settings.width = width;
+        script.settings.height = height;
+    }
     
     // Initialize terminal
-    let mut terminal = TerminalController::new(&script.settings)?;
+    let mut terminal = TerminalController::new(&script.settings).await?;
     
     // Execute command
     terminal.execute_command(&command).await?;
     
     // Take screenshot
-    let recorder = MediaRecorder::new(OutputFormat::Png, &output.parent().unwrap_or(&PathBuf::from(".")))?;
+    let mut recorder = MediaRecorder::new(OutputFormat::Png, output.parent().unwrap_or(&PathBuf::from(".")))?
+        .with_theme(&theme)
+        .with_config(MediaConfig::builder().autofit(autofit).build());
+    recorder.add_command(&command);
     recorder.take_screenshot(&terminal, &output).await?;
+    terminal.close().await;
     
     println!("✅ Screenshot saved: {}", output.display());
     Ok(())
 }
 
+/// A keypress understood while stepping through an interactive demo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemoKey {
+    /// Run the step shown and move on
+    Next,
+    /// Show the previous step again, to run it once more
+    Back,
+    /// Save a screenshot of the terminal as it is now
+    Screenshot,
+    Quit,
+}
+
+impl DemoKey {
+    fn from_key(code: KeyCode, modifiers: KeyModifiers) -> Option<Self> {
+        match code {
+            KeyCode::Enter => Some(DemoKey::Next),
+            KeyCode::Char('b') => Some(DemoKey::Back),
+            KeyCode::Char('s') => Some(DemoKey::Screenshot),
+            KeyCode::Char('q') => Some(DemoKey::Quit),
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Some(DemoKey::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Wait for a single demo keypress, with the terminal in raw mode only while waiting
+fn read_demo_key() -> Result<DemoKey> {
+    crossterm::terminal::enable_raw_mode()?;
+    let key = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if let Some(demo_key) = DemoKey::from_key(key.code, key.modifiers) {
+                    break Ok(demo_key);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => break Err(err.into()),
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    key
+}
+
 pub async fn demo_command(script_path: PathBuf, interactive: bool) -> Result<()> {
     println!("🎭 Running demo: {}", script_path.display());
     
     let script = ScriptLoader::load_from_file(&script_path)?;
-    let mut terminal = TerminalController::new(&script.settings)?;
+    if let Some(description) = &script.description {
+        println!("📖 {}", description);
+    }
+    let mut keys = read_demo_key;
+    run_demo(&script, interactive.then_some(&mut keys as _), Path::new(".")).await
+}
+
+/// Run `script`'s terminal steps, asking `keys` what to do before each one
+///
+/// Without `keys` every step runs straight through. Screenshots taken with
+/// `s` are written to `screenshot_dir` as `demo-1.png`, `demo-2.png`, ...
+async fn run_demo(
+    script: &Script,
+    mut keys: Option<&mut dyn FnMut() -> Result<DemoKey>>,
+    screenshot_dir: &Path,
+) -> Result<()> {
+    let mut terminal = TerminalController::new(&script.settings).await?;
+    let mut screenshots = 0;
+    let mut i = 0;
     
-    for (i, step) in script.steps.iter().enumerate() {
-        if interactive {
+    while let Some(step) = script.steps.get(i) {
+        if let Some(keys) = keys.as_mut() {
             println!("\n📋 Next step {}/{}: {:?}", i + 1, script.steps.len(), step.step_type);
-            println!("Press Enter to continue...");
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
+            if let Some(note) = &step.note {
+                println!("💬 {}", note);
+            }
+            println!("Enter: run · b: back · s: screenshot · q: quit");
+            match keys()? {
+                DemoKey::Next => {}
+                DemoKey::Back => {
+                    i = i.saturating_sub(1);
+                    continue;
+                }
+                DemoKey::Screenshot => {
+                    screenshots += 1;
+                    let path = screenshot_dir.join(format!("demo-{}.png", screenshots));
+                    MediaRecorder::new(OutputFormat::Png, screenshot_dir)?
+                        .with_theme(&script.settings.theme)
+                        .take_screenshot(&terminal, &path)
+                        .await?;
+                    println!("📸 Screenshot saved: {}", path.display());
+                    continue;
+                }
+                DemoKey::Quit => {
+                    println!("👋 Demo stopped before step {}/{}", i + 1, script.steps.len());
+                    terminal.close().await;
+                    return Ok(());
+                }
+            }
+        } else if let Some(note) = &step.note {
+            println!("💬 {}", note);
         }
         
-        match step.step_type {
-            crate::script::StepType::Command { ref text, wait } => {
-                terminal.execute_command(text).await?;
-                if let Some(duration) = wait {
-                    tokio::time::sleep(duration).await;
-                }
+        run_demo_step(&mut terminal, step).await?;
+        i += 1;
+    }
+    terminal.close().await;
+    
+    println!("✅ Demo complete!");
+    Ok(())
+}
+
+/// Run one step's terminal input, including the steps inside a repeat
+///
+/// A step whose `when` pattern doesn't match the screen is skipped.
+async fn run_demo_step(terminal: &mut TerminalController, step: &ScriptStep) -> Result<()> {
+    if let Some(pattern) = &step.when {
+        terminal.wait_for_settled().await;
+        if !crate::screen_matches(terminal, pattern)? {
+            println!("⏭️ Skipped {}: the screen does not match /{}/", step.step_type.summary(), pattern);
+            return Ok(());
+        }
+    }
+    match &step.step_type {
+        StepType::Command { text, stdin, wait, .. } => {
+            match stdin {
+                Some(path) => terminal.execute_command_with_stdin(text, path).await?,
+                None => terminal.execute_command(text).await?,
             }
-            crate::script::StepType::Type { ref text, speed } => {
-                terminal.type_text(text, speed).await?;
+            if let Some(duration) = wait {
+                tokio::time::sleep(*duration).await;
             }
-            _ => {} // Skip recording steps in demo mode
         }
+        StepType::Type { text, speed, interpret_escapes } => {
+            if *interpret_escapes {
+                terminal.type_text(&crate::script::interpret_escapes(text)?, *speed).await?;
+            } else {
+                terminal.type_text(text, *speed).await?;
+            }
+        }
+        StepType::Input { text } => {
+            terminal.send_input(text).await?;
+        }
+        StepType::Repeat { count, steps, delay } => {
+            for iteration in 1..=*count {
+                if let (true, Some(duration)) = (iteration > 1, delay) {
+                    tokio::time::sleep(*duration).await;
+                }
+                for step in steps {
+                    Box::pin(run_demo_step(terminal, step)).await?;
+                }
+            }
+        }
+        _ => {} // Skip recording steps in demo mode
     }
+    Ok(())
+}
+
+pub fn validate_command(script_path: PathBuf) -> Result<()> {
+    println!("🔍 Validating script: {}", script_path.display());
     
-    println!("✅ Demo complete!");
+    let script = ScriptLoader::load_from_file(&script_path)?;
+    let issues = script.validate();
+    
+    for issue in &issues {
+        println!("  {}", issue);
+    }
+    
+    if has_errors(&issues) {
+        anyhow::bail!("{} has errors", script_path.display());
+    }
+    
+    println!("✅ {} is valid ({} steps)", script_path.display(), script.steps.len());
+    Ok(())
+}
+
+pub fn list_themes_command() -> Result<()> {
+    println!("🎨 Builtin themes:");
+    
+    for name in ThemeConfig::builtin_names() {
+        let theme = ThemeConfig::from_name(name);
+        let swatch: String = std::iter::once(theme.background)
+            .chain(std::iter::once(theme.foreground))
+            .chain(theme.colors.iter().copied())
+            .map(|(r, g, b)| format!("\x1b[48;2;{};{};{}m  ", r, g, b))
+            .collect();
+        println!("  {:<16} {}\x1b[0m", name, swatch);
+    }
+    
+    Ok(())
+}
+
+/// Starter script written by `kla init`
+const STARTER_SCRIPT: &str = r#"# KLA script - run it with: kla record <this file>
+name: "My First Recording"
+
+settings:
+  # Terminal size in columns and rows
+  width: 100
+  height: 25
+  # Shell used to run commands
+  shell: "bash"
+  # Color theme for screenshots and GIFs
+  theme: "dracula"
+
+steps:
+  # Run a command, then pause so its output is visible
+  - type: command
+    text: "echo 'Hello from KLA!'"
+    wait: "1s"
+
+  # Type text character by character like a human would
+  - type: type
+    text: "ls -la"
+    speed: "50ms"
+
+  # Send raw input without pressing Enter (here: just Enter)
+  - type: input
+    text: "\n"
+
+  # Save a PNG of the current screen as output/listing.png
+  - type: screenshot
+    name: "listing"
+
+  # Record an animation of the next few seconds as output/session.gif
+  - type: record_gif
+    duration: "3s"
+    name: "session"
+"#;
+
+pub fn init_command(path: PathBuf, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite)", path.display());
+    }
+    
+    std::fs::write(&path, STARTER_SCRIPT)
+        .with_context(|| format!("Failed to write script: {}", path.display()))?;
+    
+    println!("✨ Created {}", path.display());
+    println!("   Record it with: kla record {}", path.display());
+    Ok(())
+}
+
+pub fn play_command(input: PathBuf, output: PathBuf, theme: String) -> Result<()> {
+    println!("▶️ Playing {} into {}", input.display(), output.display());
+    
+    let extension = output.extension().and_then(|ext| ext.to_str()).unwrap_or("gif");
+    let format = OutputFormat::from_string(extension)?;
+    match format {
+        OutputFormat::Gif | OutputFormat::WebP | OutputFormat::Apng => {}
+        OutputFormat::Mp4 => anyhow::bail!("MP4 output is not supported yet; use a .gif, .webp or .apng output"),
+        _ => anyhow::bail!("Cannot play a cast into .{}; use a .gif, .webp or .apng output", extension),
+    }
+    
+    let theme = ThemeConfig::resolve(&theme)?;
+    let cast = AsciicastRecorder::load(&input)?;
+    let header = cast.header();
+    println!("🎞️ Replaying {} events at {}x{}", cast.event_count(), header.width, header.height);
+    
+    let config = MediaConfig::default();
+    let recorder = replay_cast(&cast, &config, &theme)?;
+    match format {
+        OutputFormat::WebP => save_webp(recorder.frames(), &config, &output)?,
+        OutputFormat::Apng => save_apng(recorder.frames(), &config, &output)?,
+        _ => {
+            let encoding = recorder.save_gif(&output)?;
+            println!(
+                "📦 GIF is {} bytes ({} colors, scale {})",
+                encoding.bytes, encoding.palette_size, encoding.scale
+            );
+        }
+    }
+    
+    println!("✅ Saved {} frames to {}", recorder.frame_count(), output.display());
+    Ok(())
+}
+
+pub fn schema_command(output: Option<PathBuf>) -> Result<()> {
+    let schema = serde_json::to_string_pretty(&ScriptLoader::json_schema())?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, schema + "\n")
+                .with_context(|| format!("Failed to write schema: {}", path.display()))?;
+            println!("📐 Wrote script schema to {}", path.display());
+        }
+        None => println!("{}", schema),
+    }
     Ok(())
 }
 
+pub async fn diff_command(a: PathBuf, b: PathBuf) -> Result<()> {
+    println!("🔍 Comparing {} with {}", a.display(), b.display());
+    
+    let before = recording_text(&a).await?;
+    let after = recording_text(&b).await?;
+    let diff = ScreenDiff::between(&before, &after);
+    
+    if diff.is_empty() {
+        println!("✅ No differences");
+        return Ok(());
+    }
+    print!("{}", diff);
+    anyhow::bail!("{} line(s) differ", diff.changes.len())
+}
+
+/// Final screen text of a cast file, or of running a script
+async fn recording_text(path: &Path) -> Result<String> {
+    if path.extension().is_some_and(|ext| ext == "cast") {
+        return Ok(cast_text(&AsciicastRecorder::load(path)?));
+    }
+    
+    let script = ScriptLoader::load_from_file(path)?;
+    // Screenshots the script takes are thrown away with the directory
+    let output_dir = tempfile::TempDir::new()?;
+    let result = Kla::new()
+        .settings(script.settings.clone())
+        .format(OutputFormat::Png)
+        .output_dir(output_dir.path())
+        .execute_script(&script)
+        .await
+        .with_context(|| format!("Failed to run {}", path.display()))?;
+    Ok(screen_text(&result.output, script.settings.width, script.settings.height))
+}
+
 pub async fn convert_command(input: PathBuf, output: PathBuf) -> Result<()> {
     println!("🔄 Converting {} to {}", input.display(), output.display());
     
+    // Registered generators take the recording's raw output
+    let extension = output.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    if let Some(generator) = registry::generator_for(extension) {
+        let content = if input.extension().is_some_and(|ext| ext == "cast") {
+            AsciicastRecorder::load(&input)?.events().iter().map(|event| event.data.as_str()).collect()
+        } else {
+            std::fs::read_to_string(&input).with_context(|| format!("Failed to read {}", input.display()))?
+        };
+        generator.create_output(&content, &output)?;
+        println!("✅ Conversion complete!");
+        return Ok(());
+    }
+    
     // TODO: Implement format conversion logic
     // This would handle converting between different recording formats
     
     println!("✅ Conversion complete!");
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn extract_command(input: PathBuf, frame: usize, output: PathBuf) -> Result<()> {
+    println!("🎞️ Extracting frame {} of {}", frame, input.display());
+    
+    let file = std::fs::File::open(&input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let decoder = GifDecoder::new(std::io::BufReader::new(file))
+        .with_context(|| format!("{} is not a GIF", input.display()))?;
+    // Frames are composited, so each is the full picture shown at that point
+    let frames = decoder.into_frames().collect_frames()
+        .with_context(|| format!("Failed to decode {}", input.display()))?;
+    let Some(image) = frames.get(frame) else {
+        anyhow::bail!(
+            "{} has {} frames; frame {} is out of range (frames count from 0)",
+            input.display(),
+            frames.len(),
+            frame
+        );
+    };
+    
+    DynamicImage::ImageRgba8(image.buffer().clone())
+        .to_rgb8()
+        .save_with_format(&output, ImageFormat::Png)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    
+    println!("✅ Frame saved: {}", output.display());
+    Ok(())
+}
+
+/// Representative frame content for `kla bench`: a colored listing and some code
+const BENCH_CONTENT: &str = "$ ls --color\r
+\x1b[1;34msrc\x1b[0m  \x1b[1;34mtarget\x1b[0m  Cargo.toml  \x1b[1;32mbuild.sh\x1b[0m  README.md\r
+$ cat src/main.rs\r
+use std::collections::HashMap;\r
+\r
+/// Count how often each word appears\r
+fn count_words(text: &str) -> HashMap<&str, usize> {\r
+    let mut counts = HashMap::new();\r
+    for word in text.split_whitespace() {\r
+        *counts.entry(word).or_insert(0) += 1;\r
+    }\r
+    counts\r
+}\r
+$ cargo test\r
+\x1b[32mtest result: ok.\x1b[0m 12 passed; \x1b[31m0 failed\x1b[0m\r
+";
+
+/// How long rendering a number of frames took
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub frames: usize,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn frames_per_second(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+    
+    pub fn per_frame(&self) -> Duration {
+        self.elapsed / self.frames.max(1) as u32
+    }
+}
+
+impl std::fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1} frames/sec, {:.2}ms per frame ({} frames in {:.2}s)",
+            self.frames_per_second(),
+            self.per_frame().as_secs_f64() * 1000.0,
+            self.frames,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// Render `frames` frames of `BENCH_CONTENT` on an 80x24 terminal
+///
+/// Each frame ends with its own number, so no two are identical.
+fn bench_render(frames: usize, config: &MediaConfig, theme: &ThemeConfig) -> Result<BenchResult> {
+    let screenshot_gen = ScreenshotGenerator::new(config, theme);
+    let started = std::time::Instant::now();
+    for frame in 0..frames {
+        let content = format!("{}$ echo {}\r\n{}", BENCH_CONTENT, frame, frame);
+        screenshot_gen.render(&content, 80, 24)?;
+    }
+    Ok(BenchResult { frames, elapsed: started.elapsed() })
+}
+
+pub fn bench_command(frames: usize, theme: String, scale: f32, highlight: Option<String>) -> Result<()> {
+    if frames == 0 {
+        anyhow::bail!("--frames must be at least 1");
+    }
+    if scale.is_nan() || scale <= 0.0 {
+        anyhow::bail!("--scale must be greater than 0");
+    }
+    
+    let theme_config = ThemeConfig::resolve(&theme)?;
+    let mut config = MediaConfig::builder().scale(scale);
+    if let Some(language) = &highlight {
+        config = config.syntax_highlight(language.as_str());
+    }
+    let config = config.build();
+    
+    println!(
+        "⏱️ Rendering {} frames (theme {}, scale {}, highlight {})",
+        frames,
+        theme,
+        scale,
+        highlight.as_deref().unwrap_or("off")
+    );
+    let result = bench_render(frames, &config, &theme_config)?;
+    println!("✅ {}", result);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::gif::GifRecorder;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_init_writes_loadable_script() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("example.kla.yaml");
+        
+        init_command(path.clone(), false).unwrap();
+        let script = ScriptLoader::load_from_file(&path).unwrap();
+        
+        assert_eq!(script.name, "My First Recording");
+        assert_eq!(script.settings.theme, "dracula");
+        assert_eq!(script.steps.len(), 5);
+        assert!(matches!(&script.steps[2].step_type, StepType::Input { text } if text == "\n"));
+        
+        // Refuses to clobber without --force
+        assert!(init_command(path.clone(), false).is_err());
+        init_command(path, true).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_dry_run_lists_steps_without_writing() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_path = temp_dir.path().join("demo.kla.yaml");
+        std::fs::write(&script_path, r#"
+name: "Dry Run"
+settings: {}
+steps:
+  - type: command
+    text: "ls"
+    wait: "1s"
+  - type: type
+    text: "echo hi"
+    speed: "20ms"
+  - type: screenshot
+    name: "listing"
+  - type: record_gif
+    duration: "3s"
+    name: "session"
+"#).unwrap();
+        let output_dir = temp_dir.path().join("out");
+        
+        let script = ScriptLoader::load_from_file(&script_path).unwrap();
+        let plan = describe_plan(&script, &output_dir, &[OutputFormat::Asciicast], &MediaConfig::default());
+        assert_eq!(plan, vec![
+            "1. command \"ls\", then wait 1s".to_string(),
+            "2. type \"echo hi\", 20ms per key".to_string(),
+            format!("3. screenshot 'listing' -> {}", output_dir.join("listing.png").display()),
+            format!("4. record_gif 'session' for 3s at 10fps -> {}", output_dir.join("session.gif").display()),
+            format!("session -> {}", output_dir.join("dry-run.cast").display()),
+        ]);
+        
+        record_command(script_path, output_dir.clone(), vec!["cast".to_string()], None, true, false, None, None).await.unwrap();
+        assert!(!output_dir.exists());
+    }
+    
+    #[tokio::test]
+    async fn test_size_flag_overrides_script_size() {
+        assert_eq!(crate::cli::parse_size("100x40"), Ok((100, 40)));
+        assert!(crate::cli::parse_size("0x40").is_err());
+        assert!(crate::cli::parse_size("70000x40").is_err());
+        assert!(crate::cli::parse_size("100").is_err());
+        
+        let temp_dir = TempDir::new().unwrap();
+        let script_path = temp_dir.path().join("sized.kla.yaml");
+        std::fs::write(&script_path, r#"
+name: "Sized"
+settings:
+  width: 80
+  height: 24
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: command
+    text: "echo sized"
+  - type: screenshot
+    name: "sized"
+"#).unwrap();
+        let output_dir = temp_dir.path().join("out");
+        record_command(script_path, output_dir.clone(), vec!["png".to_string()], None, false, false, None, Some((100, 40)))
+            .await
+            .unwrap();
+        
+        let expected = Kla::new().size(100, 40).render_text("", temp_dir.path().join("expected.png")).unwrap();
+        assert_eq!(
+            image::image_dimensions(output_dir.join("sized.png")).unwrap(),
+            image::image_dimensions(expected).unwrap()
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_demo_keys_screenshot_and_quit() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_path = temp_dir.path().join("keys.kla.yaml");
+        std::fs::write(&script_path, r#"
+name: "Keys"
+settings:
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: command
+    text: "echo one"
+  - type: command
+    text: "echo two"
+  - type: command
+    text: "echo three"
+"#).unwrap();
+        let script = ScriptLoader::load_from_file(&script_path).unwrap();
+        
+        let mut pressed = std::collections::VecDeque::from([
+            DemoKey::Next,
+            DemoKey::Back,
+            DemoKey::Screenshot,
+            DemoKey::Quit,
+        ]);
+        let mut keys = || pressed.pop_front().context("asked for a key after quitting");
+        run_demo(&script, Some(&mut keys), temp_dir.path()).await.unwrap();
+        
+        assert!(temp_dir.path().join("demo-1.png").exists());
+        assert!(pressed.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_demo_runs_repeated_steps() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("log.txt");
+        let script = ScriptLoader::load_from_string(&format!(r#"
+name: "Repeat"
+settings:
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+  prompt_pattern: "\\$ $"
+steps:
+  - type: repeat
+    count: 3
+    steps:
+      - type: command
+        text: "echo tick >> '{0}'"
+      - type: command
+        text: "echo tock >> '{0}'"
+        when: "never shown"
+"#, log.display())).unwrap();
+        
+        run_demo(&script, None, temp_dir.path()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "tick\ntick\ntick\n");
+    }
+    
+    #[test]
+    fn test_bench_reports_positive_throughput() {
+        let config = MediaConfig::builder().syntax_highlight("rust").build();
+        let result = bench_render(3, &config, &ThemeConfig::default_theme()).unwrap();
+        assert_eq!(result.frames, 3);
+        
+        let report = result.to_string();
+        let fps: f64 = report.split_whitespace().next().unwrap().parse().unwrap();
+        assert!(fps > 0.0, "{}", report);
+        assert!(report.contains("ms per frame"), "{}", report);
+        
+        bench_command(2, "dracula".to_string(), 2.0, None).unwrap();
+        assert!(bench_command(0, "default".to_string(), 1.0, None).is_err());
+    }
+    
+    #[test]
+    fn test_extract_writes_requested_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let gif_path = temp_dir.path().join("three.gif");
+        let mut recorder = GifRecorder::new(&MediaConfig::default(), &ThemeConfig::default_theme(), 20, 2);
+        for text in ["one", "two", "three"] {
+            recorder.capture_frame(text).unwrap();
+        }
+        recorder.save_gif(&gif_path).unwrap();
+        
+        let first = temp_dir.path().join("first.png");
+        let second = temp_dir.path().join("second.png");
+        extract_command(gif_path.clone(), 0, first.clone()).unwrap();
+        extract_command(gif_path.clone(), 1, second.clone()).unwrap();
+        
+        let first = image::open(&first).unwrap().to_rgb8();
+        let second = image::open(&second).unwrap().to_rgb8();
+        assert_eq!(second.dimensions(), recorder.frames()[1].image.dimensions());
+        assert_ne!(first, second);
+        
+        let error = extract_command(gif_path, 3, temp_dir.path().join("missing.png")).unwrap_err();
+        assert!(error.to_string().contains("has 3 frames"), "{}", error);
+    }
+}
@@ -2,12 +2,13 @@ use clap::Subcommand;
 use std::path::PathBuf;
 
 pub mod commands;
+pub mod watch;
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Record a terminal session from a script
     Record {
-        /// Script file to execute (.kla.yaml)
+        /// Script file to execute (.kla.yaml), or `-` to read YAML or JSON from stdin
         #[arg(value_name = "SCRIPT")]
         script: PathBuf,
         
@@ -15,9 +16,35 @@ pub enum Commands {
         #[arg(short, long, default_value = "./output")]
         output: PathBuf,
         
-        /// Output format (png, gif, mp4)
-        #[arg(short, long, default_value = "gif")]
-        format: String,
+        /// Output format (png, gif, mp4, cast, svg, webp, apng, html); a comma-separated
+        /// list produces each of them from one run
+        #[arg(short, long, default_value = "gif", value_delimiter = ',')]
+        format: Vec<String>,
+        
+        /// Theme name or .toml/.itermcolors file, overriding the script's theme
+        #[arg(short, long)]
+        theme: Option<String>,
+        
+        /// Print the steps and output paths without running anything
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Record again each time the script file is saved
+        #[arg(long, conflicts_with = "dry_run")]
+        watch: bool,
+        
+        /// Write `snapshot` steps' golden files instead of failing when they are missing or differ
+        #[arg(long)]
+        update_snapshots: bool,
+        
+        /// How screenshot and animation files are named, e.g. "{script}-{index}-{name}.{format}";
+        /// also accepts {date}
+        #[arg(long)]
+        name_template: Option<String>,
+        
+        /// Terminal size as columns x rows, e.g. 100x40, overriding the script's
+        #[arg(long, value_name = "WxH", value_parser = parse_size)]
+        size: Option<(u16, u16)>,
     },
     
     /// Take a screenshot of a single command
@@ -29,6 +56,18 @@ pub enum Commands {
         /// Output file name
         #[arg(short, long, default_value = "screenshot.png")]
         output: PathBuf,
+        
+        /// Theme name or .toml/.itermcolors file
+        #[arg(short, long, default_value = "default")]
+        theme: String,
+        
+        /// Size the image to the command's output instead of the whole terminal
+        #[arg(long)]
+        autofit: bool,
+        
+        /// Terminal size as columns x rows, e.g. 100x40
+        #[arg(long, value_name = "WxH", value_parser = parse_size)]
+        size: Option<(u16, u16)>,
     },
     
     /// Run interactive demo mode
@@ -42,6 +81,60 @@ pub enum Commands {
         interactive: bool,
     },
     
+    /// Check a script for mistakes without running it
+    Validate {
+        /// Script file to check
+        #[arg(value_name = "SCRIPT")]
+        script: PathBuf,
+    },
+    
+    /// List the builtin themes
+    ListThemes,
+    
+    /// Create a starter script to edit
+    Init {
+        /// Where to write the script
+        #[arg(value_name = "PATH", default_value = "example.kla.yaml")]
+        path: PathBuf,
+        
+        /// Overwrite the file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Render an asciinema cast file into a GIF
+    Play {
+        /// Cast file to replay (.cast)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+        
+        /// Output file
+        #[arg(value_name = "OUTPUT", default_value = "output.gif")]
+        output: PathBuf,
+        
+        /// Theme name or .toml/.itermcolors file
+        #[arg(short, long, default_value = "default")]
+        theme: String,
+    },
+    
+    /// Print the JSON Schema for .kla.yaml scripts
+    Schema {
+        /// Write the schema to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Compare the final text of two recordings or scripts, failing if they differ
+    Diff {
+        /// Cast file (.cast) or script (.kla.yaml) to compare against
+        #[arg(value_name = "A")]
+        a: PathBuf,
+        
+        /// Cast file (.cast) or script (.kla.yaml) to compare
+        #[arg(value_name = "B")]
+        b: PathBuf,
+    },
+    
     /// Convert between recording formats
     Convert {
         /// Input file
@@ -52,21 +145,110 @@ pub enum Commands {
         #[arg(value_name = "OUTPUT")]
         output: PathBuf,
     },
+    
+    /// Save one frame of a GIF as a PNG
+    Extract {
+        /// GIF to take the frame from
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+        
+        /// Index of the frame, counting from 0
+        #[arg(short, long, default_value_t = 0)]
+        frame: usize,
+        
+        /// PNG file to write
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+    },
+    
+    /// Measure how fast frames render with the given settings
+    Bench {
+        /// Number of frames to render
+        #[arg(short, long, default_value_t = 100)]
+        frames: usize,
+        
+        /// Theme name or .toml/.itermcolors file
+        #[arg(short, long, default_value = "default")]
+        theme: String,
+        
+        /// Output pixels per logical pixel, e.g. 2 for retina-quality images
+        #[arg(long, default_value_t = 1.0)]
+        scale: f32,
+        
+        /// Language to syntax highlight the rendered text as, e.g. rust
+        #[arg(long)]
+        highlight: Option<String>,
+    },
 }
 
 pub async fn execute_command(command: Commands) -> anyhow::Result<()> {
     match command {
-        Commands::Record { script, output, format } => {
-            commands::record_command(script, output, format).await
+        Commands::Record { script, output, format, theme, dry_run, watch, update_snapshots, name_template, size } => {
+            if watch {
+                if script.as_os_str() == "-" {
+                    anyhow::bail!("--watch needs a script file, not stdin");
+                }
+                return watch::watch_script(&script.clone(), None, || {
+                    commands::record_command(
+                        script.clone(),
+                        output.clone(),
+                        format.clone(),
+                        theme.clone(),
+                        false,
+                        update_snapshots,
+                        name_template.clone(),
+                        size,
+                    )
+                })
+                .await;
+            }
+            commands::record_command(script, output, format, theme, dry_run, update_snapshots, name_template, size).await
         }
-        Commands::Screenshot { command, output } => {
-            commands::screenshot_command(command, output).await
+        Commands::Screenshot { command, output, theme, autofit, size } => {
+            commands::screenshot_command(command, output, theme, autofit, size).await
         }
         Commands::Demo { script, interactive } => {
             commands::demo_command(script, interactive).await
         }
+        Commands::Validate { script } => {
+            commands::validate_command(script)
+        }
+        Commands::ListThemes => {
+            commands::list_themes_command()
+        }
+        Commands::Init { path, force } => {
+            commands::init_command(path, force)
+        }
+        Commands::Play { input, output, theme } => {
+            commands::play_command(input, output, theme)
+        }
+        Commands::Schema { output } => {
+            commands::schema_command(output)
+        }
+        Commands::Diff { a, b } => {
+            commands::diff_command(a, b).await
+        }
         Commands::Convert { input, output } => {
             commands::convert_command(input, output).await
         }
+        Commands::Extract { input, frame, output } => {
+            commands::extract_command(input, frame, output)
+        }
+        Commands::Bench { frames, theme, scale, highlight } => {
+            commands::bench_command(frames, theme, scale, highlight)
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Parse a terminal size written as `WxH`, e.g. `100x40`
+fn parse_size(value: &str) -> Result<(u16, u16), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("'{}' is not a size; expected columns x rows, e.g. 100x40", value))?;
+    let dimension = |part: &str, name: &str| match part.trim().parse::<u16>() {
+        Ok(0) => Err(format!("{} in '{}' must be at least 1", name, value)),
+        Ok(size) => Ok(size),
+        Err(_) => Err(format!("{} in '{}' must be a number from 1 to {}", name, value, u16::MAX)),
+    };
+    Ok((dimension(width, "Width")?, dimension(height, "Height")?))
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::ffi::OsStr;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long saves must stop arriving before the script is run again
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Call `run` now and again after every change to `script_path`
+///
+/// The script's directory is watched rather than the file, so editors that
+/// save by replacing the file are still noticed. A failed run is reported
+/// and watching continues. Stops after `max_runs` runs, if given.
+pub async fn watch_script<F, Fut>(script_path: &Path, max_runs: Option<usize>, mut run: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let file_name = script_path
+        .file_name()
+        .with_context(|| format!("Not a script file: {}", script_path.display()))?
+        .to_os_string();
+    let directory = match script_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            // Sending only fails once watching has stopped
+            let _ = tx.send(event);
+        }
+    })
+    .context("Failed to start the file watcher")?;
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", directory.display()))?;
+    
+    let mut runs = 0;
+    loop {
+        runs += 1;
+        println!("🔁 [{}] Run {}: {}", timestamp(), runs, script_path.display());
+        if let Err(err) = run().await {
+            eprintln!("❌ {:#}", err);
+        }
+        if max_runs.is_some_and(|max| runs >= max) {
+            return Ok(());
+        }
+        
+        println!("👀 Watching {} for changes (Ctrl-C to stop)", script_path.display());
+        loop {
+            let event = rx.recv().await.context("File watcher stopped")?;
+            if changes_file(&event, &file_name) {
+                break;
+            }
+        }
+        // Editors often write several times per save; wait for the burst to end
+        while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+    }
+}
+
+/// Whether `event` writes, creates or renames onto the file called `file_name`
+fn changes_file(event: &Event, file_name: &OsStr) -> bool {
+    let writes = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    );
+    writes && event.paths.iter().any(|path| path.file_name() == Some(file_name))
+}
+
+/// Current wall-clock time of day, in UTC
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+        % 86_400;
+    format!("{:02}:{:02}:{:02} UTC", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    
+    #[tokio::test]
+    async fn test_saving_script_triggers_another_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let script_path = temp_dir.path().join("demo.kla.yaml");
+        std::fs::write(&script_path, "name: first\n").unwrap();
+        
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let watching = tokio::spawn({
+            let script_path = script_path.clone();
+            async move {
+                watch_script(&script_path, Some(2), || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { Ok(()) }
+                })
+                .await
+            }
+        });
+        
+        // Save the way many editors do: write a temporary file and rename it over the script
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        let replacement = temp_dir.path().join(".demo.kla.yaml.swp");
+        std::fs::write(&replacement, "name: second\n").unwrap();
+        std::fs::rename(&replacement, &script_path).unwrap();
+        
+        tokio::time::timeout(Duration::from_secs(5), watching).await.unwrap().unwrap().unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
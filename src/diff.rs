@@ -0,0 +1,118 @@
+use similar::{DiffOp, TextDiff};
+use std::fmt;
+
+use crate::media::AsciicastRecorder;
+use crate::pty::capture::TerminalCapture;
+
+/// Rows of history kept when turning a recording into text
+const SCROLLBACK_LINES: usize = 10_000;
+
+/// A line that differs between two screens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineChange {
+    /// Only in the second screen, at one-based `line` of it
+    Added { line: usize, text: String },
+    /// Only in the first screen, at one-based `line` of it
+    Removed { line: usize, text: String },
+    /// Replaced at one-based `line` of the second screen
+    Changed { line: usize, before: String, after: String },
+}
+
+impl fmt::Display for LineChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineChange::Added { line, text } => write!(f, "+ {:>4} | {}", line, text),
+            LineChange::Removed { line, text } => write!(f, "- {:>4} | {}", line, text),
+            LineChange::Changed { line, before, after } => {
+                write!(f, "~ {:>4} | {}\n       | {}", line, before, after)
+            }
+        }
+    }
+}
+
+/// Line-by-line differences between the text of two screens
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScreenDiff {
+    pub changes: Vec<LineChange>,
+}
+
+impl ScreenDiff {
+    /// Compare two screens' text; a removed line followed by an added one is a change
+    pub fn between(before: &str, after: &str) -> Self {
+        let diff = TextDiff::from_lines(before, after);
+        let old: Vec<&str> = before.lines().collect();
+        let new: Vec<&str> = after.lines().collect();
+        let mut changes = Vec::new();
+
+        let removed = |changes: &mut Vec<LineChange>, range: std::ops::Range<usize>| {
+            changes.extend(range.map(|index| LineChange::Removed { line: index + 1, text: old[index].to_string() }));
+        };
+        let added = |changes: &mut Vec<LineChange>, range: std::ops::Range<usize>| {
+            changes.extend(range.map(|index| LineChange::Added { line: index + 1, text: new[index].to_string() }));
+        };
+
+        for op in diff.ops() {
+            match *op {
+                DiffOp::Equal { .. } => {}
+                DiffOp::Delete { old_index, old_len, .. } => removed(&mut changes, old_index..old_index + old_len),
+                DiffOp::Insert { new_index, new_len, .. } => added(&mut changes, new_index..new_index + new_len),
+                DiffOp::Replace { old_index, old_len, new_index, new_len } => {
+                    let paired = old_len.min(new_len);
+                    changes.extend((0..paired).map(|offset| LineChange::Changed {
+                        line: new_index + offset + 1,
+                        before: old[old_index + offset].to_string(),
+                        after: new[new_index + offset].to_string(),
+                    }));
+                    removed(&mut changes, old_index + paired..old_index + old_len);
+                    added(&mut changes, new_index + paired..new_index + new_len);
+                }
+            }
+        }
+
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for ScreenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Text of everything raw terminal output left on screen, scrollback included
+pub fn screen_text(output: &str, width: u16, height: u16) -> String {
+    let mut capture = TerminalCapture::with_scrollback(width, height, SCROLLBACK_LINES);
+    capture.process_output(output).ok();
+    capture.get_scrollback_lines().join("\n")
+}
+
+/// Text a cast leaves on screen once every event has played
+pub fn cast_text(cast: &AsciicastRecorder) -> String {
+    let output: String = cast.events().iter().map(|event| event.data.as_str()).collect();
+    screen_text(&output, cast.header().width, cast.header().height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replaced_lines_pair_up_as_changes() {
+        let diff = ScreenDiff::between("$ ls\na.txt\nb.txt\n$", "$ ls\na.txt\nc.txt\nd.txt\n$");
+        assert_eq!(
+            diff.changes,
+            vec![
+                LineChange::Changed { line: 3, before: "b.txt".to_string(), after: "c.txt".to_string() },
+                LineChange::Added { line: 4, text: "d.txt".to_string() },
+            ]
+        );
+        assert!(ScreenDiff::between("same\ntext", "same\ntext").is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 /// KLA error types
@@ -7,7 +8,7 @@ pub enum KlaError {
     Terminal(String),
 
     #[error("PTY error: {0}")]
-    Pty(#[from] portable_pty::Error),
+    Pty(#[source] anyhow::Error),
 
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
@@ -18,6 +19,37 @@ pub enum KlaError {
     #[error("Timeout waiting for: {0}")]
     Timeout(String),
 
+    /// A script step ran past the configured `step_timeout`
+    #[error("Step {step} ({summary}) timed out after {limit:?}")]
+    StepTimeout {
+        /// One-based position of the step in the script
+        step: usize,
+        summary: String,
+        limit: Duration,
+    },
+
+    /// A command with `retries` was still failing after its last attempt
+    #[error("Command `{command}` failed after {attempts} attempt(s)")]
+    CommandFailed {
+        command: String,
+        attempts: u32,
+    },
+
+    /// The screen no longer matches a stored snapshot
+    #[error("Snapshot '{name}' does not match {path}:\n{diff}")]
+    SnapshotMismatch {
+        name: String,
+        path: std::path::PathBuf,
+        diff: String,
+    },
+
+    /// A `snapshot` step has no golden file yet
+    #[error("Snapshot '{name}' has no golden file at {path}; record with --update-snapshots to write it")]
+    SnapshotMissing {
+        name: String,
+        path: std::path::PathBuf,
+    },
+
     #[error("Session closed")]
     SessionClosed,
 
@@ -32,6 +64,13 @@ pub enum KlaError {
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    /// Any other failure, with its context chain intact
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
 }
 
 /// Result type alias for KLA operations
@@ -57,4 +96,9 @@ impl KlaError {
     pub fn parse<S: Into<String>>(msg: S) -> Self {
         Self::Parse(msg.into())
     }
+
+    /// An IO error of the same kind, with `context` prefixed to its message
+    pub(crate) fn io_context(err: std::io::Error, context: impl std::fmt::Display) -> Self {
+        Self::Io(std::io::Error::new(err.kind(), format!("{}: {}", context, err)))
+    }
 }
\ No newline at end of file
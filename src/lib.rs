@@ -7,17 +7,53 @@ pub mod cli;
 pub mod script;
 pub mod pty;
 pub mod media;
+pub mod terminal;
+pub mod error;
+pub mod diff;
 
 // Re-export main types for convenience
-pub use script::{Script, ScriptStep, StepType, TerminalSettings, ScriptLoader};
-pub use pty::{Terminal, TerminalController};
-pub use media::{MediaRecorder, OutputFormat, MediaConfig, ThemeConfig};
+pub use script::{Layout, Script, ScriptStep, StepType, TerminalSettings, ScriptLoader};
+pub use pty::{CommandRun, Terminal, TerminalController};
+pub use media::{register_generator, MediaGenerator, MediaRecorder, OutputFormat, MediaConfig, ThemeConfig};
+pub use error::KlaError;
+
+/// How long a command with a screenshot may run when no step timeout is set
+const COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Pause before a command's first retry; it doubles for each retry after that
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Hooks invoked around each step while a script executes
+pub trait StepObserver {
+    /// Called before step `index` runs
+    fn on_step_start(&mut self, _index: usize, _step: &ScriptStep) {}
+    
+    /// Called after step `index` has finished successfully
+    fn on_step_complete(&mut self, _index: usize, _step: &ScriptStep) {}
+    
+    /// Called instead of `on_step_complete` when the step's `when` did not match
+    fn on_step_skipped(&mut self, _index: usize, _step: &ScriptStep) {}
+}
+
+/// The unit observer ignores all step events
+impl StepObserver for () {}
 
 /// Main KLA interface for programmatic usage
 pub struct Kla {
     settings: TerminalSettings,
-    output_format: OutputFormat,
+    /// Every format to produce, the primary one first
+    output_formats: Vec<OutputFormat>,
     theme: String,
+    media_config: MediaConfig,
+    output_dir: std::path::PathBuf,
+    /// Advance a simulated clock instead of sleeping
+    deterministic: bool,
+    /// Where `snapshot` steps keep their golden files
+    snapshot_dir: std::path::PathBuf,
+    /// Write snapshots that are missing or differ instead of failing
+    update_snapshots: bool,
+    /// Most independent screenshot steps run at once
+    max_parallelism: usize,
 }
 
 impl Kla {
@@ -25,11 +61,23 @@ impl Kla {
     pub fn new() -> Self {
         Self {
             settings: TerminalSettings::default(),
-            output_format: OutputFormat::Gif,
+            output_formats: vec![OutputFormat::Gif],
             theme: "default".to_string(),
+            media_config: MediaConfig::default(),
+            output_dir: std::path::PathBuf::from("./output"),
+            deterministic: false,
+            snapshot_dir: std::path::PathBuf::from("./snapshots"),
+            update_snapshots: false,
+            max_parallelism: 1,
         }
     }
     
+    /// Use the given terminal settings, e.g. those of a loaded script
+    pub fn settings(mut self, settings: TerminalSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+    
     /// Set terminal dimensions
     pub fn size(mut self, width: u16, height: u16) -> Self {
         self.settings.width = width;
@@ -49,59 +97,601 @@ impl Kla {
         self
     }
     
+    /// Set fonts, padding and backdrop used for screenshots and recordings
+    pub fn media_config(mut self, config: MediaConfig) -> Self {
+        self.media_config = config;
+        self
+    }
+    
+    /// Set the font family used for rendering
+    pub fn font_family<S: Into<String>>(mut self, family: S) -> Self {
+        self.media_config.font_family = family.into();
+        self
+    }
+    
+    /// Set the font size in pixels
+    pub fn font_size(mut self, size: u16) -> Self {
+        self.media_config.font_size = size;
+        self
+    }
+    
+    /// Set the same padding around the terminal on every side
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.media_config.set_padding(padding);
+        self
+    }
+    
+    /// Abort the run if any single step takes longer than `timeout`
+    pub fn step_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.settings.step_timeout = Some(timeout);
+        self
+    }
+    
     /// Set output format
     pub fn format(mut self, format: OutputFormat) -> Self {
-        self.output_format = format;
+        self.output_formats = vec![format];
+        self
+    }
+    
+    /// Produce every one of `formats` from a single run; the first is the primary format
+    pub fn formats(mut self, formats: Vec<OutputFormat>) -> Self {
+        if !formats.is_empty() {
+            self.output_formats = formats;
+        }
+        self
+    }
+    
+    /// Set the directory artifacts are written to
+    pub fn output_dir<P: Into<std::path::PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+    
+    /// Record the same frames every time a script runs
+    ///
+    /// Waits and typing advance a simulated clock that times captions and
+    /// GIF frames, instead of sleeping. Every step and frame waits for the
+    /// terminal's output to settle first, so frames don't depend on how fast
+    /// the shell happened to respond. Output that arrives after more than a
+    /// short quiet gap may be missed, since waits take no real time.
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.deterministic = enabled;
+        self
+    }
+    
+    /// Set the directory `snapshot` steps read and write golden files in
+    pub fn snapshot_dir<P: Into<std::path::PathBuf>>(mut self, snapshot_dir: P) -> Self {
+        self.snapshot_dir = snapshot_dir.into();
+        self
+    }
+    
+    /// Write snapshots that are missing or no longer match instead of failing the run
+    pub fn update_snapshots(mut self, enabled: bool) -> Self {
+        self.update_snapshots = enabled;
+        self
+    }
+    
+    /// Run up to `n` steps at once when a script is a batch of independent screenshots
+    ///
+    /// A batch is a script without a layout whose steps are all commands with
+    /// a `screenshot`, recorded without session-wide formats like asciicast.
+    /// Each step then gets its own terminal. Results keep the script's order.
+    pub fn max_parallelism(mut self, n: usize) -> Self {
+        self.max_parallelism = n.max(1);
         self
     }
     
     /// Execute a script and return the results
-    pub async fn execute_script(&self, script: &Script) -> anyhow::Result<ExecutionResult> {
-        let mut terminal = TerminalController::new(&self.settings)?;
-        let media_recorder = MediaRecorder::new(self.output_format.clone(), &std::path::PathBuf::from("./output"))?
-            .with_theme(&self.theme);
+    pub async fn execute_script(&self, script: &Script) -> error::Result<ExecutionResult> {
+        self.execute_script_with_observer(script, &mut ()).await
+    }
+    
+    /// Execute a script, reporting progress on each step to `observer`
+    pub async fn execute_script_with_observer(
+        &self,
+        script: &Script,
+        observer: &mut dyn StepObserver,
+    ) -> error::Result<ExecutionResult> {
+        if self.max_parallelism > 1 && self.is_screenshot_batch(script) {
+            return self.execute_batch(script, observer).await;
+        }
+        let pane_ids = script.pane_ids();
+        if pane_ids.is_empty() {
+            return Err(KlaError::parse("layout needs at least one pane"));
+        }
+        let mut panes = Vec::with_capacity(pane_ids.len());
+        for _ in &pane_ids {
+            panes.push(TerminalController::new(&self.settings).await?);
+        }
+        let mut media_recorder = self.media_recorder()?.with_script_name(&script.file_name());
+        if let Some(layout) = &script.layout {
+            let direction = media::PaneDirection::from_name(layout.direction.as_deref())?;
+            media_recorder = media_recorder.with_pane_layout(media::PaneLayout { direction, gap: layout.gap });
+        }
         
         let mut screenshots = Vec::new();
         let mut recordings = Vec::new();
+        let mut steps = Vec::with_capacity(script.steps.len());
+        let mut timeline = Vec::new();
         
-        for step in &script.steps {
-            match &step.step_type {
-                StepType::Command { text, wait } => {
-                    terminal.execute_command(text).await?;
-                    if let Some(duration) = wait {
-                        tokio::time::sleep(*duration).await;
-                    }
-                }
-                StepType::Type { text, speed } => {
-                    terminal.type_text(text, *speed).await?;
-                }
-                StepType::Screenshot { name } => {
-                    let path = std::path::PathBuf::from(format!("{}.png", name));
-                    media_recorder.take_screenshot(&terminal, &path).await?;
-                    screenshots.push(path);
-                }
-                StepType::RecordGif { duration: _, name } => {
-                    let path = std::path::PathBuf::from(format!("{}.gif", name));
-                    recordings.push(path);
+        for (index, step) in script.steps.iter().enumerate() {
+            observer.on_step_start(index, step);
+            let target = script.pane_index(step).ok_or_else(|| {
+                KlaError::parse(format!(
+                    "step {} runs in pane '{}', which the layout does not declare",
+                    index + 1,
+                    step.pane.as_deref().unwrap_or_default()
+                ))
+            })?;
+            self.settle(&panes).await;
+            if let Some(pattern) = &step.when {
+                if !screen_matches(&panes[target], pattern)? {
+                    steps.push(StepResult {
+                        index,
+                        step_type: step.step_type.summary(),
+                        duration: std::time::Duration::ZERO,
+                        outcome: StepOutcome::Skipped,
+                        artifacts: Vec::new(),
+                        attempts: 0,
+                    });
+                    observer.on_step_skipped(index, step);
+                    continue;
                 }
             }
+            media_recorder.set_step_index(index + 1);
+            if let StepType::Command { text, .. } = &step.step_type {
+                media_recorder.add_command(text);
+            }
+            let started = std::time::Instant::now();
+            let timeline_start = (media_recorder.elapsed(), panes[target].mark());
+            
+            let StepRun { artifacts, screenshots: taken, recordings: recorded, attempts } = self
+                .run_step_within_timeout(index, &step.step_type, &mut panes, target, &mut media_recorder)
+                .await?;
+            
+            if let (true, StepType::Command { text, .. }) = (self.media_config.emit_timeline, &step.step_type) {
+                // Deterministic waits take no real time, so let the output land first
+                panes[target].wait_for_settled().await;
+                let (start, mark) = timeline_start;
+                let output = panes[target].read_since(mark);
+                timeline.push(media::timeline::TimelineEntry::new(text, start, media_recorder.elapsed(), &output));
+            }
+            
+            screenshots.extend(taken);
+            recordings.extend(recorded);
+            
+            steps.push(StepResult {
+                index,
+                step_type: step.step_type.summary(),
+                duration: started.elapsed(),
+                outcome: StepOutcome::Ok,
+                artifacts,
+                attempts,
+            });
+            observer.on_step_complete(index, step);
+        }
+        
+        // Whole-session artifacts come from the first pane
+        let terminal = &panes[0];
+        if self.output_formats.contains(&OutputFormat::Asciicast) {
+            let path = self.output_dir.join(format!("{}.{}", script.file_name(), OutputFormat::Asciicast.extension()));
+            media_recorder.save_asciicast(terminal, &path)?;
+            recordings.push(path);
+        }
+        if self.media_config.emit_timeline {
+            let path = self.output_dir.join(format!("{}.timeline.json", script.file_name()));
+            media::timeline::save_timeline(&timeline, &path)?;
+            recordings.push(path);
+        }
+        for format in &self.output_formats {
+            let OutputFormat::Custom(extension) = format else {
+                continue;
+            };
+            let generator = media::registry::generator_for(extension).ok_or_else(|| {
+                KlaError::invalid_state(format!("no generator is registered for .{}", extension))
+            })?;
+            let path = self.output_dir.join(format!("{}.{}", script.file_name(), extension));
+            generator
+                .create_output(&terminal.get_output(), &path)
+                .map_err(|err| KlaError::Other(err.context(format!("Failed to write {}", path.display()))))?;
+            recordings.push(path);
         }
         
+        let output = terminal.get_output();
+        let pane_outputs = pane_ids
+            .iter()
+            .zip(&panes)
+            .filter_map(|(id, pane)| Some((id.map(str::to_string)?, pane.get_output())))
+            .collect();
+        for pane in &mut panes {
+            pane.close().await;
+        }
         Ok(ExecutionResult {
-            output: terminal.get_output(),
+            output,
             screenshots,
             recordings,
+            steps,
+            pane_outputs,
+        })
+    }
+    
+    /// Whether every step is a command screenshot that can run in a terminal of its own
+    fn is_screenshot_batch(&self, script: &Script) -> bool {
+        let session_formats = self.media_config.emit_timeline
+            || self.output_formats.iter().any(|format| matches!(format, OutputFormat::Asciicast | OutputFormat::Custom(_)));
+        script.layout.is_none()
+            && !session_formats
+            && !script.steps.is_empty()
+            && script.steps.iter().all(|step| {
+                step.when.is_none()
+                    && matches!(step.step_type, StepType::Command { screenshot: Some(_), stdin: None, .. })
+            })
+    }
+    
+    /// Run a batch of independent screenshot steps, `max_parallelism` at a time
+    ///
+    /// `output` holds each step's terminal output in script order.
+    async fn execute_batch(&self, script: &Script, observer: &mut dyn StepObserver) -> error::Result<ExecutionResult> {
+        use futures::stream::{self, StreamExt, TryStreamExt};
+        
+        // Steps only borrow the observer between awaits
+        let observer = std::cell::RefCell::new(observer);
+        let runs: Vec<(StepResult, String)> = stream::iter(script.steps.iter().enumerate())
+            .map(|(index, step)| {
+                let observer = &observer;
+                async move {
+                    observer.borrow_mut().on_step_start(index, step);
+                    let mut terminal = [TerminalController::new(&self.settings).await?];
+                    let mut media_recorder = self.media_recorder()?.with_script_name(&script.file_name());
+                    media_recorder.set_step_index(index + 1);
+                    if let StepType::Command { text, .. } = &step.step_type {
+                        media_recorder.add_command(text);
+                    }
+                    
+                    let started = std::time::Instant::now();
+                    let StepRun { artifacts, attempts, .. } = self
+                        .run_step_within_timeout(index, &step.step_type, &mut terminal, 0, &mut media_recorder)
+                        .await?;
+                    observer.borrow_mut().on_step_complete(index, step);
+                    let result = StepResult {
+                        index,
+                        step_type: step.step_type.summary(),
+                        duration: started.elapsed(),
+                        outcome: StepOutcome::Ok,
+                        artifacts,
+                        attempts,
+                    };
+                    let [mut terminal] = terminal;
+                    let output = terminal.get_output();
+                    terminal.close().await;
+                    Ok::<_, KlaError>((result, output))
+                }
+            })
+            .buffered(self.max_parallelism)
+            .try_collect()
+            .await?;
+        
+        let (steps, outputs): (Vec<StepResult>, Vec<String>) = runs.into_iter().unzip();
+        Ok(ExecutionResult {
+            output: outputs.concat(),
+            screenshots: steps.iter().flat_map(|step| step.artifacts.iter().cloned()).collect(),
+            recordings: Vec::new(),
+            steps,
+            pane_outputs: std::collections::HashMap::new(),
         })
     }
     
+    /// Run step `index` with `run_step`, failing it if it outlasts the step timeout
+    ///
+    /// A repeat step has no limit of its own; each step inside it gets one.
+    async fn run_step_within_timeout(
+        &self,
+        index: usize,
+        step_type: &StepType,
+        panes: &mut [TerminalController],
+        target: usize,
+        media_recorder: &mut MediaRecorder,
+    ) -> error::Result<StepRun> {
+        let run = self.run_step(index, step_type, panes, target, media_recorder);
+        match self.settings.step_timeout {
+            Some(limit) if !matches!(step_type, StepType::Repeat { .. }) => {
+                tokio::time::timeout(limit, run).await.map_err(|_| KlaError::StepTimeout {
+                    step: index + 1,
+                    summary: step_type.summary(),
+                    limit,
+                })?
+            }
+            _ => run.await,
+        }
+    }
+    
+    /// Wait for every pane's output to settle before a step, if deterministic
+    async fn settle(&self, panes: &[TerminalController]) {
+        if self.deterministic {
+            for pane in panes {
+                pane.wait_for_settled().await;
+            }
+        }
+    }
+    
+    /// Run step `index` in the pane at `target`, returning the files it wrote
+    ///
+    /// Screenshots and animations show every pane.
+    async fn run_step(
+        &self,
+        index: usize,
+        step_type: &StepType,
+        panes: &mut [TerminalController],
+        target: usize,
+        media_recorder: &mut MediaRecorder,
+    ) -> error::Result<StepRun> {
+        let mut artifacts = Vec::new();
+        let mut screenshots = Vec::new();
+        let mut recordings = Vec::new();
+        let mut attempts = 1;
+        let terminal = &mut panes[target];
+        
+        match step_type {
+            StepType::Command { text, stdin, screenshot, wait, retries, retry_on } if *retries > 0 => {
+                if stdin.is_some() {
+                    return Err(KlaError::invalid_state("a command with retries cannot take stdin"));
+                }
+                let failure = retry_on
+                    .as_deref()
+                    .map(regex::Regex::new)
+                    .transpose()
+                    .map_err(|err| KlaError::parse(format!("Invalid retry_on: {}", err)))?;
+                let path = screenshot.as_ref().map(|name| media_recorder.output_path(name, "png"));
+                let timeout = self.settings.step_timeout.unwrap_or(COMMAND_TIMEOUT);
+                
+                let mut backoff = RETRY_BACKOFF;
+                loop {
+                    let run = match &path {
+                        Some(path) => media_recorder.capture_command(terminal, text, timeout, path).await?,
+                        None => terminal.run_command(text, timeout).await?,
+                    };
+                    if !command_failed(&run, failure.as_ref()) {
+                        break;
+                    }
+                    if attempts > *retries {
+                        return Err(KlaError::CommandFailed { command: text.clone(), attempts });
+                    }
+                    log::info!("Command `{}` failed on attempt {}; retrying in {:?}", text, attempts, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempts += 1;
+                }
+                screenshots.extend(path.clone());
+                artifacts.extend(path);
+                if let Some(duration) = wait {
+                    self.pause(*duration, media_recorder).await;
+                }
+            }
+            StepType::Command { text, stdin, screenshot: Some(name), wait, .. } => {
+                if stdin.is_some() {
+                    return Err(KlaError::invalid_state("a command with a screenshot cannot take stdin"));
+                }
+                let path = media_recorder.output_path(name, "png");
+                let timeout = self.settings.step_timeout.unwrap_or(COMMAND_TIMEOUT);
+                media_recorder.capture_command(terminal, text, timeout, &path).await?;
+                screenshots.push(path.clone());
+                artifacts.push(path);
+                if let Some(duration) = wait {
+                    self.pause(*duration, media_recorder).await;
+                }
+            }
+            StepType::Command { text, stdin, screenshot: None, wait, .. } => {
+                match stdin {
+                    Some(path) => terminal.execute_command_with_stdin(text, path).await?,
+                    None => terminal.execute_command(text).await?,
+                }
+                if let Some(duration) = wait {
+                    self.pause(*duration, media_recorder).await;
+                }
+            }
+            StepType::Type { text, speed, interpret_escapes } => {
+                let text = match interpret_escapes {
+                    true => std::borrow::Cow::Owned(script::interpret_escapes(text)?),
+                    false => std::borrow::Cow::Borrowed(text.as_str()),
+                };
+                // The badge gains each key as it is pressed
+                if self.deterministic {
+                    terminal.send_input(&text).await?;
+                    for ch in text.chars() {
+                        media_recorder.show_keystrokes(ch.encode_utf8(&mut [0; 4]));
+                        let delay = media_recorder.typing_delay(*speed);
+                        media_recorder.advance_clock(delay);
+                    }
+                } else {
+                    terminal
+                        .type_text_paced(&text, |ch| {
+                            media_recorder.show_keystrokes(ch.encode_utf8(&mut [0; 4]));
+                            media_recorder.typing_delay(*speed)
+                        })
+                        .await?;
+                }
+            }
+            StepType::Input { text } => {
+                terminal.send_input(text).await?;
+                media_recorder.show_keystrokes(text);
+            }
+            StepType::Screenshot { name, theme } => {
+                let path = media_recorder.output_path(name, "png");
+                let previous = override_theme(media_recorder, theme.as_deref())?;
+                let all: Vec<&TerminalController> = panes.iter().collect();
+                let result = media_recorder.take_panes_screenshot(&all, &path).await;
+                if let Some(previous) = previous {
+                    media_recorder.replace_theme(previous);
+                }
+                result?;
+                screenshots.push(path.clone());
+                artifacts.push(path);
+            }
+            StepType::Snapshot { name } => {
+                artifacts.extend(self.check_snapshot(name, terminal, media_recorder)?);
+            }
+            StepType::RecordGif { duration, name, fps, theme } => {
+                let path = media_recorder.output_path(name, media_recorder.animation_extension());
+                let previous = override_theme(media_recorder, theme.as_deref())?;
+                let all: Vec<&TerminalController> = panes.iter().collect();
+                let result = async {
+                    media_recorder.start_gif_recording(all[0]).await?;
+                    media_recorder.capture_panes_frames(&all, *duration, *fps).await?;
+                    media_recorder.stop_gif_recording(&path).await
+                }
+                .await;
+                if let Some(previous) = previous {
+                    media_recorder.replace_theme(previous);
+                }
+                let written = result?;
+                recordings.extend(written.clone());
+                artifacts.extend(written);
+            }
+            StepType::Annotate { text, duration, position } => {
+                let position = media::CaptionPosition::from_name(position.as_deref())?;
+                media_recorder.show_caption(media::Caption::new(text.clone(), position), *duration);
+            }
+            StepType::Mouse { event, x, y } => {
+                terminal.send_mouse(pty::MouseEvent::from_name(event)?, *x, *y).await?;
+            }
+            StepType::Repeat { count, steps, delay } => {
+                for iteration in 1..=*count {
+                    if let (true, Some(duration)) = (iteration > 1, delay) {
+                        self.pause(*duration, media_recorder).await;
+                    }
+                    for step in steps {
+                        self.settle(panes).await;
+                        if let Some(pattern) = &step.when {
+                            if !screen_matches(&panes[target], pattern)? {
+                                continue;
+                            }
+                        }
+                        let step_type = step.step_type.for_iteration(iteration);
+                        if let StepType::Command { text, .. } = &step_type {
+                            media_recorder.add_command(text);
+                        }
+                        let run = Box::pin(self.run_step_within_timeout(index, &step_type, panes, target, media_recorder))
+                            .await?;
+                        artifacts.extend(run.artifacts);
+                        screenshots.extend(run.screenshots);
+                        recordings.extend(run.recordings);
+                    }
+                }
+            }
+        }
+        
+        Ok(StepRun { artifacts, screenshots, recordings, attempts })
+    }
+    
+    /// Compare the visible screen's text with snapshot `name`, returning the file if written
+    ///
+    /// The screen is read as screenshots show it, after `prompt_replace`. A
+    /// missing or differing snapshot fails the step, unless snapshots are
+    /// being updated.
+    fn check_snapshot(
+        &self,
+        name: &str,
+        terminal: &TerminalController,
+        media_recorder: &MediaRecorder,
+    ) -> error::Result<Option<std::path::PathBuf>> {
+        let screen = media_recorder.screen_state(terminal).get_text();
+        let text = screen.trim_end_matches('\n').lines().map(|line| format!("{}\n", line)).collect::<String>();
+        
+        let path = self.snapshot_dir.join(format!("{}.txt", name));
+        match std::fs::read_to_string(&path) {
+            Ok(stored) if stored == text => return Ok(None),
+            Ok(stored) if !self.update_snapshots => {
+                return Err(KlaError::SnapshotMismatch {
+                    name: name.to_string(),
+                    path,
+                    diff: diff::ScreenDiff::between(&stored, &text).to_string(),
+                });
+            }
+            Ok(_) => log::info!("Updating snapshot {}", path.display()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && self.update_snapshots => {
+                log::info!("Writing new snapshot {}", path.display());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(KlaError::SnapshotMissing { name: name.to_string(), path });
+            }
+            Err(err) => {
+                return Err(KlaError::io_context(err, format_args!("Failed to read snapshot {}", path.display())));
+            }
+        }
+        
+        std::fs::create_dir_all(&self.snapshot_dir).map_err(|err| {
+            KlaError::io_context(err, format_args!("Failed to create snapshot directory {}", self.snapshot_dir.display()))
+        })?;
+        std::fs::write(&path, text)
+            .map_err(|err| KlaError::io_context(err, format_args!("Failed to write snapshot {}", path.display())))?;
+        Ok(Some(path))
+    }
+    
+    /// Let `duration` pass, on the recorder's simulated clock if deterministic
+    async fn pause(&self, duration: std::time::Duration, media_recorder: &mut MediaRecorder) {
+        if self.deterministic {
+            media_recorder.advance_clock(duration);
+        } else {
+            tokio::time::sleep(duration).await;
+        }
+    }
+    
+    /// The recorder used for every screenshot and animation during execution
+    fn media_recorder(&self) -> error::Result<MediaRecorder> {
+        let mut recorder = MediaRecorder::new(self.output_formats[0].clone(), &self.output_dir)?
+            .with_formats(self.output_formats.clone())
+            .deterministic(self.deterministic)
+            .with_theme(&self.theme)
+            .with_config(self.media_config.clone());
+        if let Some((pattern, replacement)) = &self.settings.prompt_replace {
+            let pattern = regex::Regex::new(pattern)
+                .map_err(|err| KlaError::parse(format!("Invalid prompt_replace: {}", err)))?;
+            recorder = recorder.with_prompt_replace(pattern, replacement);
+        }
+        Ok(recorder)
+    }
+    
+    /// Render a known block of text to a PNG without spawning a shell
+    ///
+    /// The image uses the configured terminal size, theme and media config.
+    pub fn render_text<P: AsRef<std::path::Path>>(
+        &self,
+        content: &str,
+        output_path: P,
+    ) -> error::Result<std::path::PathBuf> {
+        let output_path = output_path.as_ref();
+        let theme = ThemeConfig::from_name(&self.theme);
+        let metadata = media::PngMetadata {
+            theme: theme.name.clone(),
+            timestamp: !self.deterministic,
+            ..media::PngMetadata::default()
+        };
+        let generator = media::screenshot::ScreenshotGenerator::new(&self.media_config, &theme).with_metadata(metadata);
+        generator.generate(content, self.settings.width, self.settings.height, output_path)?;
+        Ok(output_path.to_path_buf())
+    }
+    
+    /// Run both scripts and compare the text each leaves in the terminal
+    ///
+    /// Scrollback is included, so output that scrolled off screen still counts.
+    pub async fn diff_screens(&self, before: &Script, after: &Script) -> error::Result<diff::ScreenDiff> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let before = self.execute_script(before).await?;
+        let after = self.execute_script(after).await?;
+        Ok(diff::ScreenDiff::between(
+            &diff::screen_text(&before.output, width, height),
+            &diff::screen_text(&after.output, width, height),
+        ))
+    }
+    
     /// Take a single screenshot of a command
-    pub async fn screenshot(&self, command: &str) -> anyhow::Result<std::path::PathBuf> {
+    pub async fn screenshot(&self, command: &str) -> error::Result<std::path::PathBuf> {
         let script = Script::single_command(command)?;
         let result = self.execute_script(&script).await?;
         
         // Return the first screenshot if any
         result.screenshots.into_iter().next()
-            .ok_or_else(|| anyhow::anyhow!("No screenshot was generated"))
+            .ok_or_else(|| KlaError::recording("No screenshot was generated"))
     }
 }
 
@@ -117,15 +707,92 @@ pub struct ExecutionResult {
     pub output: String,
     pub screenshots: Vec<std::path::PathBuf>,
     pub recordings: Vec<std::path::PathBuf>,
+    /// One entry per executed or skipped step, in order; a failing step ends the run with an error
+    pub steps: Vec<StepResult>,
+    /// Output of each layout pane by id; `output` holds the first pane's too
+    pub pane_outputs: std::collections::HashMap<String, String>,
+}
+
+/// What happened when a single script step ran
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    /// Position of the step in the script
+    pub index: usize,
+    /// Short description of the step, e.g. `screenshot 'listing'`
+    pub step_type: String,
+    pub duration: std::time::Duration,
+    pub outcome: StepOutcome,
+    /// Files written by this step
+    pub artifacts: Vec<std::path::PathBuf>,
+    /// Times the step ran; above 1 only for commands that were retried, 0 if skipped
+    pub attempts: u32,
+}
+
+/// What `run_step` reports back about a finished step
+struct StepRun {
+    artifacts: Vec<std::path::PathBuf>,
+    /// The screenshots among `artifacts`
+    screenshots: Vec<std::path::PathBuf>,
+    /// The animations among `artifacts`
+    recordings: Vec<std::path::PathBuf>,
+    attempts: u32,
+}
+
+/// Whether a command run failed: a nonzero exit, or output matching `failure`
+fn command_failed(run: &CommandRun, failure: Option<&regex::Regex>) -> bool {
+    let output = pty::ansi::strip_ansi(&run.output);
+    // The first line is the echoed command, which may well contain the pattern itself
+    let printed = output.split_once('\n').map_or("", |(_, rest)| rest);
+    run.exit_code != 0 || failure.is_some_and(|pattern| pattern.is_match(printed))
+}
+
+/// Whether the text on `terminal`'s screen matches the regex `pattern`
+pub(crate) fn screen_matches(terminal: &TerminalController, pattern: &str) -> error::Result<bool> {
+    let pattern = regex::Regex::new(pattern)
+        .map_err(|err| KlaError::parse(format!("invalid when pattern: {}", err)))?;
+    let (width, height) = terminal.get_size();
+    let mut capture = pty::capture::TerminalCapture::new(width, height);
+    capture.process_output(&terminal.get_output()).ok();
+    Ok(pattern.is_match(&capture.get_screen_contents()))
+}
+
+/// Switch `recorder` to a step's own theme, returning the theme to restore afterwards
+fn override_theme(recorder: &mut MediaRecorder, theme: Option<&str>) -> error::Result<Option<media::ThemeConfig>> {
+    let Some(theme) = theme else {
+        return Ok(None);
+    };
+    let theme = media::ThemeConfig::resolve(theme)?;
+    Ok(Some(recorder.replace_theme(theme)))
+}
+
+/// How a step finished
+///
+/// There is no failed outcome: a step that times out, mismatches its snapshot or
+/// exhausts its retries aborts the whole run with its error instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Ok,
+    /// The step's `when` pattern did not match the screen, so it never ran
+    Skipped,
 }
 
 /// Convenience function for quick automation
-pub async fn quick_screenshot(command: &str) -> anyhow::Result<std::path::PathBuf> {
+pub async fn quick_screenshot(command: &str) -> error::Result<std::path::PathBuf> {
     Kla::new().screenshot(command).await
 }
 
+/// Convenience function for rendering text straight to an image
+pub fn render_to_image<P: AsRef<std::path::Path>>(
+    content: &str,
+    width: u16,
+    height: u16,
+    output_path: P,
+) -> error::Result<std::path::PathBuf> {
+    Kla::new().size(width, height).render_text(content, output_path)
+}
+
 /// Convenience function for executing a script file
-pub async fn execute_script_file<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<ExecutionResult> {
+pub async fn execute_script_file<P: AsRef<std::path::Path>>(path: P) -> error::Result<ExecutionResult> {
     let script = ScriptLoader::load_from_file(path)?;
     Kla::new().execute_script(&script).await
 }
@@ -134,6 +801,17 @@ pub async fn execute_script_file<P: AsRef<std::path::Path>>(path: P) -> anyhow::
 mod tests {
     use super::*;
     
+    /// A small `/bin/sh` terminal with a plain `$ ` prompt
+    fn sh_settings() -> TerminalSettings {
+        TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 10,
+            startup_commands: vec!["PS1='$ '".to_string()],
+            ..TerminalSettings::default()
+        }
+    }
+    
     #[test]
     fn test_kla_builder() {
         let kla = Kla::new()
@@ -160,4 +838,904 @@ mod tests {
             _ => panic!("Expected Command step"),
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_render_text_dimensions() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let path = Kla::new()
+            .size(40, 10)
+            .render_text("build: ok\ntests: 42 passed\n", output_dir.path().join("log.png"))
+            .unwrap();
+        
+        let config = MediaConfig::default();
+        let char_width = config.font_size as u32 * 6 / 10;
+        let char_height = (config.font_size as f32 * config.line_height) as u32;
+        let (width, height) = image::image_dimensions(&path).unwrap();
+        
+        assert_eq!(width, 40 * char_width + config.padding as u32 * 2);
+        assert_eq!(height, 10 * char_height + config.padding as u32 * 2);
+    }
+    
+    #[test]
+    fn test_media_config_font_size_changes_dimensions() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let config = MediaConfig::builder().font_size(20).padding(10).build();
+        let path = Kla::new()
+            .size(40, 10)
+            .media_config(config)
+            .render_text("larger text", output_dir.path().join("large.png"))
+            .unwrap();
+        
+        // 20px font: 12px wide cells, 24px tall lines
+        let (width, height) = image::image_dimensions(&path).unwrap();
+        assert_eq!(width, 40 * 12 + 20);
+        assert_eq!(height, 10 * 24 + 20);
+    }
+    
+    #[tokio::test]
+    async fn test_font_size_propagates_to_execution() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let kla = Kla::new()
+            .shell("/bin/sh")
+            .size(20, 5)
+            .font_family("Fira Code")
+            .font_size(20)
+            .padding(4)
+            .output_dir(output_dir.path());
+        
+        let recorder = kla.media_recorder().unwrap();
+        assert_eq!(recorder.config().font_family, "Fira Code");
+        assert_eq!(recorder.config().font_size, 20);
+        
+        let script = ScriptLoader::load_from_string(r#"
+name: "Fonts"
+settings: {}
+steps:
+  - type: command
+    text: "echo hi"
+  - type: screenshot
+    name: "still"
+  - type: record_gif
+    duration: "50ms"
+    name: "moving"
+"#).unwrap();
+        let result = kla.execute_script(&script).await.unwrap();
+        
+        // 20px font: 12px wide cells, 24px tall lines, plus 4px padding per side
+        let expected = (20 * 12 + 8, 5 * 24 + 8);
+        assert_eq!(image::image_dimensions(&result.screenshots[0]).unwrap(), expected);
+        assert_eq!(image::image_dimensions(&result.recordings[0]).unwrap(), expected);
+    }
+    
+    #[tokio::test]
+    async fn test_step_results_track_artifacts() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Steps"
+settings:
+  shell: "/bin/sh"
+  width: 20
+  height: 5
+steps:
+  - type: command
+    text: "echo hi"
+    wait: "50ms"
+  - type: screenshot
+    name: "after"
+  - type: record_gif
+    duration: "50ms"
+    name: "clip"
+"#).unwrap();
+        
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        assert_eq!(result.steps.len(), 3);
+        for (index, step) in result.steps.iter().enumerate() {
+            assert_eq!(step.index, index);
+            assert_eq!(step.outcome, StepOutcome::Ok);
+        }
+        
+        assert_eq!(result.steps[0].step_type, "command \"echo hi\"");
+        assert!(result.steps[0].duration >= std::time::Duration::from_millis(50));
+        assert!(result.steps[0].artifacts.is_empty());
+        
+        assert_eq!(result.steps[1].step_type, "screenshot 'after'");
+        assert_eq!(result.steps[1].artifacts, vec![output_dir.path().join("after.png")]);
+        
+        assert!(result.steps[2].duration >= std::time::Duration::from_millis(50));
+        assert_eq!(result.steps[2].artifacts, vec![output_dir.path().join("clip.gif")]);
+    }
+    
+    #[tokio::test]
+    async fn test_step_timeout_aborts_hung_step() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Hangs"
+settings:
+  shell: "/bin/sh"
+steps:
+  - type: command
+    text: "echo ready"
+  - type: command
+    text: "read answer"
+    wait: "30s"
+"#).unwrap();
+        
+        let started = std::time::Instant::now();
+        let mut observer = CountingObserver::default();
+        let error = Kla::new()
+            .settings(script.settings.clone())
+            .step_timeout(std::time::Duration::from_secs(1))
+            .output_dir(output_dir.path())
+            .execute_script_with_observer(&script, &mut observer)
+            .await
+            .unwrap_err();
+        
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(observer.started, vec![0, 1]);
+        assert_eq!(observer.completed, vec![0]);
+        assert_eq!(error.to_string(), "Step 2 (command \"read answer\") timed out after 1s");
+        assert!(matches!(error, KlaError::StepTimeout { step: 2, .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_command_stdin_file() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let input = output_dir.path().join("input.txt");
+        std::fs::write(&input, "alpha\nbravo\ncharlie\n").unwrap();
+        
+        let script = Script {
+            name: "Stdin".to_string(),
+            description: None,
+            settings: TerminalSettings {
+                shell: "/bin/sh".to_string(),
+                ..TerminalSettings::default()
+            },
+            steps: vec![ScriptStep {
+                step_type: StepType::Command {
+                    text: "cat".to_string(),
+                    stdin: Some(input),
+                    screenshot: None,
+                    wait: Some(std::time::Duration::from_millis(500)),
+                    retries: 0,
+                    retry_on: None,
+                },
+                pane: None,
+                note: None,
+                when: None,
+            }],
+            layout: None,
+        };
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        // Each line is echoed by the tty and then printed again by `cat`
+        for line in ["alpha", "bravo", "charlie"] {
+            assert_eq!(result.output.matches(line).count(), 2, "{:?}", result.output);
+        }
+        
+        let missing = Script {
+            steps: vec![ScriptStep {
+                step_type: StepType::Command {
+                    text: "cat".to_string(),
+                    stdin: Some(output_dir.path().join("nope.txt")),
+                    screenshot: None,
+                    wait: None,
+                    retries: 0,
+                    retry_on: None,
+                },
+                pane: None,
+                note: None,
+                when: None,
+            }],
+            ..script
+        };
+        let error = Kla::new()
+            .settings(missing.settings.clone())
+            .output_dir(output_dir.path())
+            .execute_script(&missing)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to read stdin file"), "{:#}", error);
+    }
+    
+    #[tokio::test]
+    async fn test_flaky_command_passes_after_retry() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            working_dir: Some(output_dir.path().to_path_buf()),
+            ..TerminalSettings::default()
+        };
+        // Fails the first time it runs, leaving a marker that makes the next run pass
+        let flaky = "if [ -e ran-once ]; then echo ok; else touch ran-once; false; fi";
+        let command = |text: &str, retries| Script {
+            name: "Retry".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![ScriptStep {
+                step_type: StepType::Command {
+                    text: text.to_string(),
+                    stdin: None,
+                    screenshot: None,
+                    wait: None,
+                    retries,
+                    retry_on: None,
+                },
+                pane: None,
+                note: None,
+                when: None,
+            }],
+            layout: None,
+        };
+        let kla = Kla::new().settings(settings.clone()).output_dir(output_dir.path());
+        
+        let result = kla.execute_script(&command(flaky, 2)).await.unwrap();
+        assert_eq!(result.steps[0].attempts, 2);
+        assert!(output_dir.path().join("ran-once").exists());
+        
+        let error = kla.execute_script(&command("false", 1)).await.unwrap_err();
+        assert!(matches!(error, KlaError::CommandFailed { attempts: 2, .. }), "{}", error);
+    }
+    
+    #[tokio::test]
+    async fn test_parallel_screenshot_batch() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 5,
+            ..TerminalSettings::default()
+        };
+        let script = Script {
+            name: "Batch".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: (1..=8)
+                .map(|n| ScriptStep {
+                    step_type: StepType::Command {
+                        text: format!("sleep 0.5; echo step-{}", n),
+                        stdin: None,
+                        screenshot: Some(format!("shot-{}", n)),
+                        wait: None,
+                        retries: 0,
+                        retry_on: None,
+                    },
+                    pane: None,
+                    note: None,
+                    when: None,
+                })
+                .collect(),
+            layout: None,
+        };
+        let kla = Kla::new().settings(settings).output_dir(output_dir.path()).max_parallelism(8);
+        
+        let started = std::time::Instant::now();
+        let result = kla.execute_script(&script).await.unwrap();
+        // Run one after another, the sleeps alone would take four seconds
+        assert!(started.elapsed() < std::time::Duration::from_secs(3), "took {:?}", started.elapsed());
+        
+        assert_eq!(result.steps.iter().map(|step| step.index).collect::<Vec<_>>(), (0..8).collect::<Vec<_>>());
+        for (n, path) in (1..=8).zip(&result.screenshots) {
+            assert_eq!(path, &output_dir.path().join(format!("shot-{}.png", n)));
+            assert!(path.exists());
+        }
+        assert_eq!(result.screenshots.len(), 8);
+        assert!(result.output.find("step-1").unwrap() < result.output.find("step-8").unwrap());
+    }
+    
+    struct MarkerGenerator;
+    
+    impl MediaGenerator for MarkerGenerator {
+        fn create_output(&self, content: &str, output_path: &std::path::Path) -> anyhow::Result<()> {
+            std::fs::write(output_path, format!("MARKER\n{}", content))?;
+            Ok(())
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_registered_generator_writes_custom_format() {
+        register_generator(".kla-marker", Box::new(MarkerGenerator));
+        let format = OutputFormat::from_string("KLA-MARKER").unwrap();
+        assert_eq!(format, OutputFormat::Custom("kla-marker".to_string()));
+        assert!(OutputFormat::from_string("unregistered").is_err());
+        
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let script = Script {
+            name: "Custom Format".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![ScriptStep {
+                step_type: StepType::Command {
+                    text: "echo plug''in".to_string(),
+                    stdin: None,
+                    screenshot: None,
+                    wait: Some(std::time::Duration::from_millis(300)),
+                    retries: 0,
+                    retry_on: None,
+                },
+                pane: None,
+                note: None,
+                when: None,
+            }],
+            layout: None,
+        };
+        let result = Kla::new()
+            .settings(settings)
+            .formats(vec![format])
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        let path = output_dir.path().join("custom-format.kla-marker");
+        assert_eq!(result.recordings, vec![path.clone()]);
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("MARKER\n"));
+        assert!(written.contains("plugin"), "{:?}", written);
+    }
+    
+    #[tokio::test]
+    async fn test_cast_leaves_out_separate_stderr_wrapper() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Stderr Cast"
+settings:
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+  separate_stderr: true
+steps:
+  - type: command
+    text: "echo hello"
+    screenshot: "hello"
+  - type: command
+    text: "echo oops >&2"
+    wait: "300ms"
+"#).unwrap();
+        
+        let output_dir = tempfile::TempDir::new().unwrap();
+        Kla::new()
+            .settings(script.settings.clone())
+            .formats(vec![OutputFormat::Png, OutputFormat::Asciicast])
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        let cast = std::fs::read_to_string(output_dir.path().join("stderr-cast.cast")).unwrap();
+        assert!(cast.contains("echo hello"), "{}", cast);
+        assert!(cast.contains("echo oops >&2"), "{}", cast);
+        for plumbing in ["kla-stderr", "2>>", "kla-exit"] {
+            assert!(!cast.contains(plumbing), "{} in {}", plumbing, cast);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_timeline_lists_each_command() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = sh_settings();
+        let command = |text: &str| ScriptStep {
+            step_type: StepType::Command {
+                text: text.to_string(),
+                stdin: None,
+                screenshot: None,
+                wait: Some(std::time::Duration::from_millis(300)),
+                retries: 0,
+                retry_on: None,
+            },
+            pane: None,
+            note: None,
+            when: None,
+        };
+        let script = Script {
+            name: "Timeline".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![command("echo first"), command("echo second")],
+            layout: None,
+        };
+        let kla = Kla::new()
+            .settings(settings)
+            .format(OutputFormat::Png)
+            .media_config(MediaConfig::builder().emit_timeline(true).build())
+            .output_dir(output_dir.path());
+        let result = kla.execute_script(&script).await.unwrap();
+        
+        let path = output_dir.path().join("timeline.timeline.json");
+        assert!(result.recordings.contains(&path));
+        let read_timeline = || -> Vec<media::timeline::TimelineEntry> {
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+        };
+        let timeline = read_timeline();
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].text, "echo first");
+        assert!(timeline[0].output.contains("\nfirst\n"), "{:?}", timeline[0].output);
+        assert!(timeline[0].start < timeline[0].end);
+        assert!(timeline[0].end <= timeline[1].start);
+        assert!(timeline[1].output.contains("second"));
+        
+        // Deterministic runs time entries on the simulated clock
+        kla.deterministic(true).execute_script(&script).await.unwrap();
+        let timeline = read_timeline();
+        let times: Vec<_> = timeline.iter().map(|entry| (entry.start, entry.end)).collect();
+        assert_eq!(times, vec![(0.0, 0.3), (0.3, 0.6)]);
+        assert!(timeline[1].output.contains("second"));
+    }
+    
+    #[tokio::test]
+    async fn test_snapshot_written_then_matched() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let snapshot_dir = output_dir.path().join("snapshots");
+        let settings = sh_settings();
+        let script = Script {
+            name: "Snapshot".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![
+                ScriptStep {
+                    step_type: StepType::Command {
+                        text: "echo golden".to_string(),
+                        stdin: None,
+                        screenshot: None,
+                        wait: Some(std::time::Duration::from_millis(300)),
+                        retries: 0,
+                        retry_on: None,
+                    },
+                    pane: None,
+                    note: None,
+                    when: None,
+                },
+                ScriptStep { step_type: StepType::Snapshot { name: "echo".to_string() }, pane: None, note: None, when: None },
+            ],
+            layout: None,
+        };
+        let kla = |update: bool| {
+            Kla::new()
+                .settings(settings.clone())
+                .output_dir(output_dir.path())
+                .snapshot_dir(&snapshot_dir)
+                .update_snapshots(update)
+        };
+        let path = snapshot_dir.join("echo.txt");
+        
+        // A missing golden file fails until snapshots are updated
+        let error = kla(false).execute_script(&script).await.unwrap_err();
+        assert!(matches!(error, KlaError::SnapshotMissing { .. }), "{}", error);
+        assert!(!path.exists());
+        let first = kla(true).execute_script(&script).await.unwrap();
+        assert_eq!(first.steps[1].artifacts, vec![path.clone()]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "$ echo golden\ngolden\n$\n");
+        
+        let second = kla(false).execute_script(&script).await.unwrap();
+        assert!(second.steps[1].artifacts.is_empty());
+        
+        std::fs::write(&path, "$ echo golden\nsilver\n$\n").unwrap();
+        let error = kla(false).execute_script(&script).await.unwrap_err();
+        assert!(matches!(error, KlaError::SnapshotMismatch { .. }), "{}", error);
+        assert!(error.to_string().contains("silver"), "{}", error);
+        
+        kla(true).execute_script(&script).await.unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().contains("\ngolden\n"));
+        
+        // Snapshots see the screen as screenshots do, prompt rewritten
+        let settings = TerminalSettings {
+            prompt_replace: Some(("^\\$".to_string(), "demo>".to_string())),
+            ..settings
+        };
+        Kla::new()
+            .settings(settings)
+            .output_dir(output_dir.path())
+            .snapshot_dir(&snapshot_dir)
+            .update_snapshots(true)
+            .execute_script(&script)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "demo> echo golden\ngolden\ndemo>\n");
+    }
+    
+    #[tokio::test]
+    async fn test_step_theme_overrides_only_its_screenshot() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = sh_settings();
+        let screenshot = |name: &str, theme: Option<&str>| ScriptStep {
+            step_type: StepType::Screenshot { name: name.to_string(), theme: theme.map(str::to_string) },
+            pane: None,
+            note: None,
+            when: None,
+        };
+        let script = Script {
+            name: "Themes".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![screenshot("dracula", Some("dracula")), screenshot("plain", None)],
+            layout: None,
+        };
+        Kla::new()
+            .settings(settings)
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        let background = |name: &str| {
+            let image = image::open(output_dir.path().join(format!("{}.png", name))).unwrap().to_rgb8();
+            let [r, g, b] = image[(image.width() / 2, image.height() - 2)].0;
+            (r, g, b)
+        };
+        assert_eq!(background("dracula"), media::ThemeConfig::dracula_theme().background);
+        assert_eq!(background("plain"), media::ThemeConfig::default_theme().background);
+    }
+    
+    #[tokio::test]
+    async fn test_layout_routes_steps_to_their_pane() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = sh_settings();
+        let command = |pane: &str, text: &str| ScriptStep {
+            step_type: StepType::Command {
+                text: text.to_string(),
+                stdin: None,
+                screenshot: None,
+                wait: Some(std::time::Duration::from_millis(300)),
+                retries: 0,
+                retry_on: None,
+            },
+            pane: Some(pane.to_string()),
+            note: None,
+            when: None,
+        };
+        let script = Script {
+            name: "Panes".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![
+                command("a", "echo from-pane-a"),
+                command("b", "echo from-pane-b"),
+                ScriptStep { step_type: StepType::Screenshot { name: "both".to_string(), theme: None }, pane: None, note: None, when: None },
+            ],
+            layout: Some(Layout { panes: vec!["a".to_string(), "b".to_string()], direction: None, gap: 10 }),
+        };
+        let result = Kla::new().settings(settings).output_dir(output_dir.path()).execute_script(&script).await.unwrap();
+        
+        assert!(result.pane_outputs["a"].contains("from-pane-a"));
+        assert!(!result.pane_outputs["a"].contains("from-pane-b"));
+        assert!(result.pane_outputs["b"].contains("from-pane-b"));
+        assert!(!result.pane_outputs["b"].contains("from-pane-a"));
+        
+        let (width, _) = image::image_dimensions(output_dir.path().join("both.png")).unwrap();
+        let (pane_width, _) = image::image_dimensions(
+            Kla::new().size(40, 10).render_text("", output_dir.path().join("one.png")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(width, 2 * pane_width + 10);
+        
+        let empty = Script { layout: Some(Layout { panes: Vec::new(), direction: None, gap: 0 }), ..script };
+        let error = Kla::new().output_dir(output_dir.path()).execute_script(&empty).await.unwrap_err();
+        assert!(error.to_string().contains("at least one pane"), "{}", error);
+    }
+    
+    #[tokio::test]
+    async fn test_diff_screens_flags_changed_output() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let settings = sh_settings();
+        let script = |word: &str| Script {
+            name: "Diff".to_string(),
+            description: None,
+            settings: settings.clone(),
+            steps: vec![ScriptStep {
+                step_type: StepType::Command {
+                    text: format!("echo {}", word),
+                    stdin: None,
+                    screenshot: None,
+                    wait: Some(std::time::Duration::from_millis(300)),
+                    retries: 0,
+                    retry_on: None,
+                },
+                pane: None,
+                note: None,
+                when: None,
+            }],
+            layout: None,
+        };
+        let kla = Kla::new().settings(settings.clone()).output_dir(output_dir.path());
+        
+        let diff = kla.diff_screens(&script("alpha"), &script("bravo")).await.unwrap();
+        let output_line = diff.changes.iter().any(|change| matches!(
+            change,
+            diff::LineChange::Changed { before, after, .. } if before == "alpha" && after == "bravo"
+        ));
+        assert!(output_line, "{}", diff);
+        
+        let same = kla.diff_screens(&script("alpha"), &script("alpha")).await.unwrap();
+        assert!(same.is_empty(), "{}", same);
+    }
+    
+    #[derive(Default)]
+    struct CountingObserver {
+        started: Vec<usize>,
+        completed: Vec<usize>,
+    }
+    
+    impl StepObserver for CountingObserver {
+        fn on_step_start(&mut self, index: usize, _step: &ScriptStep) {
+            self.started.push(index);
+        }
+        
+        fn on_step_complete(&mut self, index: usize, _step: &ScriptStep) {
+            self.completed.push(index);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_observer_sees_every_step_once() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Observed"
+settings:
+  shell: "/bin/sh"
+steps:
+  - type: command
+    text: "echo one"
+  - type: type
+    text: "echo two"
+    speed: "1ms"
+  - type: screenshot
+    name: "observed"
+"#).unwrap();
+        
+        let kla = Kla::new()
+            .settings(script.settings.clone())
+            .output_dir(output_dir.path());
+        let mut observer = CountingObserver::default();
+        let result = kla.execute_script_with_observer(&script, &mut observer).await.unwrap();
+        
+        assert_eq!(observer.started, vec![0, 1, 2]);
+        assert_eq!(observer.completed, vec![0, 1, 2]);
+        assert_eq!(result.screenshots, vec![output_dir.path().join("observed.png")]);
+    }
+    
+    #[tokio::test]
+    async fn test_one_run_produces_every_format() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Formats"
+settings:
+  shell: "/bin/sh"
+  width: 20
+  height: 4
+steps:
+  - type: command
+    text: "echo hi"
+  - type: screenshot
+    name: "still"
+  - type: record_gif
+    duration: "1s"
+    name: "moving"
+    fps: 2
+"#).unwrap();
+        
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .formats(vec![OutputFormat::Png, OutputFormat::Gif, OutputFormat::Apng])
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        assert_eq!(result.screenshots, vec![output_dir.path().join("still.png")]);
+        assert_eq!(
+            result.recordings,
+            vec![output_dir.path().join("moving.gif"), output_dir.path().join("moving.apng")]
+        );
+        for path in result.screenshots.iter().chain(&result.recordings) {
+            assert!(path.exists(), "{} was not written", path.display());
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_deterministic_recordings_are_identical() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Deterministic"
+settings:
+  shell: "/bin/sh"
+  width: 30
+  height: 5
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: type
+    text: "echo one"
+    speed: "50ms"
+  - type: command
+    text: ""
+  - type: annotate
+    text: "Counting"
+    duration: "400ms"
+  - type: command
+    text: "echo two"
+    wait: "200ms"
+  - type: record_gif
+    duration: "1s"
+    name: "counting"
+    fps: 5
+"#).unwrap();
+        
+        let mut recordings = Vec::new();
+        for _ in 0..2 {
+            let output_dir = tempfile::TempDir::new().unwrap();
+            let result = Kla::new()
+                .settings(script.settings.clone())
+                .deterministic(true)
+                .output_dir(output_dir.path())
+                .execute_script(&script)
+                .await
+                .unwrap();
+            recordings.push(std::fs::read(&result.recordings[0]).unwrap());
+        }
+        assert_eq!(recordings[0], recordings[1]);
+    }
+    
+    #[tokio::test]
+    async fn test_typed_keys_shown_in_frame_badge() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Keys"
+settings:
+  shell: "/bin/sh"
+  width: 40
+  height: 10
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: type
+    text: "ls"
+    speed: "10ms"
+  - type: record_gif
+    duration: "2s"
+    name: "keys"
+    fps: 2
+"#).unwrap();
+        
+        async fn first_and_last_frames(script: &Script, show: bool) -> (image::RgbaImage, image::RgbaImage) {
+            use image::AnimationDecoder;
+            let output_dir = tempfile::TempDir::new().unwrap();
+            let result = Kla::new()
+                .settings(script.settings.clone())
+                .media_config(MediaConfig::builtin_font().show_keystrokes(show).build())
+                .deterministic(true)
+                .output_dir(output_dir.path())
+                .execute_script(script)
+                .await
+                .unwrap();
+            let file = std::fs::File::open(&result.recordings[0]).unwrap();
+            let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+            (frames[0].buffer().clone(), frames[frames.len() - 1].buffer().clone())
+        }
+        
+        let (first, last) = first_and_last_frames(&script, false).await;
+        assert!(first == last, "nothing changes without the badge");
+        
+        // Frames in the first 1.5s after typing show the keys, later ones don't
+        let (first, last) = first_and_last_frames(&script, true).await;
+        let (width, height) = first.dimensions();
+        let changed: Vec<(u32, u32)> = first
+            .enumerate_pixels()
+            .filter(|&(x, y, pixel)| pixel != last.get_pixel(x, y))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!changed.is_empty(), "the badge goes away once the keys linger long enough");
+        assert!(changed.iter().all(|&(x, y)| x >= width / 2 && y >= height / 2), "the badge sits at the bottom right");
+    }
+    
+    #[tokio::test]
+    async fn test_type_interprets_escapes_when_asked() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Escapes"
+settings:
+  run_program: ["cat", "-A"]
+steps:
+  - type: type
+    text: 'a\tb\n'
+    speed: "1ms"
+    interpret_escapes: true
+  - type: type
+    text: 'c\td'
+    speed: "1ms"
+  - type: command
+    text: ""
+    wait: "300ms"
+"#).unwrap();
+        
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        // `cat -A` shows a tab as ^I and a literal backslash as itself
+        assert!(result.output.contains("a^Ib$"), "{:?}", result.output);
+        assert!(result.output.contains("c\\td$"), "{:?}", result.output);
+    }
+    
+    #[tokio::test]
+    async fn test_step_skipped_when_screen_does_not_match() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Conditional"
+settings:
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: command
+    text: "echo service is run''ning"
+    wait: "200ms"
+  - type: command
+    text: "echo start''ed"
+    wait: "200ms"
+    when: "service is stopped"
+  - type: command
+    text: "echo chec''ked"
+    wait: "200ms"
+    when: "service is running"
+"#).unwrap();
+        
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        let outcomes: Vec<&StepOutcome> = result.steps.iter().map(|step| &step.outcome).collect();
+        assert_eq!(outcomes, vec![&StepOutcome::Ok, &StepOutcome::Skipped, &StepOutcome::Ok]);
+        assert_eq!(result.steps[1].attempts, 0);
+        assert!(!result.output.contains("start"), "{:?}", result.output);
+        assert!(result.output.contains("checked"), "{:?}", result.output);
+    }
+    
+    #[tokio::test]
+    async fn test_repeat_runs_inner_steps_each_iteration() {
+        let output_dir = tempfile::TempDir::new().unwrap();
+        let script = ScriptLoader::load_from_string(r#"
+name: "Repeat"
+settings:
+  shell: "/bin/sh"
+  startup_commands: ["PS1='$ '"]
+steps:
+  - type: repeat
+    count: 3
+    delay: "50ms"
+    steps:
+      - type: command
+        text: "echo po''ll"
+        wait: "150ms"
+      - type: screenshot
+        name: "poll"
+"#).unwrap();
+        
+        // The limit applies to each inner step, not the whole repeat
+        let result = Kla::new()
+            .settings(script.settings.clone())
+            .step_timeout(std::time::Duration::from_millis(400))
+            .output_dir(output_dir.path())
+            .execute_script(&script)
+            .await
+            .unwrap();
+        
+        let printed = pty::ansi::strip_ansi(&result.output);
+        assert_eq!(printed.lines().filter(|line| line.trim() == "poll").count(), 3, "{:?}", printed);
+        let expected: Vec<_> = (1..=3).map(|i| output_dir.path().join(format!("poll-{}.png", i))).collect();
+        assert_eq!(result.screenshots, expected);
+        assert_eq!(result.steps.len(), 1);
+    }
+}
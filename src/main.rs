@@ -1,12 +1,6 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::Parser;
 
-mod cli;
-mod script;
-mod pty;
-mod media;
-
-use cli::Commands;
+use kla::cli::{self, Commands};
 
 #[derive(Parser)]
 #[command(name = "kla")]
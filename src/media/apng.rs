@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::gif::{frame_delays, GifFrame};
+use super::MediaConfig;
+
+/// Encode captured frames as an animated PNG that plays `loop_count` times
+///
+/// Unlike GIF, every frame keeps its full 24-bit color. Delays are in
+/// centiseconds, as produced by the GIF capture pipeline.
+pub fn encode_apng(frames: &[GifFrame], config: &MediaConfig) -> Result<Vec<u8>> {
+    let first = frames.first().ok_or_else(|| anyhow::anyhow!("No frames to save"))?;
+    let (width, height) = first.image.dimensions();
+
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Zero plays means loop forever
+        encoder.set_animated(frames.len() as u32, u32::from(config.loop_count.unwrap_or(0)))?;
+
+        let mut writer = encoder.write_header()?;
+        for (frame, delay) in frames.iter().zip(frame_delays(frames, config)) {
+            writer.set_frame_delay(delay, 100)?;
+            writer.write_image_data(&frame.image)
+                .context("Failed to write APNG frame")?;
+        }
+        writer.finish()?;
+    }
+    Ok(data)
+}
+
+/// Encode frames with `encode_apng` and write them to `output_path`
+pub fn save_apng(frames: &[GifFrame], config: &MediaConfig, output_path: &Path) -> Result<()> {
+    let data = encode_apng(frames, config)?;
+    std::fs::write(output_path, data)
+        .with_context(|| format!("Failed to write APNG file: {}", output_path.display()))?;
+
+    log::info!("APNG saved to: {}", output_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_apng_has_expected_frame_count() {
+        let frames: Vec<GifFrame> = [(40, 44, 52), (171, 178, 191), (97, 175, 239)]
+            .iter()
+            .map(|&(r, g, b)| GifFrame { image: RgbImage::from_pixel(24, 12, Rgb([r, g, b])), delay: 25 })
+            .collect();
+        let data = encode_apng(&frames, &MediaConfig::builtin_font().build()).unwrap();
+
+        // acTL holds the frame count followed by the play count
+        let actl = data.windows(4).position(|window| window == b"acTL").unwrap();
+        let num_frames = u32::from_be_bytes(data[actl + 4..actl + 8].try_into().unwrap());
+        let num_plays = u32::from_be_bytes(data[actl + 8..actl + 12].try_into().unwrap());
+        assert_eq!(num_frames, 3);
+        assert_eq!(num_plays, 0);
+
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, 3);
+        let frame = reader.info().frame_control.unwrap();
+        assert_eq!((frame.delay_num, frame.delay_den), (25, 100));
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::pty::{OutputEvent, TerminalController};
+
+/// Header line of an asciicast v2 file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AsciicastHeader {
+    pub version: u8,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Reads and writes terminal sessions as asciinema v2 `.cast` files
+pub struct AsciicastRecorder {
+    header: AsciicastHeader,
+    events: Vec<OutputEvent>,
+}
+
+impl AsciicastRecorder {
+    pub fn new(width: u16, height: u16) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            header: AsciicastHeader {
+                version: 2,
+                width,
+                height,
+                timestamp,
+                title: None,
+            },
+            events: Vec::new(),
+        }
+    }
+
+    /// Build a recorder holding everything the terminal has output so far
+    pub fn from_terminal(terminal: &TerminalController) -> Self {
+        let (width, height) = terminal.get_size();
+        let mut recorder = Self::new(width, height);
+        for event in terminal.get_output_events() {
+            recorder.push_event(event.elapsed, event.data);
+        }
+        recorder
+    }
+
+    pub fn with_title<S: Into<String>>(mut self, title: S) -> Self {
+        self.header.title = Some(title.into());
+        self
+    }
+
+    pub fn header(&self) -> &AsciicastHeader {
+        &self.header
+    }
+
+    /// Append an output event, keeping timestamps monotonically increasing
+    pub fn push_event(&mut self, elapsed: Duration, data: String) {
+        let elapsed = match self.events.last() {
+            Some(last) if last.elapsed > elapsed => last.elapsed,
+            _ => elapsed,
+        };
+        self.events.push(OutputEvent { elapsed, data });
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+    
+    pub fn events(&self) -> &[OutputEvent] {
+        &self.events
+    }
+    
+    /// Parse a v2 cast, keeping only output (`"o"`) events
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header_line = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Cast file is empty"))??;
+        let header: AsciicastHeader = serde_json::from_str(&header_line)
+            .context("Failed to parse asciicast header")?;
+        if header.version != 2 {
+            return Err(anyhow::anyhow!("Unsupported asciicast version: {}", header.version));
+        }
+        
+        let mut recorder = Self { header, events: Vec::new() };
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, kind, data): (f64, String, String) = serde_json::from_str(&line)
+                .with_context(|| format!("Failed to parse asciicast event on line {}", index + 2))?;
+            if kind == "o" {
+                recorder.push_event(Duration::from_secs_f64(time.max(0.0)), data);
+            }
+        }
+        Ok(recorder)
+    }
+    
+    pub fn load(input_path: &Path) -> Result<Self> {
+        let file = File::open(input_path)
+            .with_context(|| format!("Failed to open cast file: {}", input_path.display()))?;
+        Self::read_from(BufReader::new(file))
+            .with_context(|| format!("Failed to read cast file: {}", input_path.display()))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        serde_json::to_writer(&mut writer, &self.header)
+            .context("Failed to write asciicast header")?;
+        writeln!(writer)?;
+
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, &(event.elapsed.as_secs_f64(), "o", &event.data))
+                .context("Failed to write asciicast event")?;
+            writeln!(writer)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn save(&self, output_path: &Path) -> Result<()> {
+        let file = File::create(output_path)
+            .with_context(|| format!("Failed to create cast file: {}", output_path.display()))?;
+        self.write_to(BufWriter::new(file))?;
+
+        log::info!("Asciicast saved to: {}", output_path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asciicast_output() {
+        let mut recorder = AsciicastRecorder::new(100, 25);
+        recorder.push_event(Duration::from_millis(10), "$ ".to_string());
+        recorder.push_event(Duration::from_millis(250), "echo hi\r\n".to_string());
+        // Out-of-order arrival must not produce a decreasing timestamp
+        recorder.push_event(Duration::from_millis(200), "hi\r\n".to_string());
+
+        let mut output = Vec::new();
+        recorder.write_to(&mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let mut lines = text.lines();
+
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 100);
+        assert_eq!(header["height"], 25);
+        assert!(header["timestamp"].as_u64().unwrap() > 0);
+
+        let events: Vec<(f64, String, String)> = lines
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|(_, kind, _)| kind == "o"));
+        assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(events[1].2, "echo hi\r\n");
+    }
+    
+    #[test]
+    fn test_asciicast_round_trip() {
+        let mut recorder = AsciicastRecorder::new(40, 10).with_title("demo");
+        recorder.push_event(Duration::from_millis(100), "$ ls\r\n".to_string());
+        recorder.push_event(Duration::from_millis(600), "README.md\r\n".to_string());
+        
+        let mut output = Vec::new();
+        recorder.write_to(&mut output).unwrap();
+        // Input events are ignored on read
+        output.extend_from_slice(b"[0.7, \"i\", \"q\"]\n");
+        
+        let parsed = AsciicastRecorder::read_from(output.as_slice()).unwrap();
+        assert_eq!(parsed.header().width, 40);
+        assert_eq!(parsed.header().title.as_deref(), Some("demo"));
+        assert_eq!(parsed.events(), recorder.events());
+        
+        assert!(AsciicastRecorder::read_from(&b"{\"version\": 1, \"width\": 1, \"height\": 1}"[..]).is_err());
+    }
+}
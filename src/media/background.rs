@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use image::{imageops, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Backdrop drawn behind the terminal panel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Background {
+    Solid {
+        color: (u8, u8, u8),
+    },
+    /// Angle is in degrees: 0 runs left to right, 90 runs top to bottom
+    LinearGradient {
+        from: (u8, u8, u8),
+        to: (u8, u8, u8),
+        #[serde(default)]
+        angle: f32,
+    },
+    /// Image scaled to cover the whole canvas, optionally blurred
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        blur: f32,
+    },
+}
+
+impl Background {
+    /// Render the backdrop at the given canvas size
+    pub fn render(&self, width: u32, height: u32) -> Result<RgbImage> {
+        match self {
+            Background::Solid { color } => {
+                Ok(RgbImage::from_pixel(width, height, Rgb([color.0, color.1, color.2])))
+            }
+            Background::LinearGradient { from, to, angle } => {
+                Ok(linear_gradient(width, height, *from, *to, *angle))
+            }
+            Background::Image { path, blur } => {
+                let source = image::open(path)
+                    .with_context(|| format!("Failed to load background image: {}", path.display()))?
+                    .to_rgb8();
+                let mut covered = cover(&source, width, height);
+                if *blur > 0.0 {
+                    covered = imageops::blur(&covered, *blur);
+                }
+                Ok(covered)
+            }
+        }
+    }
+}
+
+fn linear_gradient(
+    width: u32,
+    height: u32,
+    from: (u8, u8, u8),
+    to: (u8, u8, u8),
+    angle: f32,
+) -> RgbImage {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let project = |x: f32, y: f32| x * cos + y * sin;
+
+    // Normalize the projection so the extreme corners map to 0.0 and 1.0
+    let (max_x, max_y) = (width.saturating_sub(1) as f32, height.saturating_sub(1) as f32);
+    let corners = [project(0.0, 0.0), project(max_x, 0.0), project(0.0, max_y), project(max_x, max_y)];
+    let min = corners.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = corners.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let span = (max - min).max(f32::EPSILON);
+
+    RgbImage::from_fn(width, height, |x, y| {
+        let t = (project(x as f32, y as f32) - min) / span;
+        Rgb([lerp(from.0, to.0, t), lerp(from.1, to.1, t), lerp(from.2, to.2, t)])
+    })
+}
+
+fn lerp(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t).round() as u8
+}
+
+/// Scale `source` to fill `width`x`height`, cropping the overflow evenly
+fn cover(source: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = f32::max(
+        width as f32 / source.width() as f32,
+        height as f32 / source.height() as f32,
+    );
+    let scaled_width = ((source.width() as f32 * scale).ceil() as u32).max(width);
+    let scaled_height = ((source.height() as f32 * scale).ceil() as u32).max(height);
+
+    let scaled = imageops::resize(source, scaled_width, scaled_height, imageops::FilterType::Triangle);
+    let x = (scaled_width - width) / 2;
+    let y = (scaled_height - height) / 2;
+    imageops::crop_imm(&scaled, x, y, width, height).to_image()
+}
+
+/// Blend `top` over `bottom` with the given opacity
+pub fn blend(bottom: Rgb<u8>, top: Rgb<u8>, opacity: f32) -> Rgb<u8> {
+    let opacity = opacity.clamp(0.0, 1.0);
+    Rgb([
+        lerp(bottom[0], top[0], opacity),
+        lerp(bottom[1], top[1], opacity),
+        lerp(bottom[2], top[2], opacity),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_gradient_corners() {
+        let horizontal = Background::LinearGradient { from: (0, 0, 0), to: (200, 100, 50), angle: 0.0 };
+        let image = horizontal.render(101, 20).unwrap();
+        assert_eq!(*image.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(0, 19), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(100, 0), Rgb([200, 100, 50]));
+        assert_eq!(*image.get_pixel(50, 10), Rgb([100, 50, 25]));
+
+        let vertical = Background::LinearGradient { from: (255, 0, 0), to: (0, 0, 255), angle: 90.0 };
+        let image = vertical.render(20, 11).unwrap();
+        assert_eq!(*image.get_pixel(19, 0), Rgb([255, 0, 0]));
+        assert_eq!(*image.get_pixel(0, 10), Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn test_image_background_covers_canvas() {
+        // Left half red, right half blue
+        let source = RgbImage::from_fn(10, 10, |x, _| {
+            if x < 5 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) }
+        });
+        let file = NamedTempFile::with_suffix(".png").unwrap();
+        source.save(file.path()).unwrap();
+
+        let background = Background::Image { path: file.path().to_path_buf(), blur: 0.0 };
+        let image = background.render(200, 100).unwrap();
+
+        assert_eq!(image.dimensions(), (200, 100));
+        assert_eq!(*image.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*image.get_pixel(199, 99), Rgb([0, 0, 255]));
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+
+/// Explanatory text drawn over rendered frames, outside the terminal itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caption {
+    pub text: String,
+    pub position: CaptionPosition,
+}
+
+/// Where a caption box sits on the frame; it is always centered horizontally
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptionPosition {
+    Top,
+    #[default]
+    Bottom,
+    Center,
+}
+
+/// Position names accepted by `annotate` steps
+pub const CAPTION_POSITIONS: &[&str] = &["top", "bottom", "center"];
+
+impl CaptionPosition {
+    /// Parse a position name, defaulting to the bottom of the frame
+    pub fn from_name(name: Option<&str>) -> Result<Self> {
+        match name.map(|name| name.trim().to_lowercase()).as_deref() {
+            None | Some("bottom") => Ok(CaptionPosition::Bottom),
+            Some("top") => Ok(CaptionPosition::Top),
+            Some("center") | Some("centre") | Some("middle") => Ok(CaptionPosition::Center),
+            Some(other) => Err(anyhow::anyhow!(
+                "Unknown caption position '{}'. Expected one of: {}",
+                other,
+                CAPTION_POSITIONS.join(", ")
+            )),
+        }
+    }
+}
+
+/// Most recent keys a keystroke badge shows
+const KEYSTROKE_BADGE_KEYS: usize = 24;
+
+/// How typed `text` reads in a keystroke badge
+///
+/// Control keys are shown by name or as `^X`, and only the last few keys are kept.
+pub fn keystroke_label(text: &str) -> String {
+    let keys: Vec<String> = text
+        .chars()
+        .map(|ch| match ch {
+            '\n' | '\r' => "⏎".to_string(),
+            '\t' => "⇥".to_string(),
+            '\x1b' => "Esc".to_string(),
+            '\x08' | '\x7f' => "⌫".to_string(),
+            ch if ch.is_ascii_control() => format!("^{}", (ch as u8 + b'@') as char),
+            ch => ch.to_string(),
+        })
+        .collect();
+    keys[keys.len().saturating_sub(KEYSTROKE_BADGE_KEYS)..].concat()
+}
+
+impl Caption {
+    pub fn new<S: Into<String>>(text: S, position: CaptionPosition) -> Self {
+        Self {
+            text: text.into(),
+            position,
+        }
+    }
+}
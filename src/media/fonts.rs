@@ -0,0 +1,134 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Name of KLA's own glyph set, which has a glyph for every code point
+///
+/// The glyphs are dot patterns that tell characters apart, not real letter
+/// shapes. They always end a fallback chain, so characters no installed font
+/// covers, emoji included, still take up their cells.
+pub const BUILTIN_FONT: &str = "builtin";
+
+/// One font in a fallback chain
+#[derive(Clone)]
+pub enum ChainFont {
+    /// A font installed on the system
+    Installed { family: String, font: Arc<FontVec> },
+    /// KLA's builtin glyphs
+    Builtin,
+}
+
+impl ChainFont {
+    pub fn name(&self) -> &str {
+        match self {
+            ChainFont::Installed { family, .. } => family,
+            ChainFont::Builtin => BUILTIN_FONT,
+        }
+    }
+
+    pub fn has_glyph(&self, ch: char) -> bool {
+        match self {
+            ChainFont::Installed { font, .. } => font.glyph_id(ch).0 != 0,
+            ChainFont::Builtin => true,
+        }
+    }
+}
+
+/// Fonts tried in order for each character, ending with the builtin glyphs
+#[derive(Clone)]
+pub struct FontChain {
+    fonts: Vec<ChainFont>,
+}
+
+impl FontChain {
+    /// Resolve each family to an installed font, skipping any that are missing
+    ///
+    /// `monospace` names the system's default monospace font.
+    pub fn new<S: AsRef<str>>(families: &[S]) -> Self {
+        let mut fonts = Vec::new();
+        for family in families {
+            let family = family.as_ref();
+            // Nothing after the builtin glyphs would ever be used
+            if family.eq_ignore_ascii_case(BUILTIN_FONT) {
+                break;
+            }
+            if let Some(font) = installed_font(family) {
+                fonts.push(ChainFont::Installed { family: family.to_string(), font });
+            }
+        }
+        fonts.push(ChainFont::Builtin);
+        Self { fonts }
+    }
+
+    /// The first font in the chain with a glyph for `ch`
+    pub fn font_for(&self, ch: char) -> &ChainFont {
+        self.fonts
+            .iter()
+            .find(|font| font.has_glyph(ch))
+            .unwrap_or(&ChainFont::Builtin)
+    }
+
+    /// Names of the fonts that were found, in fallback order
+    pub fn names(&self) -> Vec<&str> {
+        self.fonts.iter().map(ChainFont::name).collect()
+    }
+}
+
+/// Rasterize `ch` from `font` into a cell whose top-left corner is `(x, y)`
+///
+/// The glyph is sized to `px` and centered vertically in the cell. `plot`
+/// receives each covered pixel with its coverage from 0 to 1.
+pub fn rasterize(font: &FontVec, ch: char, px: f32, x: u32, y: u32, cell_height: u32, mut plot: impl FnMut(u32, u32, f32)) {
+    let scaled = font.as_scaled(PxScale::from(px));
+    let line_height = scaled.ascent() - scaled.descent();
+    let baseline = y as f32 + (cell_height as f32 - line_height) / 2.0 + scaled.ascent();
+    let glyph = font
+        .glyph_id(ch)
+        .with_scale_and_position(px, ab_glyph::point(x as f32, baseline));
+
+    if let Some(outlined) = font.outline_glyph(glyph) {
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let (px, py) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+            if px >= 0 && py >= 0 && coverage > 0.0 {
+                plot(px as u32, py as u32, coverage);
+            }
+        });
+    }
+}
+
+/// Load an installed font by family name, once per process
+fn installed_font(family: &str) -> Option<Arc<FontVec>> {
+    static LOADED: OnceLock<Mutex<HashMap<String, Option<Arc<FontVec>>>>> = OnceLock::new();
+    let loaded = LOADED.get_or_init(Default::default);
+    if let Some(font) = loaded.lock().ok()?.get(family) {
+        return font.clone();
+    }
+
+    let font = load_font(family);
+    if font.is_none() {
+        log::debug!("Font '{}' is not installed; skipping it", family);
+    }
+    loaded.lock().ok()?.insert(family.to_string(), font.clone());
+    font
+}
+
+fn load_font(family: &str) -> Option<Arc<FontVec>> {
+    static DATABASE: OnceLock<fontdb::Database> = OnceLock::new();
+    let database = DATABASE.get_or_init(|| {
+        let mut database = fontdb::Database::new();
+        database.load_system_fonts();
+        database
+    });
+
+    let family = if family.eq_ignore_ascii_case("monospace") {
+        fontdb::Family::Monospace
+    } else {
+        fontdb::Family::Name(family)
+    };
+    let id = database.query(&fontdb::Query { families: &[family], ..Default::default() })?;
+    database
+        .with_face_data(id, |data, index| FontVec::try_from_vec_and_index(data.to_vec(), index).ok())
+        .flatten()
+        .map(Arc::new)
+}
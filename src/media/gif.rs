@@ -1,88 +1,84 @@
 use anyhow::{Context, Result};
-use gif::{Encoder, Frame, Repeat};
-use image::{ImageBuffer, Rgb};
-use std::fs::File;
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use image::{imageops, RgbImage};
+use std::collections::HashMap;
 use std::path::Path;
 
-use super::{MediaConfig, ThemeConfig};
+use super::{Caption, MediaConfig, ThemeConfig};
 use super::screenshot::ScreenshotGenerator;
+use crate::terminal::TerminalState;
 
-pub struct GifGenerator {
-    encoder: Encoder<File>,
-    screenshot_gen: ScreenshotGenerator,
-    width: u16,
-    height: u16,
-    frame_delay: u16, // in centiseconds (1/100th of a second)
+/// A rendered GIF frame and how long it stays on screen
+#[derive(Clone)]
+pub struct GifFrame {
+    pub image: RgbImage,
+    pub delay: u16, // in centiseconds
 }
 
-impl GifGenerator {
-    pub fn new(
-        config: &MediaConfig,
-        theme: &ThemeConfig,
-        terminal_width: u16,
-        terminal_height: u16,
-    ) -> Result<Self> {
-        // We'll create the encoder later when we know the output path
-        // For now, create a temporary file
-        let temp_file = tempfile::NamedTempFile::new()
-            .context("Failed to create temporary file for GIF")?;
-        
-        let file = temp_file.into_file();
-        let mut encoder = Encoder::new(file, terminal_width, terminal_height, &[])?;
-        encoder.set_repeat(Repeat::Infinite)?;
-        
-        Ok(Self {
-            encoder,
-            screenshot_gen: ScreenshotGenerator::new(config, theme),
-            width: terminal_width,
-            height: terminal_height,
-            frame_delay: 50, // 0.5 seconds default
-        })
-    }
-    
-    pub fn with_frame_delay(mut self, delay_centiseconds: u16) -> Self {
-        self.frame_delay = delay_centiseconds;
-        self
-    }
-    
-    pub fn add_frame(&mut self, content: &str, terminal_width: u16, terminal_height: u16) -> Result<()> {
-        // Generate a frame image
-        let temp_image_file = tempfile::NamedTempFile::with_suffix(".png")?;
-        self.screenshot_gen.generate(content, terminal_width, terminal_height, temp_image_file.path())?;
-        
-        // Load the image and convert to GIF frame
-        let image = image::open(temp_image_file.path())
-            .context("Failed to load generated screenshot")?;
-        
-        let rgb_image = image.to_rgb8();
-        let (width, height) = rgb_image.dimensions();
-        
-        // Convert to GIF frame format
-        let mut frame = Frame::from_rgb(width as u16, height as u16, &rgb_image);
-        frame.delay = self.frame_delay;
-        
-        self.encoder.write_frame(&frame)
-            .context("Failed to write GIF frame")?;
-        
-        Ok(())
-    }
-    
-    pub fn save(self, output_path: &Path) -> Result<()> {
-        // The encoder automatically finalizes when dropped
-        // We need to move the temporary file to the desired location
-        // This is a simplified approach - in practice, you'd handle this better
-        
-        log::info!("GIF saved to: {}", output_path.display());
-        Ok(())
+/// How long each frame is shown, in centiseconds, with `end_pause` added to the last
+pub fn frame_delays(frames: &[GifFrame], config: &MediaConfig) -> Vec<u16> {
+    let mut delays: Vec<u16> = frames.iter().map(|frame| frame.delay).collect();
+    if let (Some(last), Some(pause)) = (delays.last_mut(), config.end_pause) {
+        let pause = (pause.as_millis() / 10).min(u16::MAX as u128) as u16;
+        *last = last.saturating_add(pause);
     }
+    delays
+}
+
+/// How long the cursor stays on, then off, with `animate_idle`, in centiseconds
+const IDLE_BLINK_CENTISECONDS: u32 = 53;
+
+/// Longest a visual bell's inverted frame is shown, in centiseconds
+const VISUAL_BELL_CENTISECONDS: u16 = 10;
+
+/// Palette sizes tried, largest first, when fitting a GIF into `max_gif_bytes`
+const BUDGET_PALETTE_SIZES: &[usize] = &[256, 128, 64, 32, 16];
+
+/// Frame scales tried, each with every palette size, when fitting a size budget
+const BUDGET_SCALES: &[f32] = &[1.0, 0.75, 0.5];
+
+/// Parameters a GIF was encoded with, and the resulting file size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GifEncoding {
+    /// Most colors any frame's palette may hold
+    pub palette_size: usize,
+    /// Frame size relative to the captured frames
+    pub scale: f32,
+    pub bytes: usize,
+}
+
+/// An idle-blink capture, kept so it can be laid out again for another delay
+struct BlinkCapture {
+    /// How many frames there were before the capture, and the last one's delay
+    frames_len: usize,
+    last_delay: Option<u16>,
+    /// How long the screen had been idle when the capture started
+    idle: u32,
+    shown: RgbImage,
+    hidden: RgbImage,
 }
 
 pub struct GifRecorder {
-    frames: Vec<Vec<u8>>,
+    frames: Vec<GifFrame>,
     width: u16,
     height: u16,
+    frame_delay: u16, // in centiseconds
     config: MediaConfig,
     theme: ThemeConfig,
+    /// Whether the next captured state shows its cursor, when blinking
+    cursor_phase: bool,
+    /// Drawn over every frame captured while set
+    caption: Option<Caption>,
+    /// Keys shown in a badge on every frame captured while set
+    keystrokes: Option<String>,
+    /// With `animate_idle`, the last screen captured and how long it has been shown
+    idle: Option<(RgbImage, u32)>,
+    /// The most recent capture, if it was an idle blink
+    last_blink: Option<BlinkCapture>,
+    /// Delay of the most recent capture, so trimming can hold the final screen once
+    last_delay: u16,
+    /// The last state rendered and its render without caption, to redraw only what changed
+    last_render: Option<(TerminalState, RgbImage)>,
 }
 
 impl GifRecorder {
@@ -91,51 +87,328 @@ impl GifRecorder {
             frames: Vec::new(),
             width,
             height,
+            frame_delay: 50, // 0.5 seconds default
             config: config.clone(),
             theme: theme.clone(),
+            cursor_phase: true,
+            caption: None,
+            keystrokes: None,
+            idle: None,
+            last_blink: None,
+            last_delay: 0,
+            last_render: None,
         }
     }
     
+    /// Overlay `caption` on subsequent captures, or stop overlaying with `None`
+    pub fn set_caption(&mut self, caption: Option<Caption>) {
+        self.caption = caption;
+    }
+    
+    /// Show `keys` in a badge on subsequent captures, or stop showing them with `None`
+    pub fn set_keystrokes(&mut self, keys: Option<String>) {
+        self.keystrokes = keys;
+    }
+    
+    /// Draw the caption and keystroke badge, whichever are set
+    fn draw_overlays(&self, screenshot_gen: &ScreenshotGenerator, image: &mut RgbImage) {
+        if let Some(caption) = &self.caption {
+            screenshot_gen.draw_caption(image, caption);
+        }
+        if let Some(keys) = &self.keystrokes {
+            screenshot_gen.draw_keystrokes(image, keys);
+        }
+    }
+    
+    pub fn with_frame_delay(mut self, delay_centiseconds: u16) -> Self {
+        self.frame_delay = delay_centiseconds;
+        self
+    }
+    
     pub fn capture_frame(&mut self, content: &str) -> Result<()> {
-        // Generate screenshot data
-        let temp_file = tempfile::NamedTempFile::with_suffix(".png")?;
+        self.capture_frame_with_delay(content, self.frame_delay)
+    }
+    
+    /// Capture a frame shown for `delay` centiseconds
+    ///
+    /// If the rendered pixels match the previous frame, the previous frame's
+    /// delay is extended instead of storing a duplicate.
+    pub fn capture_frame_with_delay(&mut self, content: &str, delay: u16) -> Result<()> {
         let screenshot_gen = ScreenshotGenerator::new(&self.config, &self.theme);
-        screenshot_gen.generate(content, self.width, self.height, temp_file.path())?;
+        let image = screenshot_gen.render(content, self.width, self.height)?;
+        self.last_blink = None;
+        self.push_frame(image, delay);
+        Ok(())
+    }
+    
+    pub fn capture_state(&mut self, state: &TerminalState) -> Result<()> {
+        self.capture_state_with_delay(state, self.frame_delay)
+    }
+    
+    /// Capture a terminal snapshot, cursor included, shown for `delay` centiseconds
+    ///
+    /// With `cursor_blink` enabled the cursor is hidden on every other capture.
+    /// With `animate_idle` it blinks in real time instead, while the screen is unchanged.
+    pub fn capture_state_with_delay(&mut self, state: &TerminalState, delay: u16) -> Result<()> {
+        let screenshot_gen = ScreenshotGenerator::new(&self.config, &self.theme);
+        if self.config.animate_idle && state.cursor_visible {
+            return self.capture_idle_blink(&screenshot_gen, state, delay);
+        }
+        self.last_blink = None;
         
-        // Read the image data
-        let image_data = std::fs::read(temp_file.path())
-            .context("Failed to read screenshot data")?;
+        let mut shown = state.clone();
+        if self.config.cursor_blink && !self.cursor_phase {
+            shown.cursor_visible = false;
+        }
+        let mut image = self.render_incremental(&screenshot_gen, shown)?;
+        if self.config.cursor_blink {
+            self.cursor_phase = !self.cursor_phase;
+        }
+        self.draw_overlays(&screenshot_gen, &mut image);
         
-        self.frames.push(image_data);
+        self.push_frame(image, delay);
         Ok(())
     }
     
-    pub fn save_gif(&self, output_path: &Path, frame_delay: u16) -> Result<()> {
+    /// Render `state`, redrawing only the rows changed since the last render
+    fn render_incremental(&mut self, screenshot_gen: &ScreenshotGenerator, state: TerminalState) -> Result<RgbImage> {
+        let image = match self.last_render.take() {
+            Some((previous, mut image)) => {
+                screenshot_gen.update_render(&mut image, &previous, &state)?;
+                image
+            }
+            None => screenshot_gen.render_state(&state)?,
+        };
+        self.last_render = Some((state, image.clone()));
+        Ok(image)
+    }
+    
+    /// Capture an already rendered frame shown for `delay` centiseconds
+    pub fn capture_image(&mut self, image: RgbImage, delay: u16) {
+        self.last_blink = None;
+        self.push_frame(image, delay);
+    }
+    
+    /// Capture `state` inverted, as a visual bell, for part of `delay`
+    ///
+    /// Returns how many centiseconds the flash takes, for the caller to take
+    /// off the normal frame that follows it.
+    pub fn capture_visual_bell(&mut self, state: &TerminalState, delay: u16) -> Result<u16> {
+        let screenshot_gen = ScreenshotGenerator::new(&self.config, &self.theme);
+        let mut image = screenshot_gen.render_state(state)?;
+        self.draw_overlays(&screenshot_gen, &mut image);
+        imageops::invert(&mut image);
+        
+        let flash = VISUAL_BELL_CENTISECONDS.min(delay / 2).max(1);
+        self.last_blink = None;
+        self.push_frame(image, flash);
+        Ok(flash)
+    }
+    
+    /// Capture `state` for `delay`, split into cursor-on and cursor-off frames
+    ///
+    /// The blink restarts, cursor on, whenever the screen changes.
+    fn capture_idle_blink(&mut self, screenshot_gen: &ScreenshotGenerator, state: &TerminalState, delay: u16) -> Result<()> {
+        let mut hidden = state.clone();
+        hidden.cursor_visible = false;
+        let mut shown = screenshot_gen.render_state(state)?;
+        let mut hidden = screenshot_gen.render_state(&hidden)?;
+        self.draw_overlays(screenshot_gen, &mut shown);
+        self.draw_overlays(screenshot_gen, &mut hidden);
+        
+        let idle = match self.idle.take() {
+            Some((last, idle)) if last == shown => idle,
+            _ => 0,
+        };
+        let blink = BlinkCapture {
+            frames_len: self.frames.len(),
+            last_delay: self.frames.last().map(|frame| frame.delay),
+            idle,
+            shown,
+            hidden,
+        };
+        self.push_blink(blink, delay);
+        Ok(())
+    }
+    
+    /// Lay out `blink`'s cursor-on and cursor-off spans over `delay`
+    fn push_blink(&mut self, blink: BlinkCapture, delay: u16) {
+        let mut idle = blink.idle;
+        let mut remaining = u32::from(delay);
+        while remaining > 0 {
+            let span = remaining.min(IDLE_BLINK_CENTISECONDS - idle % IDLE_BLINK_CENTISECONDS);
+            let image = if (idle / IDLE_BLINK_CENTISECONDS).is_multiple_of(2) { &blink.shown } else { &blink.hidden };
+            self.push_frame(image.clone(), span as u16);
+            idle += span;
+            remaining -= span;
+        }
+        self.idle = Some((blink.shown.clone(), idle));
+        self.last_blink = Some(blink);
+    }
+    
+    /// Show the most recent capture for `delay` centiseconds instead of the delay
+    /// it was captured with, once it is known how long its screen really stayed up
+    ///
+    /// An idle blink is laid out again, so the cursor keeps blinking in rhythm.
+    pub fn retime_last_capture(&mut self, delay: u16) {
+        if let Some(blink) = self.last_blink.take() {
+            self.frames.truncate(blink.frames_len);
+            if let (Some(last), Some(before)) = (self.frames.last_mut(), blink.last_delay) {
+                last.delay = before;
+            }
+            self.push_blink(blink, delay.max(1));
+            return;
+        }
+        if let Some(last) = self.frames.last_mut() {
+            last.delay = last.delay.saturating_sub(self.last_delay).saturating_add(delay).max(1);
+            self.last_delay = delay;
+        }
+    }
+    
+    fn push_frame(&mut self, image: RgbImage, delay: u16) {
+        self.last_delay = delay;
+        if let Some(last) = self.frames.last_mut() {
+            if last.image == image {
+                last.delay = last.delay.saturating_add(delay);
+                return;
+            }
+        }
+        self.frames.push(GifFrame { image, delay });
+    }
+    
+    /// Write the frames as a GIF that loops `loop_count` times, returning how it was encoded
+    ///
+    /// With `max_gif_bytes` set, smaller palettes and then smaller frames are
+    /// tried until the file fits. If nothing fits, the smallest attempt is
+    /// written and a warning is logged.
+    pub fn save_gif(&self, output_path: &Path) -> Result<GifEncoding> {
+        let (data, encoding) = self.encode()?;
+        std::fs::write(output_path, &data)
+            .with_context(|| format!("Failed to write GIF file: {}", output_path.display()))?;
+        
+        log::info!(
+            "GIF saved to: {} ({} bytes, {} colors, scale {})",
+            output_path.display(),
+            encoding.bytes,
+            encoding.palette_size,
+            encoding.scale
+        );
+        Ok(encoding)
+    }
+    
+    /// Encode the frames in memory, honoring `max_gif_bytes` if set
+    pub fn encode(&self) -> Result<(Vec<u8>, GifEncoding)> {
+        let Some(budget) = self.config.max_gif_bytes else {
+            let data = self.encode_with(256, 1.0)?;
+            let encoding = GifEncoding { palette_size: 256, scale: 1.0, bytes: data.len() };
+            return Ok((data, encoding));
+        };
+        
+        let mut smallest: Option<(Vec<u8>, GifEncoding)> = None;
+        for &scale in BUDGET_SCALES {
+            for &palette_size in BUDGET_PALETTE_SIZES {
+                let data = self.encode_with(palette_size, scale)?;
+                let encoding = GifEncoding { palette_size, scale, bytes: data.len() };
+                if data.len() <= budget {
+                    return Ok((data, encoding));
+                }
+                if smallest.as_ref().is_none_or(|(best, _)| data.len() < best.len()) {
+                    smallest = Some((data, encoding));
+                }
+            }
+        }
+        
+        let (data, encoding) = smallest.expect("at least one encoding is attempted");
+        log::warn!(
+            "GIF is {} bytes, over the {} byte budget even with {} colors at scale {}",
+            encoding.bytes,
+            budget,
+            encoding.palette_size,
+            encoding.scale
+        );
+        Ok((data, encoding))
+    }
+    
+    fn encode_with(&self, palette_size: usize, scale: f32) -> Result<Vec<u8>> {
         if self.frames.is_empty() {
             return Err(anyhow::anyhow!("No frames to save"));
         }
         
-        let file = File::create(output_path)
-            .with_context(|| format!("Failed to create GIF file: {}", output_path.display()))?;
+        // The GIF canvas is sized from the rendered frames, not the terminal grid
+        let (width, height) = self.frames[0].image.dimensions();
+        let width = ((width as f32 * scale).round() as u32).max(1);
+        let height = ((height as f32 * scale).round() as u32).max(1);
         
-        let mut encoder = Encoder::new(file, self.width, self.height, &[])?;
-        encoder.set_repeat(Repeat::Infinite)?;
-        
-        for frame_data in &self.frames {
-            // Convert PNG data back to raw pixels (simplified)
-            // In practice, you'd want to maintain raw pixel data
-            let image = image::load_from_memory(frame_data)
-                .context("Failed to decode frame image")?;
-            
-            let rgb_image = image.to_rgb8();
-            let mut frame = Frame::from_rgb(self.width, self.height, &rgb_image);
-            frame.delay = frame_delay;
+        let mut data = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut data, width as u16, height as u16, &[])?;
+            // The NETSCAPE extension counts repeats after the first play, and
+            // a GIF without one plays once
+            match self.config.loop_count {
+                None | Some(0) => encoder.set_repeat(Repeat::Infinite)?,
+                Some(1) => {}
+                Some(plays) => encoder.set_repeat(Repeat::Finite(plays - 1))?,
+            }
             
-            encoder.write_frame(&frame)
-                .context("Failed to write GIF frame")?;
+            // After the first frame, only the rectangle that changed is stored,
+            // drawn over the frames before it
+            let mut previous: Option<RgbImage> = None;
+            for (gif_frame, delay) in self.frames.iter().zip(frame_delays(&self.frames, &self.config)) {
+                let image = if scale == 1.0 {
+                    gif_frame.image.clone()
+                } else {
+                    imageops::resize(&gif_frame.image, width, height, imageops::FilterType::Triangle)
+                };
+                let (left, top, patch) = match &previous {
+                    Some(previous) => changed_patch(previous, &image),
+                    None => (0, 0, image.clone()),
+                };
+                let mut frame = quantized_frame(&patch, palette_size);
+                frame.left = left;
+                frame.top = top;
+                frame.dispose = DisposalMethod::Keep;
+                frame.delay = delay;
+                
+                encoder.write_frame(&frame)
+                    .context("Failed to write GIF frame")?;
+                previous = Some(image);
+            }
+        }
+        Ok(data)
+    }
+    
+    pub fn frames(&self) -> &[GifFrame] {
+        &self.frames
+    }
+    
+    /// Apply `processor` to every captured frame
+    pub fn process_frames(&mut self, processor: &mut dyn FnMut(&mut RgbImage)) {
+        for frame in &mut self.frames {
+            processor(&mut frame.image);
+        }
+    }
+    
+    /// Drop frames showing the starting screen from both ends, and cut the
+    /// final screen down to one capture
+    ///
+    /// The first frame is the screen shown before anything changed. Repeated
+    /// captures are already merged into one frame, so the last one carries
+    /// all the idle time after the final change. At least one frame is kept.
+    pub fn trim_blank_edges(&mut self) {
+        let blank = self.frames.first().map(|frame| frame.image.clone());
+        let is_blank = |frame: &GifFrame| Some(&frame.image) == blank.as_ref();
+        let leading = self.frames.iter().take_while(|frame| is_blank(frame)).count();
+        if leading == self.frames.len() {
+            self.frames.truncate(1);
+        } else {
+            self.frames.drain(..leading);
+            while self.frames.last().is_some_and(is_blank) {
+                self.frames.pop();
+            }
+        }
+        if let Some(last) = self.frames.last_mut() {
+            last.delay = last.delay.min(self.last_delay.max(1));
         }
-        
-        Ok(())
     }
     
     pub fn frame_count(&self) -> usize {
@@ -147,14 +420,68 @@ impl GifRecorder {
     }
 }
 
+/// Build a GIF frame whose palette holds at most `palette_size` colors
+///
+/// Frames that already use few enough colors keep them exactly; others are
+/// reduced with NeuQuant.
+fn quantized_frame(image: &RgbImage, palette_size: usize) -> Frame<'static> {
+    let (width, height) = (image.width() as u16, image.height() as u16);
+    if palette_size >= 256 {
+        return Frame::from_rgb(width, height, image);
+    }
+    
+    let mut exact: HashMap<[u8; 3], u8> = HashMap::new();
+    for pixel in image.pixels() {
+        if exact.len() > palette_size {
+            break;
+        }
+        let next = exact.len() as u8;
+        exact.entry(pixel.0).or_insert(next);
+    }
+    if exact.len() <= palette_size {
+        let mut palette = vec![0; exact.len() * 3];
+        for (color, &index) in &exact {
+            palette[index as usize * 3..index as usize * 3 + 3].copy_from_slice(color);
+        }
+        let pixels: Vec<u8> = image.pixels().map(|pixel| exact[&pixel.0]).collect();
+        return Frame::from_palette_pixels(width, height, &pixels, &palette, None);
+    }
+    
+    let rgba: Vec<u8> = image.pixels().flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect();
+    let quantizer = color_quant::NeuQuant::new(10, palette_size, &rgba);
+    let pixels: Vec<u8> = rgba.chunks_exact(4).map(|pixel| quantizer.index_of(pixel) as u8).collect();
+    Frame::from_palette_pixels(width, height, &pixels, &quantizer.color_map_rgb(), None)
+}
+
+/// The smallest rectangle of `image` that differs from `previous`, with its offset
+///
+/// Frames with no change still need a pixel to carry their delay.
+fn changed_patch(previous: &RgbImage, image: &RgbImage) -> (u16, u16, RgbImage) {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if previous.get_pixel(x, y) != pixel {
+            let (left, top, right, bottom) = bounds.get_or_insert((x, y, x, y));
+            *left = (*left).min(x);
+            *top = (*top).min(y);
+            *right = (*right).max(x);
+            *bottom = (*bottom).max(y);
+        }
+    }
+    let (left, top, right, bottom) = bounds.unwrap_or((0, 0, 0, 0));
+    let patch = imageops::crop_imm(image, left, top, right - left + 1, bottom - top + 1).to_image();
+    (left as u16, top as u16, patch)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::File;
+    use std::time::Duration;
     use tempfile::NamedTempFile;
     
     #[test]
     fn test_gif_recorder() {
-        let config = MediaConfig::default();
+        let config = MediaConfig::builtin_font().build();
         let theme = ThemeConfig::default_theme();
         let mut recorder = GifRecorder::new(&config, &theme, 80, 24);
         
@@ -164,8 +491,194 @@ mod tests {
         assert_eq!(recorder.frame_count(), 2);
         
         let temp_file = NamedTempFile::with_suffix(".gif").unwrap();
-        recorder.save_gif(temp_file.path(), 50).unwrap();
+        recorder.save_gif(temp_file.path()).unwrap();
         
         assert!(temp_file.path().exists());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_loop_count_sets_netscape_extension() {
+        let netscape_repeats = |config: &MediaConfig| {
+            let mut recorder = GifRecorder::new(config, &ThemeConfig::default_theme(), 10, 2);
+            recorder.capture_frame("loop").unwrap();
+            let (data, _) = recorder.encode().unwrap();
+            data.windows(16)
+                .find(|block| &block[..11] == b"NETSCAPE2.0")
+                .map(|block| u16::from_le_bytes([block[13], block[14]]))
+        };
+        
+        assert_eq!(netscape_repeats(&MediaConfig::builtin_font().build()), Some(0));
+        assert_eq!(netscape_repeats(&MediaConfig { loop_count: Some(2), ..MediaConfig::builtin_font().build() }), Some(1));
+        assert_eq!(netscape_repeats(&MediaConfig { loop_count: Some(1), ..MediaConfig::builtin_font().build() }), None);
+    }
+    
+    #[test]
+    fn test_end_pause_holds_last_frame() {
+        let config = MediaConfig { end_pause: Some(Duration::from_secs(2)), ..MediaConfig::builtin_font().build() };
+        let mut recorder = GifRecorder::new(&config, &ThemeConfig::default_theme(), 10, 2).with_frame_delay(10);
+        recorder.capture_frame("one").unwrap();
+        recorder.capture_frame("two").unwrap();
+        
+        let (data, _) = recorder.encode().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(data.as_slice()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![10, 210]);
+    }
+    
+    #[test]
+    fn test_trim_blank_edges_keeps_content_frames() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = GifRecorder::new(&config, &theme, 40, 10).with_frame_delay(30);
+        
+        for _ in 0..3 {
+            recorder.capture_frame("").unwrap();
+        }
+        recorder.capture_frame("$ make").unwrap();
+        for _ in 0..3 {
+            recorder.capture_frame("$ make\nbuilding...").unwrap();
+        }
+        recorder.trim_blank_edges();
+        
+        let delays: Vec<u16> = recorder.frames().iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![30, 30]);
+        
+        // A screen cleared back to the start is trimmed from the end too
+        let mut cleared = GifRecorder::new(&config, &theme, 40, 10).with_frame_delay(30);
+        for content in ["", "$ make", "", ""] {
+            cleared.capture_frame(content).unwrap();
+        }
+        cleared.trim_blank_edges();
+        assert_eq!(cleared.frame_count(), 1);
+        assert_eq!(cleared.frames()[0].delay, 30);
+        
+        let mut lone = GifRecorder::new(&config, &theme, 40, 10).with_frame_delay(30);
+        lone.capture_frame("").unwrap();
+        lone.capture_frame("").unwrap();
+        lone.trim_blank_edges();
+        assert_eq!(lone.frame_count(), 1);
+    }
+    
+    #[test]
+    fn test_identical_frames_are_merged() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = GifRecorder::new(&config, &theme, 40, 10).with_frame_delay(30);
+        
+        recorder.capture_frame("$ make").unwrap();
+        recorder.capture_frame("$ make\nbuilding...").unwrap();
+        recorder.capture_frame("$ make\nbuilding...").unwrap();
+        
+        assert_eq!(recorder.frame_count(), 2);
+        assert_eq!(recorder.frames()[0].delay, 30);
+        assert_eq!(recorder.frames()[1].delay, 60);
+        
+        let temp_file = NamedTempFile::with_suffix(".gif").unwrap();
+        recorder.save_gif(temp_file.path()).unwrap();
+        
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(temp_file.path()).unwrap())
+            .unwrap();
+        let mut delays = Vec::new();
+        let mut widths = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+            widths.push(frame.width);
+        }
+        assert_eq!(delays, vec![30, 60]);
+        // The second frame only stores the line that changed
+        let (canvas_width, _) = recorder.frames()[0].image.dimensions();
+        assert_eq!(widths[0] as u32, canvas_width);
+        assert!((widths[1] as u32) < canvas_width);
+    }
+    
+    #[test]
+    fn test_size_budget_reduces_palette() {
+        // A smooth gradient uses far more than 256 colors, so palette size matters
+        let gradient = |offset: u32| {
+            RgbImage::from_fn(96, 96, |x, y| {
+                image::Rgb([(x * 2 + offset) as u8, (y * 2) as u8, ((x + y + offset) % 256) as u8])
+            })
+        };
+        let theme = ThemeConfig::default_theme();
+        let mut unlimited = GifRecorder::new(&MediaConfig::builtin_font().build(), &theme, 10, 2);
+        for offset in [0, 40] {
+            unlimited.push_frame(gradient(offset), 50);
+        }
+        let (full, full_encoding) = unlimited.encode().unwrap();
+        assert_eq!(full_encoding.palette_size, 256);
+        
+        let budget = full.len() * 3 / 4;
+        let config = MediaConfig { max_gif_bytes: Some(budget), ..MediaConfig::builtin_font().build() };
+        let mut limited = GifRecorder::new(&config, &theme, 10, 2);
+        limited.frames = unlimited.frames.clone();
+        
+        let temp_file = NamedTempFile::with_suffix(".gif").unwrap();
+        let encoding = limited.save_gif(temp_file.path()).unwrap();
+        assert!(encoding.palette_size < 256, "{:?}", encoding);
+        assert!(encoding.bytes <= budget, "{:?}", encoding);
+        assert_eq!(std::fs::metadata(temp_file.path()).unwrap().len() as usize, encoding.bytes);
+        
+        // The result still decodes as a two-frame animation
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(temp_file.path()).unwrap())
+            .unwrap();
+        let mut frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+    
+    #[test]
+    fn test_idle_screen_blinks_cursor() {
+        let config = MediaConfig::builtin_font().animate_idle(true).build();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = GifRecorder::new(&config, &theme, 10, 2);
+        let state = crate::media::screenshot::text_state("$ ", 10, 2);
+        
+        // Two idle seconds sampled at 10fps
+        for _ in 0..20 {
+            recorder.capture_state_with_delay(&state, 10).unwrap();
+        }
+        
+        let cursor = image::Rgb([theme.cursor.0, theme.cursor.1, theme.cursor.2]);
+        let frames: Vec<(bool, u16)> = recorder
+            .frames()
+            .iter()
+            .map(|frame| (frame.image.pixels().any(|pixel| *pixel == cursor), frame.delay))
+            .collect();
+        assert_eq!(frames, vec![(true, 53), (false, 53), (true, 53), (false, 41)]);
+        
+        // Retiming a capture keeps the blink rhythm instead of stretching one frame
+        let mut retimed = GifRecorder::new(&config, &theme, 10, 2);
+        retimed.capture_state_with_delay(&state, 10).unwrap();
+        retimed.retime_last_capture(120);
+        retimed.capture_state_with_delay(&state, 10).unwrap();
+        let delays: Vec<u16> = retimed.frames().iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![53, 53, 24]);
+    }
+    
+    #[test]
+    fn test_blinking_cursor_alternates() {
+        let config = MediaConfig::builtin_font().cursor_blink(true).build();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = GifRecorder::new(&config, &theme, 10, 2);
+        let state = crate::media::screenshot::text_state("$ ", 10, 2);
+        
+        for _ in 0..3 {
+            recorder.capture_state(&state).unwrap();
+        }
+        
+        let cursor = image::Rgb([theme.cursor.0, theme.cursor.1, theme.cursor.2]);
+        let shows_cursor: Vec<bool> = recorder
+            .frames()
+            .iter()
+            .map(|frame| frame.image.pixels().any(|pixel| *pixel == cursor))
+            .collect();
+        assert_eq!(shows_cursor, vec![true, false, true]);
+    }
+}
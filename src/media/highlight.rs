@@ -0,0 +1,89 @@
+use anyhow::Result;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+use crate::terminal::TerminalState;
+
+/// Highlight theme applied to code, from syntect's bundled set
+const HIGHLIGHT_THEME: &str = "base16-ocean.dark";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn highlight_theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        themes.themes.remove(HIGHLIGHT_THEME).unwrap_or_default()
+    })
+}
+
+/// Token color of each screen cell, indexed `[row][column]`
+pub type CellColors = Vec<Vec<Option<(u8, u8, u8)>>>;
+
+/// Colors screen text as source code in a given language
+pub struct SyntaxHighlighter {
+    syntax: &'static SyntaxReference,
+}
+
+impl SyntaxHighlighter {
+    /// Look up a language by name or file extension, e.g. "Rust" or "rs"
+    pub fn new(language: &str) -> Result<Self> {
+        let syntaxes = syntax_set();
+        let syntax = syntaxes
+            .find_syntax_by_token(language)
+            .ok_or_else(|| anyhow::anyhow!("Unknown syntax highlighting language: {}", language))?;
+        Ok(Self { syntax })
+    }
+    
+    /// Token color for every cell of the screen
+    ///
+    /// Lines are highlighted top to bottom so multi-line constructs carry over.
+    /// Wide-character continuation cells get no color of their own.
+    pub fn cell_colors(&self, state: &TerminalState) -> Result<CellColors> {
+        let mut highlighter = HighlightLines::new(self.syntax, highlight_theme());
+        let mut colors = Vec::with_capacity(state.buffer.len());
+        
+        for row in &state.buffer {
+            let columns: Vec<usize> = (0..row.len()).filter(|&x| !row[x].continuation).collect();
+            let mut line: String = columns.iter().map(|&x| row[x].ch).collect();
+            line.push('\n');
+            
+            let mut row_colors = vec![None; row.len()];
+            let mut column = columns.iter();
+            for (style, text) in highlighter.highlight_line(&line, syntax_set())? {
+                let color = (style.foreground.r, style.foreground.g, style.foreground.b);
+                for _ in text.chars() {
+                    if let Some(&x) = column.next() {
+                        row_colors[x] = Some(color);
+                    }
+                }
+            }
+            colors.push(row_colors);
+        }
+        
+        Ok(colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::screenshot::text_state;
+    
+    #[test]
+    fn test_keyword_and_string_colors_differ() {
+        let state = text_state("let s = \"hi\";", 20, 1);
+        let colors = SyntaxHighlighter::new("rust").unwrap().cell_colors(&state).unwrap();
+        
+        let keyword = colors[0][0].unwrap();
+        assert_eq!(colors[0][1], Some(keyword));
+        assert_ne!(colors[0][9].unwrap(), keyword);
+        
+        assert!(SyntaxHighlighter::new("no-such-language").is_err());
+    }
+}
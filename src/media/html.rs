@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+use super::fonts::BUILTIN_FONT;
+use super::svg::{escape_xml, hex_color};
+use super::{MediaConfig, ThemeConfig};
+use crate::terminal::{CharAttributes, TerminalState};
+
+/// Records terminal screens and renders them as a self-contained HTML page
+///
+/// Each frame is a `<pre>` of styled spans, so the text stays selectable.
+/// Recordings with more than one frame get a small script that plays them.
+pub struct HtmlRecorder {
+    frames: Vec<TerminalState>,
+    config: MediaConfig,
+    theme: ThemeConfig,
+}
+
+impl HtmlRecorder {
+    pub fn new(config: &MediaConfig, theme: &ThemeConfig) -> Self {
+        Self {
+            frames: Vec::new(),
+            config: config.clone(),
+            theme: theme.clone(),
+        }
+    }
+
+    pub fn capture_state(&mut self, state: &TerminalState) {
+        self.frames.push(state.clone());
+    }
+
+    pub fn render(&self, frame_delay: Duration) -> Result<String> {
+        if self.frames.is_empty() {
+            return Err(anyhow::anyhow!("No frames to save"));
+        }
+
+        let padding = self.config.padding_sides();
+        let mut html = String::new();
+        writeln!(html, "<!DOCTYPE html>")?;
+        writeln!(html, "<html>")?;
+        writeln!(html, "<head>")?;
+        writeln!(html, r#"<meta charset="utf-8">"#)?;
+        writeln!(html, "<title>{}</title>", escape_xml(&self.frames[0].title))?;
+        writeln!(html, "<style>")?;
+        write!(
+            html,
+            ":root {{ --kla-background: {}; --kla-foreground: {}; --kla-cursor: {};",
+            hex_color(self.theme.background),
+            hex_color(self.theme.foreground),
+            hex_color(self.theme.cursor),
+        )?;
+        for index in 0..16u8 {
+            write!(html, " --kla-color-{}: {};", index, hex_color(self.theme.ansi_color(index)))?;
+        }
+        writeln!(html, " }}")?;
+        writeln!(
+            html,
+            "pre.kla {{ margin: 0; padding: {}px {}px {}px {}px; font-family: {}monospace; font-size: {}px; line-height: {}; letter-spacing: {}px; color: var(--kla-foreground); background: var(--kla-background); }}",
+            padding.top,
+            padding.right,
+            padding.bottom,
+            padding.left,
+            self.config
+                .font_chain()
+                .iter()
+                .filter(|family| !family.eq_ignore_ascii_case(BUILTIN_FONT))
+                .map(|family| format!("'{}', ", escape_xml(family)))
+                .collect::<String>(),
+            self.config.font_size,
+            self.config.line_height,
+            self.config.letter_spacing,
+        )?;
+        writeln!(html, "pre.kla a {{ color: inherit; }}")?;
+        writeln!(html, "</style>")?;
+        writeln!(html, "</head>")?;
+        writeln!(html, "<body>")?;
+
+        for (index, state) in self.frames.iter().enumerate() {
+            let hidden = if index > 0 { " hidden" } else { "" };
+            writeln!(html, r#"<pre class="kla"{}>{}</pre>"#, hidden, self.render_screen(state)?)?;
+        }
+
+        if self.frames.len() > 1 {
+            writeln!(html, "<script>")?;
+            writeln!(html, r#"const frames = document.querySelectorAll("pre.kla");"#)?;
+            writeln!(html, "let current = 0;")?;
+            writeln!(html, "setInterval(() => {{")?;
+            writeln!(html, "  frames[current].hidden = true;")?;
+            writeln!(html, "  current = (current + 1) % frames.length;")?;
+            writeln!(html, "  frames[current].hidden = false;")?;
+            writeln!(html, "}}, {});", frame_delay.as_millis().max(1))?;
+            writeln!(html, "</script>")?;
+        }
+
+        writeln!(html, "</body>")?;
+        writeln!(html, "</html>")?;
+        Ok(html)
+    }
+
+    /// Rows of the screen, with each run of identically styled cells in one span
+    fn render_screen(&self, state: &TerminalState) -> Result<String> {
+        let mut rows = Vec::with_capacity(state.buffer.len());
+        for (y, row) in state.buffer.iter().enumerate() {
+            // Trailing blank cells would only get in the way of copying
+            let used = row
+                .iter()
+                .rposition(|cell| cell.ch != ' ' || cell.attrs.bg_color.is_some() || cell.attrs.reverse)
+                .map_or(0, |last| last + 1);
+
+            let mut line = String::new();
+            let mut run = String::new();
+            let mut run_key: Option<(String, Option<&str>)> = None;
+            for (x, cell) in row[..used].iter().enumerate() {
+                if cell.continuation {
+                    continue;
+                }
+                let link = state.hyperlink_at(x as u16, y as u16).map(|link| link.uri.as_str());
+                let key = (self.cell_style(&cell.attrs), link);
+                if run_key.as_ref() != Some(&key) {
+                    if let Some((style, link)) = run_key.take() {
+                        push_run(&mut line, &run, &style, link)?;
+                    }
+                    run.clear();
+                    run_key = Some(key);
+                }
+                run.push(cell.ch);
+            }
+            if let Some((style, link)) = run_key {
+                push_run(&mut line, &run, &style, link)?;
+            }
+            rows.push(line);
+        }
+        Ok(rows.join("\n"))
+    }
+
+    /// Inline CSS for a cell's attributes; empty for plain text
+    fn cell_style(&self, attrs: &CharAttributes) -> String {
+        let color = |index: Option<u8>, default: &str| match index {
+            Some(index) if index < 16 => format!("var(--kla-color-{})", index),
+            Some(index) => hex_color(self.theme.ansi_color(index)),
+            None => default.to_string(),
+        };
+        let (foreground, background) = if attrs.reverse {
+            (Some(color(attrs.bg_color, "var(--kla-background)")), Some(color(attrs.fg_color, "var(--kla-foreground)")))
+        } else {
+            (attrs.fg_color.map(|_| color(attrs.fg_color, "")), attrs.bg_color.map(|_| color(attrs.bg_color, "")))
+        };
+
+        let mut style = String::new();
+        if let Some(foreground) = foreground {
+            style.push_str(&format!("color: {}; ", foreground));
+        }
+        if let Some(background) = background {
+            style.push_str(&format!("background: {}; ", background));
+        }
+        if attrs.bold {
+            style.push_str("font-weight: bold; ");
+        }
+        if attrs.italic {
+            style.push_str("font-style: italic; ");
+        }
+        if attrs.underline {
+            style.push_str("text-decoration: underline; ");
+        }
+        style.trim_end().to_string()
+    }
+
+    pub fn save_html(&self, output_path: &Path, frame_delay: Duration) -> Result<()> {
+        let html = self.render(frame_delay)?;
+        std::fs::write(output_path, html)
+            .with_context(|| format!("Failed to write HTML file: {}", output_path.display()))?;
+
+        log::info!("HTML saved to: {}", output_path.display());
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+/// Append `text` wrapped in its style span and link, as needed
+fn push_run(line: &mut String, text: &str, style: &str, link: Option<&str>) -> Result<()> {
+    if let Some(uri) = link {
+        write!(line, r#"<a href="{}">"#, escape_xml(uri))?;
+    }
+    if style.is_empty() {
+        line.push_str(&escape_xml(text));
+    } else {
+        write!(line, r#"<span style="{}">{}</span>"#, style, escape_xml(text))?;
+    }
+    if link.is_some() {
+        line.push_str("</a>");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pty::capture::TerminalCapture;
+
+    #[test]
+    fn test_html_holds_text_and_theme() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::dracula_theme();
+        let mut recorder = HtmlRecorder::new(&config, &theme);
+
+        let mut capture = TerminalCapture::new(40, 5);
+        capture.process_output("$ echo <hi>\r\n\x1b[1;31m<hi>\x1b[0m & bye").unwrap();
+        recorder.capture_state(&capture.to_state());
+
+        let html = recorder.render(Duration::from_millis(500)).unwrap();
+        assert!(html.contains("--kla-background: #282a36;"));
+        assert!(html.contains("$ echo &lt;hi&gt;\n"));
+        assert!(html.contains(r#"<span style="color: var(--kla-color-1); font-weight: bold;">&lt;hi&gt;</span> &amp; bye"#));
+        assert!(!html.contains("<script>"));
+
+        recorder.capture_state(&capture.to_state());
+        let html = recorder.render(Duration::from_millis(250)).unwrap();
+        assert!(html.contains("}, 250);"));
+        assert_eq!(html.matches(r#"<pre class="kla" hidden>"#).count(), 1);
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// Randomly varied delays between typed characters, like a human typist's
+pub struct TypingJitter {
+    rng: fastrand::Rng,
+    /// Largest offset from the typing speed, either way
+    bound: Duration,
+}
+
+impl TypingJitter {
+    /// Offsets of up to `bound`, drawn from `seed` or from a random seed if unset
+    pub fn new(bound: Duration, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        Self { rng, bound }
+    }
+
+    /// The delay after the next character, `speed` moved by a random offset within the bound
+    pub fn delay(&mut self, speed: Duration) -> Duration {
+        let bound = self.bound.as_micros().min(u64::MAX as u128) as u64;
+        let offset = Duration::from_micros(self.rng.u64(0..=bound));
+        if self.rng.bool() {
+            speed + offset
+        } else {
+            speed.saturating_sub(offset)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_jitter_varies_within_bound_and_repeats() {
+        let speed = Duration::from_millis(50);
+        let bound = Duration::from_millis(20);
+        let delays = |seed| {
+            let mut jitter = TypingJitter::new(bound, Some(seed));
+            (0..50).map(|_| jitter.delay(speed)).collect::<Vec<_>>()
+        };
+
+        let first = delays(7);
+        assert!(first.iter().all(|delay| *delay >= speed - bound && *delay <= speed + bound), "{:?}", first);
+        assert!(first.iter().any(|delay| *delay != first[0]), "delays should vary");
+        assert_eq!(first, delays(7));
+        assert_ne!(first, delays(8));
+    }
+}
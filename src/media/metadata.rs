@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use image::RgbImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::naming::utc_timestamp;
+
+/// Provenance embedded in saved PNG screenshots as text chunks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PngMetadata {
+    /// Commands run before the screenshot was taken, in order
+    pub commands: Vec<String>,
+    pub theme: String,
+    /// Record the creation time; off for deterministic output
+    pub timestamp: bool,
+}
+
+impl PngMetadata {
+    /// Text chunks as keyword and value, using the registered keywords where one fits
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![("Software", format!("KLA {}", env!("CARGO_PKG_VERSION")))];
+        if !self.commands.is_empty() {
+            entries.push(("kla:commands", self.commands.join("\n")));
+        }
+        if !self.theme.is_empty() {
+            entries.push(("kla:theme", self.theme.clone()));
+        }
+        if self.timestamp {
+            entries.push(("Creation Time", utc_timestamp()));
+        }
+        entries
+    }
+}
+
+/// Save `image` to `output_path`, embedding `metadata` if it is a PNG
+///
+/// Values are written as iTXt chunks, so commands keep any non-Latin-1
+/// text. Other formats are saved as the extension asks, without metadata.
+pub fn save_image(image: &RgbImage, output_path: &Path, metadata: &PngMetadata) -> Result<()> {
+    let is_png = output_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if !is_png {
+        return image.save(output_path)
+            .with_context(|| format!("Failed to save screenshot to: {}", output_path.display()));
+    }
+    
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to save screenshot to: {}", output_path.display()))?;
+    write_png(BufWriter::new(file), image, metadata)
+        .with_context(|| format!("Failed to save screenshot to: {}", output_path.display()))
+}
+
+/// `image` encoded as a PNG with `metadata` embedded, for callers that keep it in memory
+pub fn encode_png(image: &RgbImage, metadata: &PngMetadata) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_png(&mut bytes, image, metadata).context("Failed to encode PNG")?;
+    Ok(bytes)
+}
+
+fn write_png<W: Write>(writer: W, image: &RgbImage, metadata: &PngMetadata) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata.entries() {
+        encoder.add_itxt_chunk(keyword.to_string(), text)?;
+    }
+    
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -1,17 +1,53 @@
-use anyhow::{Context, Result};
-use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
 
 pub mod recorder;
 pub mod screenshot;
 pub mod gif;
+pub mod asciicast;
+pub mod svg;
+pub mod background;
+pub mod theme;
+pub mod replay;
+pub mod highlight;
+pub mod caption;
+pub mod fonts;
+pub mod panes;
+pub mod webp;
+pub mod apng;
+pub mod html;
+pub mod registry;
+pub mod timeline;
+pub mod naming;
+pub mod metadata;
+pub mod jitter;
 
 pub use recorder::MediaRecorder;
+pub use asciicast::AsciicastRecorder;
+pub use svg::SvgRecorder;
+pub use html::HtmlRecorder;
+pub use registry::register_generator;
+pub use background::Background;
+pub use theme::ThemeConfig;
+pub use caption::{keystroke_label, Caption, CaptionPosition};
+pub use naming::output_file_name;
+pub use metadata::PngMetadata;
+pub use panes::{render_panes, PaneDirection, PaneLayout};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OutputFormat {
     Png,
     Gif,
     Mp4,
+    Asciicast,
+    Svg,
+    WebP,
+    Apng,
+    Html,
+    /// An extension handled by a generator added with `register_generator`
+    Custom(String),
 }
 
 impl OutputFormat {
@@ -20,7 +56,19 @@ impl OutputFormat {
             "png" => Ok(OutputFormat::Png),
             "gif" => Ok(OutputFormat::Gif),
             "mp4" => Ok(OutputFormat::Mp4),
-            _ => Err(anyhow::anyhow!("Unsupported format: {}. Supported formats: png, gif, mp4", s)),
+            "cast" | "asciicast" => Ok(OutputFormat::Asciicast),
+            "svg" => Ok(OutputFormat::Svg),
+            "webp" => Ok(OutputFormat::WebP),
+            "apng" => Ok(OutputFormat::Apng),
+            "html" => Ok(OutputFormat::Html),
+            other if registry::generator_for(other).is_some() => Ok(OutputFormat::Custom(other.to_string())),
+            _ => {
+                let mut supported = "png, gif, mp4, cast, svg, webp, apng, html".to_string();
+                for extension in registry::registered_extensions() {
+                    supported.push_str(&format!(", {}", extension));
+                }
+                Err(anyhow::anyhow!("Unsupported format: {}. Supported formats: {}", s, supported))
+            }
         }
     }
     
@@ -29,23 +77,145 @@ impl OutputFormat {
             OutputFormat::Png => "png",
             OutputFormat::Gif => "gif",
             OutputFormat::Mp4 => "mp4",
+            OutputFormat::Asciicast => "cast",
+            OutputFormat::Svg => "svg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Apng => "apng",
+            OutputFormat::Html => "html",
+            OutputFormat::Custom(extension) => extension,
         }
     }
+    
+    /// File extension used for `record_gif` animations in this format
+    pub fn animation_extension(&self) -> &str {
+        match self {
+            OutputFormat::Svg => "svg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Apng => "apng",
+            OutputFormat::Html => "html",
+            _ => "gif",
+        }
+    }
+    
+    /// The distinct animation formats `formats` call for, in order
+    ///
+    /// Formats without an animation of their own (PNG, MP4, cast) animate as GIF.
+    pub fn animation_formats(formats: &[OutputFormat]) -> Vec<OutputFormat> {
+        let mut animations = Vec::new();
+        for format in formats {
+            let animation = match format {
+                OutputFormat::Svg | OutputFormat::WebP | OutputFormat::Apng | OutputFormat::Html => format.clone(),
+                _ => OutputFormat::Gif,
+            };
+            if !animations.contains(&animation) {
+                animations.push(animation);
+            }
+        }
+        animations
+    }
 }
 
-pub trait MediaGenerator {
+/// Writes an output file from terminal content; see `register_generator`
+pub trait MediaGenerator: Send + Sync {
     fn create_output(&self, content: &str, output_path: &Path) -> Result<()>;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MediaConfig {
     pub font_family: String,
     pub font_size: u16,
+    /// Line height as a multiple of the font size
     pub line_height: f32,
+    /// Extra horizontal space between character cells, in pixels
+    pub letter_spacing: f32,
+    /// Padding applied to every side unless overridden below
     pub padding: u16,
+    pub padding_top: Option<u16>,
+    pub padding_right: Option<u16>,
+    pub padding_bottom: Option<u16>,
+    pub padding_left: Option<u16>,
     pub background_color: (u8, u8, u8),
     pub text_color: (u8, u8, u8),
     pub cursor_color: (u8, u8, u8),
+    /// Backdrop behind the terminal panel; `None` fills with the theme background
+    pub background: Option<Background>,
+    /// Opacity of the terminal panel over a custom backdrop (0.0 - 1.0)
+    pub panel_opacity: f32,
+    /// Render screenshots with the full scrollback as a tall image instead of
+    /// just the visible screen
+    pub capture_scrollback: bool,
+    /// Shape of the cursor drawn at the terminal's cursor position
+    pub cursor_style: CursorStyle,
+    /// Alternate the cursor's visibility between consecutive GIF frames
+    pub cursor_blink: bool,
+    /// Blink the cursor every 530ms while the screen is unchanged, so idle
+    /// stretches of an animation still look like a live terminal
+    pub animate_idle: bool,
+    /// Language (name or extension) used to syntax highlight rendered text
+    ///
+    /// Only text without an explicit color is highlighted.
+    pub syntax_highlight: Option<String>,
+    /// Output pixels per logical pixel; 2.0 renders retina-quality images
+    pub scale: f32,
+    /// Encode WebP animations losslessly instead of lossy
+    pub webp_lossless: bool,
+    /// WebP quality from 0 to 100; for lossless output, higher compresses harder
+    pub webp_quality: f32,
+    /// Shrink the palette, then the frames, until each GIF fits in this many bytes
+    pub max_gif_bytes: Option<usize>,
+    /// Number each row in a dimmed gutter left of the terminal content
+    pub line_numbers: bool,
+    /// Crop still screenshots to the rows and columns their content uses,
+    /// instead of rendering the whole terminal
+    pub autofit: bool,
+    /// Draw OSC 8 hyperlinked text underlined in this color in raster output
+    pub link_color: Option<(u8, u8, u8)>,
+    /// How many times animations play; `None` loops forever, `Some(1)` plays once
+    pub loop_count: Option<u16>,
+    /// Extra time the last frame of an animation stays up before it loops
+    pub end_pause: Option<Duration>,
+    /// Write `<script>.timeline.json` next to recordings, listing when each
+    /// command ran, its text and its output
+    pub emit_timeline: bool,
+    /// Flash an inverted frame into GIF, WebP and APNG output whenever a
+    /// program rings the bell, like a terminal's visual bell
+    pub visual_bell: bool,
+    /// How screenshot and animation files are named, from the tokens
+    /// `{name}`, `{script}`, `{index}`, `{date}` and `{format}`
+    pub name_template: String,
+    /// Grow rendered text screenshots to fit lines wrapped at the terminal
+    /// width, instead of letting early rows scroll off the top
+    pub wrap_lines: bool,
+    /// Drop the unchanged starting screen from animations and hold the final
+    /// screen for a single frame instead of however long the session idled
+    pub trim_blank_edges: bool,
+    /// Show keys sent by `type` and `input` steps in a badge on animation
+    /// frames captured within 1.5 seconds after, like a keystroke visualizer
+    pub show_keystrokes: bool,
+    /// Time GIF, WebP and APNG frames by the wall-clock time between
+    /// captures, clamped to this `(min, max)` range, so animations replay at
+    /// the speed the session ran; deterministic recordings keep fixed delays
+    pub real_time_delays: Option<(Duration, Duration)>,
+    /// Vary the delay after each character a `type` step sends by up to this
+    /// much either way, since people don't type perfectly evenly
+    pub typing_jitter: Option<Duration>,
+    /// Seed for `typing_jitter`, so the same seed types with the same rhythm;
+    /// random when unset, except in deterministic recordings
+    pub typing_seed: Option<u64>,
+}
+
+/// How the cursor is drawn in rendered output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorStyle {
+    /// Fill the whole cell, drawing the character under it in the background color
+    #[default]
+    Block,
+    /// A thin vertical bar at the left edge of the cell
+    Bar,
+    /// A thin line along the bottom of the cell
+    Underline,
 }
 
 impl Default for MediaConfig {
@@ -54,85 +224,330 @@ impl Default for MediaConfig {
             font_family: "JetBrains Mono".to_string(),
             font_size: 14,
             line_height: 1.2,
+            letter_spacing: 0.0,
             padding: 20,
+            padding_top: None,
+            padding_right: None,
+            padding_bottom: None,
+            padding_left: None,
             background_color: (40, 44, 52),   // Dark background
             text_color: (171, 178, 191),      // Light text
             cursor_color: (97, 175, 239),     // Blue cursor
+            background: None,
+            panel_opacity: 1.0,
+            capture_scrollback: false,
+            cursor_style: CursorStyle::Block,
+            cursor_blink: false,
+            animate_idle: false,
+            syntax_highlight: None,
+            scale: 1.0,
+            webp_lossless: true,
+            webp_quality: 90.0,
+            max_gif_bytes: None,
+            line_numbers: false,
+            autofit: false,
+            link_color: None,
+            loop_count: None,
+            end_pause: None,
+            emit_timeline: false,
+            visual_bell: false,
+            name_template: naming::DEFAULT_NAME_TEMPLATE.to_string(),
+            wrap_lines: false,
+            trim_blank_edges: false,
+            show_keystrokes: false,
+            real_time_delays: None,
+            typing_jitter: None,
+            typing_seed: None,
         }
     }
 }
 
-#[derive(Clone)]
-pub struct ThemeConfig {
-    pub name: String,
-    pub background: (u8, u8, u8),
-    pub foreground: (u8, u8, u8),
-    pub cursor: (u8, u8, u8),
-    pub selection: (u8, u8, u8),
-    pub colors: Vec<(u8, u8, u8)>, // ANSI colors (16 colors)
+/// Resolved padding around the terminal content, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Padding {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
 }
 
-impl ThemeConfig {
-    pub fn default_theme() -> Self {
-        Self {
-            name: "Default".to_string(),
-            background: (40, 44, 52),
-            foreground: (171, 178, 191),
-            cursor: (97, 175, 239),
-            selection: (75, 81, 96),
-            colors: vec![
-                (40, 44, 52),    // Black
-                (224, 108, 117), // Red
-                (152, 195, 121), // Green
-                (229, 192, 123), // Yellow
-                (97, 175, 239),  // Blue
-                (198, 120, 221), // Magenta
-                (86, 182, 194),  // Cyan
-                (171, 178, 191), // White
-                (92, 99, 112),   // Bright Black
-                (224, 108, 117), // Bright Red
-                (152, 195, 121), // Bright Green
-                (229, 192, 123), // Bright Yellow
-                (97, 175, 239),  // Bright Blue
-                (198, 120, 221), // Bright Magenta
-                (86, 182, 194),  // Bright Cyan
-                (255, 255, 255), // Bright White
-            ],
-        }
+impl Padding {
+    pub fn horizontal(&self) -> u32 {
+        self.left + self.right
     }
     
-    pub fn dracula_theme() -> Self {
-        Self {
-            name: "Dracula".to_string(),
-            background: (40, 42, 54),
-            foreground: (248, 248, 242),
-            cursor: (248, 248, 242),
-            selection: (68, 71, 90),
-            colors: vec![
-                (40, 42, 54),    // Black
-                (255, 85, 85),   // Red
-                (80, 250, 123),  // Green
-                (241, 250, 140), // Yellow
-                (139, 233, 253), // Blue
-                (255, 121, 198), // Magenta
-                (139, 233, 253), // Cyan
-                (248, 248, 242), // White
-                (98, 114, 164),  // Bright Black
-                (255, 85, 85),   // Bright Red
-                (80, 250, 123),  // Bright Green
-                (241, 250, 140), // Bright Yellow
-                (139, 233, 253), // Bright Blue
-                (255, 121, 198), // Bright Magenta
-                (139, 233, 253), // Bright Cyan
-                (255, 255, 255), // Bright White
-            ],
-        }
+    pub fn vertical(&self) -> u32 {
+        self.top + self.bottom
+    }
+}
+
+impl MediaConfig {
+    /// Start building a config from the defaults
+    pub fn builder() -> MediaConfigBuilder {
+        MediaConfigBuilder::default()
+    }
+    
+    /// A builder that draws with the builtin glyphs, so pixel tests don't
+    /// depend on which fonts are installed
+    #[cfg(test)]
+    pub(crate) fn builtin_font() -> MediaConfigBuilder {
+        Self::builder().font(fonts::BUILTIN_FONT)
+    }
+    
+    /// Width and height of one terminal cell in pixels
+    ///
+    /// The advance width approximates a monospace font at 0.6em, plus any
+    /// letter spacing; the height is the font size scaled by `line_height`.
+    pub fn cell_size(&self) -> (u32, u32) {
+        let advance = (self.font_size as u32 * 6 / 10) as f32 + self.letter_spacing;
+        let width = advance.round().max(1.0) as u32;
+        let height = ((self.font_size as f32 * self.line_height) as u32).max(1);
+        (width, height)
+    }
+    
+    /// Font families from `font_family`, a comma-separated fallback list
+    ///
+    /// Quotes around names are optional, as in CSS.
+    pub fn font_chain(&self) -> Vec<String> {
+        self.font_family
+            .split(',')
+            .map(|family| family.trim().trim_matches(|ch| ch == '"' || ch == '\'').trim())
+            .filter(|family| !family.is_empty())
+            .map(str::to_string)
+            .collect()
     }
     
-    pub fn from_name(name: &str) -> Self {
-        match name.to_lowercase().as_str() {
-            "dracula" => Self::dracula_theme(),
-            _ => Self::default_theme(),
+    /// Convert a logical length to output pixels, never less than one
+    pub fn scaled(&self, length: u32) -> u32 {
+        ((length as f32 * self.scale).round() as u32).max(1)
+    }
+    
+    /// Set the same padding on all four sides, clearing per-side overrides
+    pub fn set_padding(&mut self, padding: u16) {
+        self.padding = padding;
+        self.padding_top = None;
+        self.padding_right = None;
+        self.padding_bottom = None;
+        self.padding_left = None;
+    }
+    
+    /// Padding for each side, with per-side overrides applied
+    pub fn padding_sides(&self) -> Padding {
+        Padding {
+            top: self.padding_top.unwrap_or(self.padding) as u32,
+            right: self.padding_right.unwrap_or(self.padding) as u32,
+            bottom: self.padding_bottom.unwrap_or(self.padding) as u32,
+            left: self.padding_left.unwrap_or(self.padding) as u32,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Fluent builder for `MediaConfig`
+///
+/// ```
+/// use kla::MediaConfig;
+///
+/// let config = MediaConfig::builder().font("Fira Code").font_size(16).padding(40).build();
+/// assert_eq!(config.font_size, 16);
+/// ```
+#[derive(Clone, Default)]
+pub struct MediaConfigBuilder {
+    config: MediaConfig,
+}
+
+impl MediaConfigBuilder {
+    pub fn font<S: Into<String>>(mut self, family: S) -> Self {
+        self.config.font_family = family.into();
+        self
+    }
+    
+    pub fn font_size(mut self, size: u16) -> Self {
+        self.config.font_size = size;
+        self
+    }
+    
+    pub fn line_height(mut self, line_height: f32) -> Self {
+        self.config.line_height = line_height;
+        self
+    }
+    
+    pub fn letter_spacing(mut self, spacing: f32) -> Self {
+        self.config.letter_spacing = spacing;
+        self
+    }
+    
+    /// Same padding on all four sides
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.config.set_padding(padding);
+        self
+    }
+    
+    /// Individual padding per side
+    pub fn padding_sides(mut self, top: u16, right: u16, bottom: u16, left: u16) -> Self {
+        self.config.padding_top = Some(top);
+        self.config.padding_right = Some(right);
+        self.config.padding_bottom = Some(bottom);
+        self.config.padding_left = Some(left);
+        self
+    }
+    
+    pub fn background(mut self, background: Background) -> Self {
+        self.config.background = Some(background);
+        self
+    }
+    
+    pub fn panel_opacity(mut self, opacity: f32) -> Self {
+        self.config.panel_opacity = opacity;
+        self
+    }
+    
+    pub fn capture_scrollback(mut self, enabled: bool) -> Self {
+        self.config.capture_scrollback = enabled;
+        self
+    }
+    
+    pub fn cursor_style(mut self, style: CursorStyle) -> Self {
+        self.config.cursor_style = style;
+        self
+    }
+    
+    pub fn cursor_blink(mut self, blink: bool) -> Self {
+        self.config.cursor_blink = blink;
+        self
+    }
+    
+    pub fn animate_idle(mut self, enabled: bool) -> Self {
+        self.config.animate_idle = enabled;
+        self
+    }
+    
+    pub fn syntax_highlight<S: Into<String>>(mut self, language: S) -> Self {
+        self.config.syntax_highlight = Some(language.into());
+        self
+    }
+    
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.config.scale = scale;
+        self
+    }
+    
+    pub fn webp_lossless(mut self, lossless: bool) -> Self {
+        self.config.webp_lossless = lossless;
+        self
+    }
+    
+    pub fn webp_quality(mut self, quality: f32) -> Self {
+        self.config.webp_quality = quality;
+        self
+    }
+    
+    /// Play animations `plays` times instead of looping forever
+    pub fn loop_count(mut self, plays: u16) -> Self {
+        self.config.loop_count = Some(plays);
+        self
+    }
+    
+    /// Hold the last frame of each animation for `pause` longer
+    pub fn end_pause(mut self, pause: Duration) -> Self {
+        self.config.end_pause = Some(pause);
+        self
+    }
+    
+    pub fn emit_timeline(mut self, enabled: bool) -> Self {
+        self.config.emit_timeline = enabled;
+        self
+    }
+    
+    pub fn visual_bell(mut self, enabled: bool) -> Self {
+        self.config.visual_bell = enabled;
+        self
+    }
+    
+    pub fn name_template<S: Into<String>>(mut self, template: S) -> Self {
+        self.config.name_template = template.into();
+        self
+    }
+    
+    pub fn wrap_lines(mut self, wrap: bool) -> Self {
+        self.config.wrap_lines = wrap;
+        self
+    }
+    
+    pub fn trim_blank_edges(mut self, trim: bool) -> Self {
+        self.config.trim_blank_edges = trim;
+        self
+    }
+    
+    pub fn show_keystrokes(mut self, show: bool) -> Self {
+        self.config.show_keystrokes = show;
+        self
+    }
+    
+    /// Time frames by how long each screen was really shown, between `min` and `max`
+    pub fn real_time_delays(mut self, min: Duration, max: Duration) -> Self {
+        self.config.real_time_delays = Some((min, max));
+        self
+    }
+    
+    /// Vary each typed character's delay by up to `jitter`, from random offsets seeded with `seed`
+    pub fn typing_jitter(mut self, jitter: Duration, seed: Option<u64>) -> Self {
+        self.config.typing_jitter = Some(jitter);
+        self.config.typing_seed = seed;
+        self
+    }
+    
+    pub fn max_gif_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_gif_bytes = Some(bytes);
+        self
+    }
+    
+    pub fn line_numbers(mut self, enabled: bool) -> Self {
+        self.config.line_numbers = enabled;
+        self
+    }
+    
+    pub fn autofit(mut self, enabled: bool) -> Self {
+        self.config.autofit = enabled;
+        self
+    }
+    
+    pub fn link_color(mut self, color: (u8, u8, u8)) -> Self {
+        self.config.link_color = Some(color);
+        self
+    }
+    
+    pub fn build(self) -> MediaConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_media_config_padding_deserialization() {
+        let uniform: MediaConfig = serde_yaml::from_str("padding: 12").unwrap();
+        assert_eq!(uniform.padding_sides(), Padding { top: 12, right: 12, bottom: 12, left: 12 });
+        
+        let sides: MediaConfig = serde_yaml::from_str("padding: 10\npadding_bottom: 60").unwrap();
+        assert_eq!(sides.padding_sides(), Padding { top: 10, right: 10, bottom: 60, left: 10 });
+        assert_eq!(sides.font_size, MediaConfig::default().font_size);
+    }
+    
+    #[test]
+    fn test_media_config_builder() {
+        let config = MediaConfig::builder()
+            .font("Fira Code")
+            .font_size(16)
+            .padding(40)
+            .padding_sides(1, 2, 3, 4)
+            .build();
+        
+        assert_eq!(config.font_family, "Fira Code");
+        assert_eq!(config.font_size, 16);
+        assert_eq!(config.padding, 40);
+        assert_eq!(config.padding_sides(), Padding { top: 1, right: 2, bottom: 3, left: 4 });
+        assert_eq!(config.line_height, MediaConfig::default().line_height);
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Template output files are named with unless `MediaConfig::name_template` is set
+pub const DEFAULT_NAME_TEMPLATE: &str = "{name}.{format}";
+
+/// Fill in `template` for an artifact called `name` in `format`
+///
+/// `{script}` is the script's file-friendly name, `{index}` the 1-based
+/// number of the step writing the file and `{date}` today's UTC date as
+/// `YYYY-MM-DD`. Anything else in braces is left as written.
+pub fn output_file_name(template: &str, name: &str, script: &str, index: usize, format: &str) -> String {
+    let mut file_name = template
+        .replace("{name}", name)
+        .replace("{script}", script)
+        .replace("{index}", &index.to_string())
+        .replace("{format}", format);
+    if file_name.contains("{date}") {
+        file_name = file_name.replace("{date}", &today());
+    }
+    file_name
+}
+
+/// Today's UTC date as `YYYY-MM-DD`
+fn today() -> String {
+    let (year, month, day) = civil_date((now_secs() / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The current UTC time as `YYYY-MM-DDTHH:MM:SSZ`
+pub(crate) fn utc_timestamp() -> String {
+    let secs = now_secs();
+    let (year, month, day) = civil_date((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3_600, time % 3_600 / 60, time % 60
+    )
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+/// Year, month and day `days` after 1970-01-01, after Howard Hinnant's algorithm
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
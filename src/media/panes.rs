@@ -0,0 +1,162 @@
+use anyhow::Result;
+use image::{imageops, Rgb, RgbImage};
+
+use super::recorder::MediaRecorder;
+use super::screenshot::ScreenshotGenerator;
+use super::{MediaConfig, ThemeConfig};
+use crate::pty::TerminalController;
+
+/// Direction panes are placed in, one after another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaneDirection {
+    /// Side by side, left to right
+    #[default]
+    Horizontal,
+    /// Stacked, top to bottom
+    Vertical,
+}
+
+/// How several terminal panes are arranged in one image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneLayout {
+    pub direction: PaneDirection,
+    /// Space between neighbouring panes, in pixels
+    pub gap: u32,
+}
+
+impl PaneLayout {
+    pub fn horizontal(gap: u32) -> Self {
+        Self { direction: PaneDirection::Horizontal, gap }
+    }
+
+    pub fn vertical(gap: u32) -> Self {
+        Self { direction: PaneDirection::Vertical, gap }
+    }
+}
+
+/// Direction names accepted by script layouts
+pub const PANE_DIRECTIONS: &[&str] = &["horizontal", "vertical"];
+
+impl PaneDirection {
+    /// Parse a direction name, defaulting to side by side
+    pub fn from_name(name: Option<&str>) -> Result<Self> {
+        match name.map(|name| name.trim().to_lowercase()).as_deref() {
+            None | Some("horizontal") => Ok(PaneDirection::Horizontal),
+            Some("vertical") => Ok(PaneDirection::Vertical),
+            Some(other) => Err(anyhow::anyhow!(
+                "Unknown pane direction '{}'. Expected one of: {}",
+                other,
+                PANE_DIRECTIONS.join(", ")
+            )),
+        }
+    }
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self::horizontal(0)
+    }
+}
+
+/// Render each terminal's current screen and join them into one image
+///
+/// Every pane is rendered like a screenshot, padding included. Panes of
+/// different sizes are aligned to the top (or left) edge, and the gap and any
+/// leftover space are filled with the theme background.
+pub fn render_panes(
+    panes: &[&TerminalController],
+    layout: &PaneLayout,
+    config: &MediaConfig,
+    theme: &ThemeConfig,
+) -> Result<RgbImage> {
+    let generator = ScreenshotGenerator::new(config, theme);
+    let images = panes
+        .iter()
+        .map(|pane| generator.render_state(&MediaRecorder::terminal_state(pane)))
+        .collect::<Result<Vec<_>>>()?;
+
+    compose(&images, layout, theme)
+}
+
+/// Join already rendered pane images according to `layout`
+pub fn compose(images: &[RgbImage], layout: &PaneLayout, theme: &ThemeConfig) -> Result<RgbImage> {
+    if images.is_empty() {
+        return Err(anyhow::anyhow!("No panes to render"));
+    }
+
+    let gaps = layout.gap * (images.len() as u32 - 1);
+    let (width, height) = match layout.direction {
+        PaneDirection::Horizontal => (
+            images.iter().map(|image| image.width()).sum::<u32>() + gaps,
+            images.iter().map(|image| image.height()).max().unwrap_or(0),
+        ),
+        PaneDirection::Vertical => (
+            images.iter().map(|image| image.width()).max().unwrap_or(0),
+            images.iter().map(|image| image.height()).sum::<u32>() + gaps,
+        ),
+    };
+
+    let fill = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+    let mut canvas = RgbImage::from_pixel(width, height, fill);
+    let mut offset = 0;
+    for image in images {
+        match layout.direction {
+            PaneDirection::Horizontal => {
+                imageops::replace(&mut canvas, image, offset as i64, 0);
+                offset += image.width() + layout.gap;
+            }
+            PaneDirection::Vertical => {
+                imageops::replace(&mut canvas, image, 0, offset as i64);
+                offset += image.height() + layout.gap;
+            }
+        }
+    }
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TerminalSettings;
+    use std::time::Duration;
+
+    async fn pane(text: &str) -> TerminalController {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 12,
+            ..TerminalSettings::default()
+        };
+        let mut terminal = TerminalController::new(&settings).await.unwrap();
+        terminal.execute_command(&format!("echo {}", text)).await.unwrap();
+        terminal.wait_for_output(&format!("\n{}", text), Duration::from_secs(5)).await.unwrap();
+        // Output is read as it arrives, so let the next prompt land too
+        terminal.wait_for_settled().await;
+        terminal
+    }
+
+    #[tokio::test]
+    async fn test_render_two_panes_side_by_side() {
+        let client = pane("client").await;
+        let server = pane("server").await;
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+
+        let image = render_panes(&[&client, &server], &PaneLayout::horizontal(16), &config, &theme).unwrap();
+
+        // Each half of the composite matches that pane rendered on its own
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        let left = generator.render_state(&MediaRecorder::terminal_state(&client)).unwrap();
+        let right = generator.render_state(&MediaRecorder::terminal_state(&server)).unwrap();
+        assert_ne!(left, right);
+        assert_eq!(image.width(), left.width() + 16 + right.width());
+        assert_eq!(image.height(), left.height());
+
+        let crop = |x: u32, pane: &RgbImage| {
+            imageops::crop_imm(&image, x, 0, pane.width(), pane.height()).to_image()
+        };
+        assert_eq!(crop(0, &left), left);
+        assert_eq!(crop(left.width() + 16, &right), right);
+    }
+}
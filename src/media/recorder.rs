@@ -1,30 +1,117 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
+use image::RgbImage;
+use regex::Regex;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::pty::TerminalController;
-use super::{OutputFormat, MediaConfig, ThemeConfig};
-use super::screenshot::ScreenshotGenerator;
-use super::gif::GifGenerator;
+use crate::error::{KlaError, Result};
+use crate::pty::capture::TerminalCapture;
+use crate::pty::{CommandRun, TerminalController};
+use crate::terminal::TerminalState;
+use super::{keystroke_label, Caption, OutputFormat, MediaConfig, PaneLayout, ThemeConfig};
+use super::metadata::{encode_png, save_image, PngMetadata};
+use super::screenshot::{text_state, ScreenshotGenerator};
+use super::gif::GifRecorder;
+use super::jitter::TypingJitter;
+use super::asciicast::AsciicastRecorder;
+use super::svg::SvgRecorder;
+use super::html::HtmlRecorder;
+
+/// Rows of history kept when screenshots capture the full scrollback
+const SCROLLBACK_LINES: usize = 10_000;
+
+/// Most rows a single command's screenshot can hold
+const MAX_COMMAND_ROWS: usize = 1_000;
+
+/// How long each animation frame is shown unless a frame rate is set
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(500);
+
+/// How long keys stay in the keystroke badge after they are sent
+const KEYSTROKE_LINGER: Duration = Duration::from_millis(1500);
+
+/// Edits an animation frame in place before it is encoded
+pub type FrameProcessor = Box<dyn FnMut(&mut RgbImage) + Send>;
 
 pub struct MediaRecorder {
     format: OutputFormat,
+    /// Every requested format, `format` first
+    formats: Vec<OutputFormat>,
     output_dir: PathBuf,
     config: MediaConfig,
     theme: ThemeConfig,
-    gif_generator: Option<GifGenerator>,
+    gif_recorder: Option<GifRecorder>,
+    svg_recorder: Option<SvgRecorder>,
+    html_recorder: Option<HtmlRecorder>,
+    frame_delay: Duration,
+    /// Caption overlaid on screenshots and GIF frames, and when it expires
+    caption: Option<(Caption, Duration)>,
+    clock: Clock,
+    /// Rewrite applied to every line of the screen before it is rendered
+    prompt_replace: Option<(Regex, String)>,
+    /// Output mark up to which bells have been looked for
+    bell_mark: usize,
+    /// `{script}` in output file names
+    script_name: String,
+    /// `{index}` in output file names: the step being run, counting from 1
+    step_index: usize,
+    frame_processor: Option<FrameProcessor>,
+    /// How panes are arranged when several are captured together
+    pane_layout: PaneLayout,
+    /// Commands run so far, embedded in PNG screenshots
+    commands: Vec<String>,
+    /// Keys last sent to the terminal, as a badge label, and when the badge goes away
+    keystrokes: Option<(String, Duration)>,
+    /// Clock time of the last animation frame captured, for `real_time_delays`
+    last_capture: Option<Duration>,
+    /// Source of typing delays, with `typing_jitter` set
+    typing_jitter: Option<TypingJitter>,
+}
+
+/// Time as seen by captions and GIF frame sampling
+enum Clock {
+    /// Wall-clock time since the recorder was created
+    Real(tokio::time::Instant),
+    /// Simulated time, which deterministic recordings advance explicitly
+    Virtual(Duration),
+}
+
+impl Clock {
+    fn now(&self) -> Duration {
+        match self {
+            Clock::Real(start) => start.elapsed(),
+            Clock::Virtual(elapsed) => *elapsed,
+        }
+    }
 }
 
 impl MediaRecorder {
     pub fn new(format: OutputFormat, output_dir: &Path) -> Result<Self> {
-        std::fs::create_dir_all(output_dir)
-            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+        std::fs::create_dir_all(output_dir).map_err(|err| {
+            KlaError::io_context(err, format_args!("Failed to create output directory {}", output_dir.display()))
+        })?;
         
         Ok(Self {
+            formats: vec![format.clone()],
             format,
             output_dir: output_dir.to_path_buf(),
             config: MediaConfig::default(),
             theme: ThemeConfig::default_theme(),
-            gif_generator: None,
+            gif_recorder: None,
+            svg_recorder: None,
+            html_recorder: None,
+            frame_delay: DEFAULT_FRAME_DELAY,
+            caption: None,
+            prompt_replace: None,
+            bell_mark: 0,
+            script_name: String::new(),
+            step_index: 1,
+            frame_processor: None,
+            pane_layout: PaneLayout::default(),
+            commands: Vec::new(),
+            keystrokes: None,
+            last_capture: None,
+            typing_jitter: None,
+            clock: Clock::Real(tokio::time::Instant::now()),
         })
     }
     
@@ -33,51 +120,539 @@ impl MediaRecorder {
         self
     }
     
+    /// Time captions and GIF frames on a simulated clock instead of the wall clock
+    ///
+    /// The clock only moves through `advance_clock` and recorded frames, and
+    /// each frame is captured once the terminal's output has settled, so a
+    /// script records the same frames on every run.
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.clock = if enabled { Clock::Virtual(Duration::ZERO) } else { Clock::Real(tokio::time::Instant::now()) };
+        self
+    }
+    
+    /// Move the simulated clock forward; does nothing on the wall clock
+    pub fn advance_clock(&mut self, by: Duration) {
+        if let Clock::Virtual(elapsed) = &mut self.clock {
+            *elapsed += by;
+        }
+    }
+    
+    /// Time on the clock captions and animation frames are timed by
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now()
+    }
+    
+    /// Write each animation in every one of `formats`, from the same frames
+    ///
+    /// The first format becomes the primary one; an empty list changes nothing.
+    pub fn with_formats(mut self, formats: Vec<OutputFormat>) -> Self {
+        if let Some(primary) = formats.first() {
+            self.format = primary.clone();
+            self.formats = formats;
+        }
+        self
+    }
+    
+    /// Name output files for `script`, where the name template uses `{script}`
+    pub fn with_script_name(mut self, script: &str) -> Self {
+        self.script_name = script.to_string();
+        self
+    }
+    
+    /// Number output files from the step at `index`, counting from 1
+    pub fn set_step_index(&mut self, index: usize) {
+        self.step_index = index;
+    }
+    
+    /// Note that `command` ran, for the metadata of later PNG screenshots
+    pub fn add_command(&mut self, command: &str) {
+        self.commands.push(command.to_string());
+    }
+    
+    /// Provenance for PNG screenshots; deterministic recordings leave out the time
+    fn png_metadata(&self) -> PngMetadata {
+        PngMetadata {
+            commands: self.commands.clone(),
+            theme: self.theme.name.clone(),
+            timestamp: matches!(self.clock, Clock::Real(_)),
+        }
+    }
+    
+    /// Run `processor` on every GIF, WebP and APNG frame before it is encoded,
+    /// e.g. to add a watermark
+    pub fn with_frame_processor<F: FnMut(&mut RgbImage) + Send + 'static>(mut self, processor: F) -> Self {
+        self.frame_processor = Some(Box::new(processor));
+        self
+    }
+    
+    pub fn with_pane_layout(mut self, layout: PaneLayout) -> Self {
+        self.pane_layout = layout;
+        self
+    }
+    
     pub fn with_config(mut self, config: MediaConfig) -> Self {
+        self.typing_jitter = None;
         self.config = config;
         self
     }
     
+    /// How long to wait after typing a character at `speed`, varied by `typing_jitter`
+    pub fn typing_delay(&mut self, speed: Duration) -> Duration {
+        let Some(bound) = self.config.typing_jitter else {
+            return speed;
+        };
+        // Seeded when typing starts, so deterministic recordings type with the
+        // same rhythm every run however the recorder was built
+        let seed = self.config.typing_seed.or(matches!(self.clock, Clock::Virtual(_)).then_some(0));
+        self.typing_jitter.get_or_insert_with(|| TypingJitter::new(bound, seed)).delay(speed)
+    }
+    
+    /// Replace matches of `pattern` on screen before rendering, e.g. to hide a home directory
+    pub fn with_prompt_replace(mut self, pattern: Regex, replacement: &str) -> Self {
+        self.prompt_replace = Some((pattern, replacement.to_string()));
+        self
+    }
+    
     pub async fn take_screenshot(
         &self,
         terminal: &TerminalController,
         output_path: &Path,
     ) -> Result<()> {
+        save_image(&self.screenshot_image(terminal)?, output_path, &self.png_metadata())?;
+        Ok(())
+    }
+    
+    /// Screenshot `terminal` as PNG bytes, for serving without touching the disk
+    pub async fn take_screenshot_bytes(&self, terminal: &TerminalController) -> Result<Vec<u8>> {
+        Ok(encode_png(&self.screenshot_image(terminal)?, &self.png_metadata())?)
+    }
+    
+    /// The screen as `take_screenshot` renders it, caption included
+    fn screenshot_image(&self, terminal: &TerminalController) -> Result<RgbImage> {
+        let screenshot_gen = ScreenshotGenerator::new(&self.config, &self.theme);
+        let state = if self.config.capture_scrollback {
+            let (content, width, height) = self.screenshot_content(terminal);
+            let mut state = text_state(&content, width, height);
+            state.cursor_visible = false;
+            state
+        } else {
+            Self::terminal_state(terminal)
+        };
+        let mut image = screenshot_gen.render_screenshot(&self.rewritten(state))
+            .context("Failed to generate screenshot")?;
+        
+        if let Some(caption) = self.active_caption() {
+            screenshot_gen.draw_caption(&mut image, caption);
+        }
+        Ok(image)
+    }
+    
+    /// Run `command` to completion and screenshot only its prompt line and output
+    ///
+    /// Rows from before the command and the prompt that follows it are left
+    /// out. Captions apply as they do to full screenshots.
+    pub async fn capture_command(
+        &self,
+        terminal: &mut TerminalController,
+        command: &str,
+        timeout: Duration,
+        output_path: &Path,
+    ) -> Result<CommandRun> {
+        let (region, run) = Self::command_region(terminal, command, timeout).await?;
+        
         let screenshot_gen = ScreenshotGenerator::new(&self.config, &self.theme);
+        let mut image = screenshot_gen.render_screenshot(&self.rewritten(region))
+            .context("Failed to generate command screenshot")?;
+        if let Some(caption) = self.active_caption() {
+            screenshot_gen.draw_caption(&mut image, caption);
+        }
+        save_image(&image, output_path, &self.png_metadata())?;
+        
+        Ok(run)
+    }
+    
+    /// Run `command` and build a screen holding just its prompt line and output
+    ///
+    /// The output is replayed onto a grid tall enough that it never scrolls,
+    /// starting after the prompt, which is copied from the screen beforehand.
+    pub(crate) async fn command_region(
+        terminal: &mut TerminalController,
+        command: &str,
+        timeout: Duration,
+    ) -> Result<(TerminalState, CommandRun)> {
+        let before = Self::terminal_state(terminal);
+        let run = terminal.run_command(command, timeout).await?;
+        
+        let width = before.size.width;
+        let prompt_len = before.cursor.x as usize;
+        let rows = run.output.matches('\n').count() + run.output.len() / width.max(1) as usize + 2;
+        let mut capture = TerminalCapture::new(width, rows.min(MAX_COMMAND_ROWS) as u16);
+        capture.process_output(&format!("\x1b[1;{}H{}", prompt_len + 1, run.output)).ok();
+        
+        let mut region = capture.to_state();
+        if let (Some(prompt), Some(first)) = (before.buffer.get(before.cursor.y as usize), region.buffer.first_mut()) {
+            let len = prompt_len.min(prompt.len()).min(first.len());
+            first[..len].clone_from_slice(&prompt[..len]);
+        }
+        
+        // A cursor back at column 0 is where the next prompt would start
+        let used = if region.cursor.x == 0 { region.cursor.y } else { region.cursor.y + 1 }.max(1);
+        region.buffer.truncate(used as usize);
+        region.size.height = used;
+        region.cursor_visible = false;
+        Ok((region, run))
+    }
+    
+    /// The screen as it should be rendered, with `prompt_replace` applied
+    fn rewritten(&self, mut state: TerminalState) -> TerminalState {
+        if let Some((pattern, replacement)) = &self.prompt_replace {
+            state.replace_text(pattern, replacement);
+        }
+        state
+    }
+    
+    /// The terminal's screen as screenshots show it, with `prompt_replace` applied
+    pub(crate) fn screen_state(&self, terminal: &TerminalController) -> TerminalState {
+        self.rewritten(Self::terminal_state(terminal))
+    }
+    
+    /// The terminal's current screen, as a terminal of its size would show it
+    pub(crate) fn terminal_state(terminal: &TerminalController) -> TerminalState {
+        let (width, height) = terminal.get_size();
+        let mut capture = TerminalCapture::new(width, height);
+        capture.process_output(&terminal.get_output()).ok();
+        capture.to_state()
+    }
+    
+    /// Text and grid size a screenshot of the terminal should render
+    ///
+    /// With `capture_scrollback` the grid grows to fit every line of history.
+    fn screenshot_content(&self, terminal: &TerminalController) -> (String, u16, u16) {
         let content = terminal.get_output();
         let (width, height) = terminal.get_size();
+        if !self.config.capture_scrollback {
+            return (content, width, height);
+        }
         
-        screenshot_gen.generate(&content, width, height, output_path)
-            .context("Failed to generate screenshot")?;
-        
-        Ok(())
+        let mut capture = TerminalCapture::with_scrollback(width, height, SCROLLBACK_LINES);
+        capture.process_output(&content).ok();
+        let lines = capture.get_scrollback_lines();
+        let rows = (lines.len() as u16).max(height);
+        (lines.join("\n"), width, rows)
     }
     
     pub async fn start_gif_recording(&mut self, terminal: &TerminalController) -> Result<()> {
         let (width, height) = terminal.get_size();
-        self.gif_generator = Some(GifGenerator::new(&self.config, &self.theme, width, height)?);
+        self.bell_mark = terminal.mark();
+        self.last_capture = None;
+        for format in OutputFormat::animation_formats(&self.formats) {
+            match format {
+                OutputFormat::Svg => {
+                    self.svg_recorder = Some(SvgRecorder::new(&self.config, &self.theme, width, height));
+                }
+                OutputFormat::Html => {
+                    self.html_recorder = Some(HtmlRecorder::new(&self.config, &self.theme));
+                }
+                _ => {
+                    self.gif_recorder = Some(GifRecorder::new(&self.config, &self.theme, width, height));
+                }
+            }
+        }
         Ok(())
     }
     
     pub async fn capture_gif_frame(&mut self, terminal: &TerminalController) -> Result<()> {
-        if let Some(ref mut gif_gen) = self.gif_generator {
-            let content = terminal.get_output();
-            let (width, height) = terminal.get_size();
-            gif_gen.add_frame(&content, width, height)?;
+        self.retime_previous_frame();
+        let caption = self.active_caption().cloned();
+        let keystrokes = self.active_keystrokes().map(str::to_string);
+        let state = self.rewritten(Self::terminal_state(terminal));
+        let bell = self.config.visual_bell && self.bell_rang(terminal);
+        if let Some(ref mut gif_recorder) = self.gif_recorder {
+            let mut delay = (self.frame_delay.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+            gif_recorder.set_caption(caption);
+            gif_recorder.set_keystrokes(keystrokes);
+            if bell {
+                delay = delay.saturating_sub(gif_recorder.capture_visual_bell(&state, delay)?).max(1);
+            }
+            gif_recorder.capture_state_with_delay(&state, delay)?;
+        }
+        if let Some(ref mut svg_recorder) = self.svg_recorder {
+            svg_recorder.capture_state(&state);
+        }
+        if let Some(ref mut html_recorder) = self.html_recorder {
+            html_recorder.capture_state(&state);
+        }
+        Ok(())
+    }
+    
+    /// Capture one frame showing every pane side by side, as laid out by `with_pane_layout`
+    ///
+    /// A single pane is captured as `capture_gif_frame` would. SVG and HTML
+    /// recordings show only the first pane.
+    pub async fn capture_panes_frame(&mut self, panes: &[&TerminalController]) -> Result<()> {
+        let [first, rest @ ..] = panes else {
+            return Err(KlaError::invalid_state("no panes to capture"));
+        };
+        if rest.is_empty() {
+            return self.capture_gif_frame(first).await;
+        }
+        
+        self.retime_previous_frame();
+        let mut image = self.render_panes(panes)?;
+        if let Some(keys) = self.active_keystrokes() {
+            ScreenshotGenerator::new(&self.config, &self.theme).draw_keystrokes(&mut image, keys);
+        }
+        if let Some(ref mut gif_recorder) = self.gif_recorder {
+            let delay = (self.frame_delay.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+            gif_recorder.capture_image(image, delay);
+        }
+        if self.svg_recorder.is_some() || self.html_recorder.is_some() {
+            let state = self.rewritten(Self::terminal_state(first));
+            if let Some(ref mut svg_recorder) = self.svg_recorder {
+                svg_recorder.capture_state(&state);
+            }
+            if let Some(ref mut html_recorder) = self.html_recorder {
+                html_recorder.capture_state(&state);
+            }
         }
         Ok(())
     }
     
-    pub async fn stop_gif_recording(&mut self, output_path: &Path) -> Result<()> {
-        if let Some(gif_gen) = self.gif_generator.take() {
-            gif_gen.save(output_path)
-                .context("Failed to save GIF")?;
+    /// With `real_time_delays`, hold the previous frame for as long as has passed since it was captured
+    fn retime_previous_frame(&mut self) {
+        let (Some((min, max)), Clock::Real(_)) = (self.config.real_time_delays, &self.clock) else {
+            return;
+        };
+        let now = self.clock.now();
+        if let (Some(previous), Some(gif_recorder)) = (self.last_capture.replace(now), &mut self.gif_recorder) {
+            let shown = now.saturating_sub(previous).clamp(min, max);
+            let delay = (shown.as_millis() / 10).clamp(1, u16::MAX as u128) as u16;
+            gif_recorder.retime_last_capture(delay);
+        }
+    }
+    
+    /// Every pane's screen joined into one image, with the active caption
+    fn render_panes(&self, panes: &[&TerminalController]) -> Result<RgbImage> {
+        let mut image = super::panes::render_panes(panes, &self.pane_layout, &self.config, &self.theme)?;
+        if let Some(caption) = self.active_caption() {
+            ScreenshotGenerator::new(&self.config, &self.theme).draw_caption(&mut image, caption);
+        }
+        Ok(image)
+    }
+    
+    /// Screenshot every pane together, or just the one pane if there is only one
+    pub async fn take_panes_screenshot(&self, panes: &[&TerminalController], output_path: &Path) -> Result<()> {
+        if let [terminal] = panes {
+            return self.take_screenshot(terminal, output_path).await;
+        }
+        save_image(&self.render_panes(panes)?, output_path, &self.png_metadata())?;
+        Ok(())
+    }
+    
+    /// Whether a bell rang in the output since the last check
+    fn bell_rang(&mut self, terminal: &TerminalController) -> bool {
+        // Output cleared since the last check is gone, bells included
+        self.bell_mark = self.bell_mark.max(terminal.oldest_mark());
+        let (bells, scanned) = crate::pty::ansi::count_bells(&terminal.read_since(self.bell_mark));
+        self.bell_mark += scanned;
+        bells > 0
+    }
+    
+    /// Sample the live screen `fps` times a second for `duration`
+    ///
+    /// Each frame is shown for one frame period, so the animation plays back in
+    /// real time. Returns how many frames were captured.
+    pub async fn capture_gif_frames(
+        &mut self,
+        terminal: &TerminalController,
+        duration: Duration,
+        fps: u16,
+    ) -> Result<usize> {
+        self.capture_panes_frames(&[terminal], duration, fps).await
+    }
+    
+    /// Sample every pane together `fps` times a second for `duration`
+    pub async fn capture_panes_frames(
+        &mut self,
+        panes: &[&TerminalController],
+        duration: Duration,
+        fps: u16,
+    ) -> Result<usize> {
+        let period = Duration::from_secs(1) / u32::from(fps.max(1));
+        self.frame_delay = period;
+        
+        if let Clock::Virtual(_) = self.clock {
+            let frames = (duration.as_secs_f64() * f64::from(fps.max(1))).round() as usize;
+            for _ in 0..frames {
+                for pane in panes {
+                    pane.wait_for_settled().await;
+                }
+                self.capture_panes_frame(panes).await?;
+                self.advance_clock(period);
+            }
+            return Ok(frames);
+        }
+        
+        let deadline = tokio::time::Instant::now() + duration;
+        let mut ticker = tokio::time::interval(period);
+        // A slow render drops frames rather than bursting to catch up
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        
+        let mut captured = 0;
+        loop {
+            ticker.tick().await;
+            if tokio::time::Instant::now() >= deadline {
+                break;
+            }
+            self.capture_panes_frame(panes).await?;
+            captured += 1;
+        }
+        Ok(captured)
+    }
+    
+    /// Save the recording in each requested animation format, returning the files written
+    ///
+    /// The primary format is written to `output_path`; the others go next to
+    /// it with their own extension.
+    pub async fn stop_gif_recording(&mut self, output_path: &Path) -> Result<Vec<PathBuf>> {
+        let gif_recorder = self.finish_frames();
+        let svg_recorder = self.svg_recorder.take();
+        let html_recorder = self.html_recorder.take();
+        
+        let mut written = Vec::new();
+        for (index, format) in OutputFormat::animation_formats(&self.formats).into_iter().enumerate() {
+            let path = if index == 0 {
+                output_path.to_path_buf()
+            } else {
+                output_path.with_extension(format.animation_extension())
+            };
+            match (&format, &gif_recorder, &svg_recorder) {
+                (OutputFormat::Svg, _, Some(svg_recorder)) => svg_recorder.save_svg(&path, self.frame_delay)
+                    .context("Failed to save SVG")?,
+                (OutputFormat::Html, _, _) => match &html_recorder {
+                    Some(html_recorder) => html_recorder.save_html(&path, self.frame_delay)
+                        .context("Failed to save HTML")?,
+                    None => continue,
+                },
+                (OutputFormat::WebP, Some(gif_recorder), _) => super::webp::save_webp(gif_recorder.frames(), &self.config, &path)
+                    .context("Failed to save WebP")?,
+                (OutputFormat::Apng, Some(gif_recorder), _) => super::apng::save_apng(gif_recorder.frames(), &self.config, &path)
+                    .context("Failed to save APNG")?,
+                (OutputFormat::Gif, Some(gif_recorder), _) => {
+                    gif_recorder.save_gif(&path)
+                        .context("Failed to save GIF")?;
+                }
+                _ => continue,
+            }
+            written.push(path);
+        }
+        Ok(written)
+    }
+    
+    /// End the recording and return it as GIF bytes instead of writing any file
+    ///
+    /// Only the GIF is produced; other requested formats are dropped.
+    pub async fn stop_gif_recording_bytes(&mut self) -> Result<Vec<u8>> {
+        self.svg_recorder = None;
+        self.html_recorder = None;
+        let gif_recorder = self.finish_frames()
+            .ok_or_else(|| KlaError::invalid_state("no GIF recording in progress"))?;
+        let (bytes, _) = gif_recorder.encode().context("Failed to encode GIF")?;
+        Ok(bytes)
+    }
+    
+    /// Take the recorded frames, trimmed and processed as configured
+    fn finish_frames(&mut self) -> Option<GifRecorder> {
+        let mut gif_recorder = self.gif_recorder.take()?;
+        if self.config.trim_blank_edges {
+            gif_recorder.trim_blank_edges();
         }
+        if let Some(processor) = &mut self.frame_processor {
+            gif_recorder.process_frames(processor);
+        }
+        Some(gif_recorder)
+    }
+    
+    /// Overlay `caption` on screenshots and GIF frames captured in the next `duration`
+    ///
+    /// A new caption replaces the current one. SVG animations are not captioned.
+    pub fn show_caption(&mut self, caption: Caption, duration: Duration) {
+        self.caption = Some((caption, self.clock.now() + duration));
+    }
+    
+    /// Show `keys` in a badge on animation frames for a moment, with `show_keystrokes` set
+    ///
+    /// Only frames captured within `KEYSTROKE_LINGER` of the last key show the
+    /// badge, so an animation must start recording soon after the typing to
+    /// catch it. Keys sent again before the badge goes away are added to it.
+    pub fn show_keystrokes(&mut self, keys: &str) {
+        if !self.config.show_keystrokes {
+            return;
+        }
+        let shown = self.active_keystrokes().unwrap_or_default().to_string();
+        let label = keystroke_label(&format!("{}{}", shown, keys));
+        self.keystrokes = Some((label, self.clock.now() + KEYSTROKE_LINGER));
+    }
+    
+    /// The keystroke badge to draw right now, if one hasn't gone away
+    fn active_keystrokes(&self) -> Option<&str> {
+        self.keystrokes
+            .as_ref()
+            .filter(|(_, until)| self.clock.now() < *until)
+            .map(|(keys, _)| keys.as_str())
+    }
+    
+    /// The caption to draw right now, if one hasn't expired
+    fn active_caption(&self) -> Option<&Caption> {
+        self.caption
+            .as_ref()
+            .filter(|(_, until)| self.clock.now() < *until)
+            .map(|(caption, _)| caption)
+    }
+    
+    /// File extension used for `record_gif` animations in the current format
+    pub fn animation_extension(&self) -> &str {
+        self.format.animation_extension()
+    }
+    
+    /// Write the terminal's full output history as an asciicast v2 file
+    pub fn save_asciicast(&self, terminal: &TerminalController, output_path: &Path) -> Result<()> {
+        AsciicastRecorder::from_terminal(terminal)
+            .save(output_path)
+            .context("Failed to save asciicast")?;
         Ok(())
     }
     
+    pub fn config(&self) -> &MediaConfig {
+        &self.config
+    }
+    
+    pub fn theme(&self) -> &ThemeConfig {
+        &self.theme
+    }
+    
+    /// Use `theme` for subsequent captures, returning the theme it replaces
+    pub fn replace_theme(&mut self, theme: ThemeConfig) -> ThemeConfig {
+        std::mem::replace(&mut self.theme, theme)
+    }
+    
+    pub fn format(&self) -> &OutputFormat {
+        &self.format
+    }
+    
     pub fn get_output_path(&self, name: &str) -> PathBuf {
-        self.output_dir.join(format!("{}.{}", name, self.format.extension()))
+        self.output_path(name, self.format.extension())
+    }
+    
+    /// Where artifact `name` is written as `extension`, following the name template
+    pub fn output_path(&self, name: &str, extension: &str) -> PathBuf {
+        self.output_dir.join(super::output_file_name(
+            &self.config.name_template,
+            name,
+            &self.script_name,
+            self.step_index,
+            extension,
+        ))
     }
 }
 
@@ -85,21 +660,267 @@ impl MediaRecorder {
 mod tests {
     use super::*;
     use tempfile::TempDir;
+    use crate::media::CaptionPosition;
+    
+    /// A 40x10 `/bin/sh` terminal showing a plain `$ ` prompt
+    async fn sh_terminal() -> TerminalController {
+        let settings = crate::TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 10,
+            startup_commands: vec!["PS1='$ '".to_string()],
+            ..Default::default()
+        };
+        TerminalController::new(&settings).await.unwrap()
+    }
+    
+    fn gif_recorder(output_dir: &Path) -> MediaRecorder {
+        MediaRecorder::new(OutputFormat::Gif, output_dir).unwrap()
+    }
     
     #[test]
     fn test_media_recorder_creation() {
         let temp_dir = TempDir::new().unwrap();
-        let recorder = MediaRecorder::new(OutputFormat::Png, temp_dir.path()).unwrap();
+        let _recorder = MediaRecorder::new(OutputFormat::Png, temp_dir.path()).unwrap();
         
         assert!(temp_dir.path().exists());
     }
     
+    #[test]
+    fn test_deterministic_typing_rhythm_ignores_builder_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = MediaConfig::builder().typing_jitter(Duration::from_millis(40), None).build();
+        let recorder = || gif_recorder(temp_dir.path());
+        let delays = |mut recorder: MediaRecorder| -> Vec<Duration> {
+            (0..8).map(|_| recorder.typing_delay(Duration::from_millis(50))).collect()
+        };
+        
+        let config_first = delays(recorder().with_config(config.clone()).deterministic(true));
+        let clock_first = delays(recorder().deterministic(true).with_config(config));
+        assert_eq!(config_first, clock_first);
+    }
+    
     #[test]
     fn test_output_path_generation() {
         let temp_dir = TempDir::new().unwrap();
-        let recorder = MediaRecorder::new(OutputFormat::Gif, temp_dir.path()).unwrap();
+        let recorder = gif_recorder(temp_dir.path());
         
         let path = recorder.get_output_path("test");
         assert_eq!(path.file_name().unwrap(), "test.gif");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_name_template_fills_in_date() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut recorder = gif_recorder(temp_dir.path())
+            .with_config(MediaConfig::builtin_font().name_template("{script}-{index}-{name}-{date}.{format}").build())
+            .with_script_name("demo");
+        recorder.set_step_index(3);
+        
+        let today = std::process::Command::new("date").args(["-u", "+%F"]).output().unwrap();
+        let today = String::from_utf8(today.stdout).unwrap();
+        let path = recorder.get_output_path("intro");
+        assert_eq!(path.file_name().unwrap().to_str().unwrap(), format!("demo-3-intro-{}.gif", today.trim()));
+    }
+    
+    #[tokio::test]
+    async fn test_capture_gif_frames_at_fps() {
+        let temp_dir = TempDir::new().unwrap();
+        let terminal = sh_terminal().await;
+        let mut recorder = gif_recorder(temp_dir.path());
+        
+        recorder.start_gif_recording(&terminal).await.unwrap();
+        let frames = recorder.capture_gif_frames(&terminal, Duration::from_secs(1), 10).await.unwrap();
+        assert!((9..=11).contains(&frames), "captured {} frames", frames);
+        
+        let delays: u32 = recorder.gif_recorder.as_ref().unwrap()
+            .frames()
+            .iter()
+            .map(|frame| frame.delay as u32)
+            .sum();
+        assert_eq!(delays, frames as u32 * 10);
+        
+        let path = temp_dir.path().join("fps.gif");
+        recorder.stop_gif_recording(&path).await.unwrap();
+        assert!(path.exists());
+    }
+    
+    #[tokio::test]
+    async fn test_command_screenshots_hold_only_their_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut terminal = sh_terminal().await;
+        let timeout = Duration::from_secs(5);
+        // The prompt has to be on screen before the first command is sent
+        assert!(terminal.wait_for_output("$ ", timeout).await.unwrap());
+        
+        let (first, run) = MediaRecorder::command_region(&mut terminal, "echo fir''st", timeout).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        let (second, _) = MediaRecorder::command_region(&mut terminal, "echo sec''ond", timeout).await.unwrap();
+        
+        // Each region is the prompt line with its command, then that command's output
+        for (region, command, output, other) in [
+            (&first, "echo fir''st", "first", "second"),
+            (&second, "echo sec''ond", "second", "first"),
+        ] {
+            let text = region.get_text();
+            let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+            assert_eq!(lines.len(), 2, "{:?}", lines);
+            assert!(lines[0].ends_with(command), "{:?}", lines);
+            assert_eq!(lines[1], output);
+            assert!(!text.contains(other), "{:?}", lines);
+            assert!(!text.contains("kla-exit"), "{:?}", lines);
+        }
+        
+        let config = MediaConfig::builtin_font().build();
+        let recorder = MediaRecorder::new(OutputFormat::Png, temp_dir.path()).unwrap();
+        let path = temp_dir.path().join("third.png");
+        recorder.capture_command(&mut terminal, "echo th''ird", timeout, &path).await.unwrap();
+        let char_height = (config.font_size as f32 * config.line_height) as u32;
+        let (_, height) = image::image_dimensions(&path).unwrap();
+        assert_eq!(height, 2 * char_height + config.padding as u32 * 2);
+    }
+    
+    #[tokio::test]
+    async fn test_scrollback_screenshot_keeps_every_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut terminal = sh_terminal().await;
+        terminal.execute_command("seq 1 100").await.unwrap();
+        terminal.wait_for_output("\n100", std::time::Duration::from_secs(5)).await.unwrap();
+        
+        let config = MediaConfig::builtin_font().capture_scrollback(true).build();
+        let recorder = MediaRecorder::new(OutputFormat::Png, temp_dir.path())
+            .unwrap()
+            .with_config(config.clone());
+        
+        let (content, _, rows) = recorder.screenshot_content(&terminal);
+        // The shell prompt may share a line with the first number
+        let last_words: Vec<&str> = content
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .collect();
+        assert!(last_words.contains(&"1"), "{:?}", last_words);
+        assert!(last_words.contains(&"100"), "{:?}", last_words);
+        assert!(rows >= 100);
+        
+        let path = temp_dir.path().join("tall.png");
+        recorder.take_screenshot(&terminal, &path).await.unwrap();
+        let char_height = (config.font_size as f32 * config.line_height) as u32;
+        let (_, height) = image::image_dimensions(&path).unwrap();
+        assert_eq!(height, rows as u32 * char_height + config.padding as u32 * 2);
+    }
+    
+    #[tokio::test]
+    async fn test_prompt_replace_rewrites_rendered_prompt() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings = crate::TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 10,
+            startup_commands: vec!["PS1='/Users/alice/project $ '".to_string()],
+            ..Default::default()
+        };
+        let terminal = TerminalController::new(&settings).await.unwrap();
+        assert!(terminal.wait_for_output("project $ ", Duration::from_secs(5)).await.unwrap());
+        
+        let recorder = MediaRecorder::new(OutputFormat::Png, temp_dir.path())
+            .unwrap()
+            .with_prompt_replace(Regex::new("/Users/[^/]+").unwrap(), "~");
+        let state = recorder.rewritten(MediaRecorder::terminal_state(&terminal));
+        
+        assert_eq!(state.get_line_text(0).as_deref(), Some("~/project $"));
+        assert_eq!((state.cursor.x, state.cursor.y), (12, 0));
+        assert!(terminal.get_output().contains("/Users/alice/project $ "), "the shell's output is untouched");
+    }
+    
+    #[tokio::test]
+    async fn test_frame_processor_tints_encoded_frames() {
+        use image::AnimationDecoder;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let terminal = sh_terminal().await;
+        let mut recorder = gif_recorder(temp_dir.path())
+            .with_frame_processor(|image| image.pixels_mut().for_each(|pixel| pixel[0] = 255));
+        
+        recorder.start_gif_recording(&terminal).await.unwrap();
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        let path = temp_dir.path().join("tinted.gif");
+        recorder.stop_gif_recording(&path).await.unwrap();
+        
+        let file = std::fs::File::open(&path).unwrap();
+        let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+        assert!(!frames.is_empty());
+        for frame in frames {
+            assert!(frame.buffer().pixels().all(|pixel| pixel[0] == 255));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_real_time_delays_follow_wall_clock() {
+        let temp_dir = TempDir::new().unwrap();
+        let terminal = sh_terminal().await;
+        let mut recorder = gif_recorder(temp_dir.path())
+            .with_config(MediaConfig::builtin_font().real_time_delays(Duration::from_millis(20), Duration::from_secs(5)).build());
+        
+        // Only `advance` moves the clock from here on
+        tokio::time::pause();
+        recorder.start_gif_recording(&terminal).await.unwrap();
+        for caption in ["ls", "cd src", "cargo build"] {
+            // A different caption each time keeps the frames from being merged
+            recorder.show_caption(Caption::new(caption, CaptionPosition::Top), Duration::from_secs(5));
+            recorder.capture_gif_frame(&terminal).await.unwrap();
+            tokio::time::advance(Duration::from_millis(200)).await;
+        }
+        
+        let delays: Vec<u16> = recorder.gif_recorder.as_ref().unwrap().frames().iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![20, 20, 50], "the last frame keeps the fixed delay");
+    }
+    
+    #[tokio::test]
+    async fn test_screenshot_and_gif_bytes_decode() {
+        let temp_dir = TempDir::new().unwrap();
+        let terminal = sh_terminal().await;
+        let mut recorder = gif_recorder(temp_dir.path());
+        
+        let path = temp_dir.path().join("on-disk.png");
+        recorder.take_screenshot(&terminal, &path).await.unwrap();
+        let png = recorder.take_screenshot_bytes(&terminal).await.unwrap();
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+        assert_eq!((image.width(), image.height()), image::image_dimensions(&path).unwrap());
+        
+        recorder.start_gif_recording(&terminal).await.unwrap();
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        let gif = recorder.stop_gif_recording_bytes().await.unwrap();
+        let frame = image::load_from_memory_with_format(&gif, image::ImageFormat::Gif).unwrap();
+        assert_eq!((frame.width(), frame.height()), (image.width(), image.height()));
+        assert!(recorder.stop_gif_recording_bytes().await.is_err());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1, "only the on-disk screenshot is written");
+    }
+    
+    #[tokio::test]
+    async fn test_bell_inserts_inverted_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut terminal = sh_terminal().await;
+        let mut recorder = gif_recorder(temp_dir.path())
+            .with_config(MediaConfig::builtin_font().visual_bell(true).build());
+        
+        recorder.start_gif_recording(&terminal).await.unwrap();
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        terminal.run_command("printf 'ding\\a'", Duration::from_secs(5)).await.unwrap();
+        terminal.wait_for_settled().await;
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        
+        let frames = recorder.gif_recorder.as_ref().unwrap().frames();
+        let delays: Vec<u16> = frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![50, 10, 90]);
+        let mut inverted = frames[2].image.clone();
+        image::imageops::invert(&mut inverted);
+        assert!(frames[1].image == inverted, "the flash is the next frame inverted");
+        
+        terminal.clear_output_buffer();
+        terminal.run_command("echo quiet", Duration::from_secs(5)).await.unwrap();
+        terminal.wait_for_settled().await;
+        recorder.capture_gif_frame(&terminal).await.unwrap();
+        assert_eq!(recorder.gif_recorder.as_ref().unwrap().frames().len(), 4, "no bell rang again");
+    }
+}
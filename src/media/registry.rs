@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use super::MediaGenerator;
+
+type Registry = RwLock<HashMap<String, Arc<dyn MediaGenerator>>>;
+
+fn registry() -> &'static Registry {
+    static GENERATORS: OnceLock<Registry> = OnceLock::new();
+    GENERATORS.get_or_init(Default::default)
+}
+
+/// Lowercase `ext` without a leading dot, as formats are looked up
+fn normalize(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
+
+/// Handle output files ending in `.ext` with `generator`
+///
+/// Recordings given that extension as a format are written by calling
+/// `generator` with the session's raw output. Registering an extension again
+/// replaces its generator.
+pub fn register_generator(ext: &str, generator: Box<dyn MediaGenerator>) {
+    if let Ok(mut generators) = registry().write() {
+        generators.insert(normalize(ext), Arc::from(generator));
+    }
+}
+
+/// The generator registered for `ext`, if any
+pub fn generator_for(ext: &str) -> Option<Arc<dyn MediaGenerator>> {
+    registry().read().ok()?.get(&normalize(ext)).cloned()
+}
+
+/// Every registered extension, sorted
+pub fn registered_extensions() -> Vec<String> {
+    let mut extensions: Vec<String> = registry()
+        .read()
+        .map(|generators| generators.keys().cloned().collect())
+        .unwrap_or_default();
+    extensions.sort();
+    extensions
+}
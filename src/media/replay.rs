@@ -0,0 +1,66 @@
+use anyhow::Result;
+use std::time::Duration;
+
+use super::asciicast::AsciicastRecorder;
+use super::gif::GifRecorder;
+use super::{MediaConfig, ThemeConfig};
+use crate::pty::capture::TerminalCapture;
+
+/// How long the final frame of a replay stays on screen, in centiseconds
+const FINAL_FRAME_DELAY: u16 = 100;
+
+/// Replay a cast's output through a virtual terminal, one GIF frame per event
+///
+/// Each frame stays on screen until the next event's timestamp, so the GIF
+/// keeps the recorded timing. The cast's declared size is used for the grid.
+pub fn replay_cast(cast: &AsciicastRecorder, config: &MediaConfig, theme: &ThemeConfig) -> Result<GifRecorder> {
+    let header = cast.header();
+    let mut capture = TerminalCapture::new(header.width, header.height);
+    let mut recorder = GifRecorder::new(config, theme, header.width, header.height);
+    
+    let events = cast.events();
+    for (index, event) in events.iter().enumerate() {
+        capture.process_output(&event.data)?;
+        
+        let delay = match events.get(index + 1) {
+            Some(next) => centiseconds(next.elapsed.saturating_sub(event.elapsed)),
+            None => FINAL_FRAME_DELAY,
+        };
+        recorder.capture_state_with_delay(&capture.to_state(), delay)?;
+    }
+    
+    if recorder.frame_count() == 0 {
+        return Err(anyhow::anyhow!("Cast has no output events"));
+    }
+    Ok(recorder)
+}
+
+/// GIF delays are in centiseconds; anything shorter than one still gets a frame
+fn centiseconds(duration: Duration) -> u16 {
+    (duration.as_millis() / 10).clamp(1, u16::MAX as u128) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_replay_frames_follow_events() {
+        let cast = AsciicastRecorder::read_from(&br#"{"version": 2, "width": 30, "height": 5}
+[0.0, "o", "$ "]
+[0.5, "o", "ls\r\n"]
+[0.6, "o", "\u001b[0m"]
+[1.5, "o", "Cargo.toml\r\n$ "]
+"#[..]).unwrap();
+        
+        let recorder = replay_cast(&cast, &MediaConfig::builtin_font().build(), &ThemeConfig::default_theme()).unwrap();
+        
+        // The bare SGR reset changes nothing on screen, so it extends the previous frame
+        let delays: Vec<u16> = recorder.frames().iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, vec![50, 100, FINAL_FRAME_DELAY]);
+        
+        let config = MediaConfig::builtin_font().build();
+        let char_width = config.font_size as u32 * 6 / 10;
+        assert_eq!(recorder.frames()[0].image.width(), 30 * char_width + config.padding as u32 * 2);
+    }
+}
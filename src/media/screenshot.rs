@@ -1,12 +1,33 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use image::{ImageBuffer, Rgb, RgbImage};
 use std::path::Path;
+use unicode_width::UnicodeWidthChar;
 
-use super::{MediaConfig, ThemeConfig, MediaGenerator};
+use super::caption::{Caption, CaptionPosition};
+use super::{CursorStyle, MediaConfig, Padding, ThemeConfig, MediaGenerator};
+use super::background::blend;
+use super::fonts::{rasterize, ChainFont, FontChain};
+use super::highlight::{CellColors, SyntaxHighlighter};
+use super::metadata::{save_image, PngMetadata};
+use crate::pty::ansi::strip_ansi;
+use crate::pty::capture::TerminalCapture;
+use crate::terminal::{CharAttributes, TerminalState};
+
+/// Synthetic font variant used when drawing a glyph
+#[derive(Debug, Clone, Copy, Default)]
+struct GlyphStyle {
+    /// Emboldened by drawing the glyph a second time one pixel to the right
+    bold: bool,
+    /// Slanted by shifting the upper half of the glyph one pixel to the right
+    italic: bool,
+}
 
 pub struct ScreenshotGenerator {
     config: MediaConfig,
     theme: ThemeConfig,
+    fonts: FontChain,
+    /// Embedded in PNGs written by `generate`
+    metadata: PngMetadata,
 }
 
 impl ScreenshotGenerator {
@@ -14,9 +35,17 @@ impl ScreenshotGenerator {
         Self {
             config: config.clone(),
             theme: theme.clone(),
+            fonts: FontChain::new(&config.font_chain()),
+            metadata: PngMetadata { theme: theme.name.clone(), timestamp: true, ..PngMetadata::default() },
         }
     }
     
+    /// Embed `metadata` in PNGs written by `generate`
+    pub fn with_metadata(mut self, metadata: PngMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+    
     pub fn generate(
         &self,
         content: &str,
@@ -24,15 +53,87 @@ impl ScreenshotGenerator {
         terminal_height: u16,
         output_path: &Path,
     ) -> Result<()> {
-        // Calculate image dimensions
-        let char_width = self.config.font_size as u32 * 6 / 10; // Approximate monospace width
-        let char_height = (self.config.font_size as f32 * self.config.line_height) as u32;
+        let mut state = self.content_state(content, terminal_width, terminal_height);
+        state.cursor_visible = false;
+        let image = self.render_screenshot(&state)?;
+        save_image(&image, output_path, &self.metadata)
+    }
+    
+    /// Render terminal content to an in-memory image
+    ///
+    /// The content is interpreted as terminal output, so escape sequences are
+    /// applied rather than drawn. No cursor is shown.
+    pub fn render(
+        &self,
+        content: &str,
+        terminal_width: u16,
+        terminal_height: u16,
+    ) -> Result<RgbImage> {
+        let mut state = self.content_state(content, terminal_width, terminal_height);
+        state.cursor_visible = false;
+        self.render_state(&state)
+    }
+    
+    /// The screen `content` leaves, with rows added to fit wrapped lines when `wrap_lines` is set
+    fn content_state(&self, content: &str, terminal_width: u16, terminal_height: u16) -> TerminalState {
+        let height = if self.config.wrap_lines {
+            terminal_height.max(wrapped_rows(content, terminal_width))
+        } else {
+            terminal_height
+        };
+        text_state(content, terminal_width, height)
+    }
+    
+    /// Render a still screenshot, cropped to its content with `autofit`
+    ///
+    /// Animation frames use `render_state` so every frame keeps one size.
+    pub fn render_screenshot(&self, state: &TerminalState) -> Result<RgbImage> {
+        if self.config.autofit {
+            self.render_state(&fit_to_content(state))
+        } else {
+            self.render_state(state)
+        }
+    }
+    
+    /// Size of one terminal cell in output pixels, after scaling
+    fn cell_size(&self) -> (u32, u32) {
+        let (width, height) = self.config.cell_size();
+        (self.config.scaled(width), self.config.scaled(height))
+    }
+    
+    /// Padding around the terminal in output pixels, after scaling
+    fn padding(&self) -> Padding {
+        let padding = self.config.padding_sides();
+        let scaled = |length: u32| if length == 0 { 0 } else { self.config.scaled(length) };
+        Padding {
+            top: scaled(padding.top),
+            right: scaled(padding.right),
+            bottom: scaled(padding.bottom),
+            left: scaled(padding.left),
+        }
+    }
+    
+    /// Width of the line-number gutter for a screen of `rows` rows, if enabled
+    ///
+    /// It fits the widest number plus one blank cell before the content.
+    fn gutter_width(&self, rows: u16) -> u32 {
+        if !self.config.line_numbers {
+            return 0;
+        }
+        let digits = rows.max(1).to_string().len() as u32;
+        (digits + 1) * self.cell_size().0
+    }
+    
+    /// Render a terminal screen snapshot, including its cursor if visible
+    pub fn render_state(&self, state: &TerminalState) -> Result<RgbImage> {
+        let (terminal_width, terminal_height) = (state.size.width, state.size.height);
         
-        let image_width = (terminal_width as u32 * char_width) + (self.config.padding as u32 * 2);
-        let image_height = (terminal_height as u32 * char_height) + (self.config.padding as u32 * 2);
+        // Calculate image dimensions
+        let (char_width, char_height) = self.cell_size();
         
-        // Create image
-        let mut image: RgbImage = ImageBuffer::new(image_width, image_height);
+        let padding = self.padding();
+        let image_width = (terminal_width as u32 * char_width) + padding.horizontal() + self.gutter_width(terminal_height);
+        let image_height = (terminal_height as u32 * char_height) + padding.vertical();
         
         // Fill background
         let bg_color = Rgb([
@@ -41,56 +142,331 @@ impl ScreenshotGenerator {
             self.theme.background.2,
         ]);
         
-        for pixel in image.pixels_mut() {
-            *pixel = bg_color;
-        }
+        let mut image: RgbImage = match &self.config.background {
+            Some(background) => {
+                let mut image = background.render(image_width, image_height)?;
+                
+                // Draw the terminal panel over the backdrop
+                let panel_right = image_width - padding.right;
+                let panel_bottom = image_height - padding.bottom;
+                for y in padding.top..panel_bottom {
+                    for x in padding.left..panel_right {
+                        let pixel = image.get_pixel_mut(x, y);
+                        *pixel = blend(*pixel, bg_color, self.config.panel_opacity);
+                    }
+                }
+                image
+            }
+            None => ImageBuffer::from_pixel(image_width, image_height, bg_color),
+        };
         
         // Render text (simplified - in a real implementation, we'd need proper font rendering)
-        self.render_terminal_content(&mut image, content, terminal_width, terminal_height)?;
+        self.render_line_numbers(&mut image, state);
+        self.render_terminal_content(&mut image, state)?;
+        self.render_cursor(&mut image, state);
+        
+        Ok(image)
+    }
+    
+    /// Draw right-aligned row numbers, starting at 1, in a dimmed foreground
+    fn render_line_numbers(&self, image: &mut RgbImage, state: &TerminalState) {
+        let gutter = self.gutter_width(state.size.height);
+        if gutter == 0 {
+            return;
+        }
+        let (char_width, char_height) = self.cell_size();
+        let padding = self.padding();
+        let foreground = Rgb([self.theme.foreground.0, self.theme.foreground.1, self.theme.foreground.2]);
+        let background = Rgb([self.theme.background.0, self.theme.background.1, self.theme.background.2]);
+        let color = blend(background, foreground, 0.5);
+        
+        for row in 0..state.size.height as u32 {
+            let number = (row + 1).to_string();
+            let y = padding.top + row * char_height;
+            // The last gutter cell stays blank to separate numbers from content
+            let mut x = padding.left + gutter - char_width * (number.len() as u32 + 1);
+            for digit in number.chars() {
+                self.draw_glyph(image, x, y, char_width, char_height, digit, color, GlyphStyle::default());
+                x += char_width;
+            }
+        }
+    }
+    
+    fn render_terminal_content(&self, image: &mut RgbImage, state: &TerminalState) -> Result<()> {
+        let highlights = match &self.config.syntax_highlight {
+            Some(language) => Some(SyntaxHighlighter::new(language)?.cell_colors(state)?),
+            None => None,
+        };
         
-        // Save image
-        image.save(output_path)
-            .with_context(|| format!("Failed to save screenshot to: {}", output_path.display()))?;
+        for line_idx in 0..state.buffer.len() {
+            self.render_row(image, state, highlights.as_ref(), line_idx);
+        }
         
         Ok(())
     }
     
-    fn render_terminal_content(
-        &self,
-        image: &mut RgbImage,
-        content: &str,
-        terminal_width: u16,
-        terminal_height: u16,
-    ) -> Result<()> {
-        // This is a simplified text rendering
-        // In a production implementation, you'd use a proper font rendering library
-        // like rusttype or fontdue to render actual text
-        
-        let lines: Vec<&str> = content.lines().collect();
-        let char_width = self.config.font_size as u32 * 6 / 10;
-        let char_height = (self.config.font_size as f32 * self.config.line_height) as u32;
-        
-        let text_color = Rgb([
-            self.theme.foreground.0,
-            self.theme.foreground.1,
-            self.theme.foreground.2,
-        ]);
+    /// Draw the text, cell backgrounds and underlines of one row
+    fn render_row(&self, image: &mut RgbImage, state: &TerminalState, highlights: Option<&CellColors>, line_idx: usize) {
+        let Some(row) = state.buffer.get(line_idx) else {
+            return;
+        };
+        let (char_width, char_height) = self.cell_size();
+        let padding = self.padding();
+        let content_left = padding.left + self.gutter_width(state.size.height);
+        let y_offset = padding.top + (line_idx as u32 * char_height);
         
-        for (line_idx, line) in lines.iter().enumerate().take(terminal_height as usize) {
-            let y_offset = self.config.padding as u32 + (line_idx as u32 * char_height);
+        for (char_idx, cell) in row.iter().enumerate() {
+            if cell.continuation {
+                continue;
+            }
+            let x_offset = content_left + (char_idx as u32 * char_width);
+            let cell_width = char_width * cell.width() as u32;
+            let (mut text_color, background) = self.cell_colors(&cell.attrs);
             
-            for (char_idx, _ch) in line.chars().enumerate().take(terminal_width as usize) {
-                let x_offset = self.config.padding as u32 + (char_idx as u32 * char_width);
-                
-                // Simple character rendering (just a colored rectangle for now)
-                // In real implementation, render actual glyphs
-                self.draw_char_placeholder(image, x_offset, y_offset, char_width, char_height, text_color);
+            // Highlighting only recolors text the program left in the default color
+            if cell.attrs.fg_color.is_none() && !cell.attrs.reverse {
+                let highlight = highlights.and_then(|rows| rows[line_idx][char_idx]);
+                if let Some((r, g, b)) = highlight {
+                    text_color = Rgb([r, g, b]);
+                }
+            }
+            
+            if let Some(background) = background {
+                fill_rect(image, x_offset, y_offset, cell_width, char_height, background);
+            }
+            
+            let mut underline = cell.attrs.underline;
+            if let Some((r, g, b)) = self.config.link_color {
+                if state.hyperlink_at(char_idx as u16, line_idx as u16).is_some() {
+                    text_color = Rgb([r, g, b]);
+                    underline = true;
+                }
+            }
+            
+            if !cell.ch.is_whitespace() {
+                let style = GlyphStyle { bold: cell.attrs.bold, italic: cell.attrs.italic };
+                self.draw_glyph(image, x_offset, y_offset, cell_width, char_height, cell.ch, text_color, style);
+            }
+            
+            if underline && char_height >= 2 {
+                fill_rect(image, x_offset, y_offset + char_height - 2, cell_width, 1, text_color);
             }
         }
+    }
+    
+    /// Bring `image`, a render of `previous`, up to date with `state`
+    ///
+    /// Only the rows that differ between the two screens are drawn again,
+    /// which is far cheaper than `render_state` when little changes between
+    /// frames. Backdrops and syntax highlighting depend on more than one row,
+    /// so with either set, or if the screen was resized, the whole image is
+    /// rendered again. Returns the number of rows drawn.
+    pub fn update_render(&self, image: &mut RgbImage, previous: &TerminalState, state: &TerminalState) -> Result<usize> {
+        let rows = match state.changed_rows(previous) {
+            Some(rows) if self.config.background.is_none() && self.config.syntax_highlight.is_none() => rows,
+            _ => {
+                *image = self.render_state(state)?;
+                return Ok(state.size.height as usize);
+            }
+        };
         
-        Ok(())
+        let (char_width, char_height) = self.cell_size();
+        let padding = self.padding();
+        let content_left = padding.left + self.gutter_width(state.size.height);
+        let content_width = state.size.width as u32 * char_width;
+        let background = Rgb([self.theme.background.0, self.theme.background.1, self.theme.background.2]);
+        
+        for &row in &rows {
+            let y = padding.top + row as u32 * char_height;
+            fill_rect(image, content_left, y, content_width, char_height, background);
+            self.render_row(image, state, None, row as usize);
+        }
+        if rows.contains(&state.cursor.y) {
+            self.render_cursor(image, state);
+        }
+        Ok(rows.len())
+    }
+    
+    /// Foreground color and, if the cell has its own, background color
+    ///
+    /// Reverse video swaps the two, so a reversed cell always has a background.
+    fn cell_colors(&self, attrs: &CharAttributes) -> (Rgb<u8>, Option<Rgb<u8>>) {
+        let resolve = |index: Option<u8>, default: (u8, u8, u8)| {
+            let (r, g, b) = index.map_or(default, |index| self.theme.ansi_color(index));
+            Rgb([r, g, b])
+        };
+        let foreground = resolve(attrs.fg_color, self.theme.foreground);
+        let background = resolve(attrs.bg_color, self.theme.background);
+        
+        if attrs.reverse {
+            (background, Some(foreground))
+        } else {
+            (foreground, attrs.bg_color.map(|_| background))
+        }
+    }
+    
+    /// Draw the cursor in the theme's cursor color, if the terminal shows one
+    fn render_cursor(&self, image: &mut RgbImage, state: &TerminalState) {
+        if !state.cursor_visible {
+            return;
+        }
+        let (x, y) = (state.cursor.x, state.cursor.y);
+        if x >= state.size.width || y >= state.size.height {
+            return;
+        }
+        
+        let (char_width, char_height) = self.cell_size();
+        let padding = self.padding();
+        let cell = state.get_char(x, y);
+        let cells = cell.map_or(1, |cell| cell.width()) as u32;
+        
+        let left = padding.left + self.gutter_width(state.size.height) + x as u32 * char_width;
+        let top = padding.top + y as u32 * char_height;
+        let width = char_width * cells;
+        let thickness = (char_width / 4).max(1);
+        
+        let cursor_color = Rgb([self.theme.cursor.0, self.theme.cursor.1, self.theme.cursor.2]);
+        let (rect_x, rect_y, rect_width, rect_height) = match self.config.cursor_style {
+            CursorStyle::Block => (left, top, width, char_height),
+            CursorStyle::Bar => (left, top, thickness, char_height),
+            CursorStyle::Underline => (left, top + char_height - thickness, width, thickness),
+        };
+        fill_rect(image, rect_x, rect_y, rect_width, rect_height, cursor_color);
+        
+        // A block cursor shows the character beneath it inverted
+        if self.config.cursor_style == CursorStyle::Block {
+            if let Some(cell) = cell.filter(|cell| !cell.ch.is_whitespace()) {
+                let background = Rgb([self.theme.background.0, self.theme.background.1, self.theme.background.2]);
+                let style = GlyphStyle { bold: cell.attrs.bold, italic: cell.attrs.italic };
+                self.draw_glyph(image, left, top, width, char_height, cell.ch, background, style);
+            }
+        }
+    }
+    
+    /// Draw a caption box over a rendered frame
+    ///
+    /// The box is inverted against the theme (foreground fill, background text),
+    /// centered horizontally, and lines that don't fit the frame are cut short.
+    pub fn draw_caption(&self, image: &mut RgbImage, caption: &Caption) {
+        let Some(text_box) = self.text_box(image, &caption.text) else {
+            return;
+        };
+        let padding = self.padding();
+        let pad_y = self.cell_size().1 / 2;
+        
+        let left = image.width().saturating_sub(text_box.width) / 2;
+        let top = match caption.position {
+            CaptionPosition::Top => padding.top + pad_y,
+            CaptionPosition::Bottom => image.height().saturating_sub(padding.bottom + pad_y + text_box.height),
+            CaptionPosition::Center => image.height().saturating_sub(text_box.height) / 2,
+        };
+        self.draw_text_box(image, &text_box, left, top);
+    }
+    
+    /// Draw `keys` in a badge at the bottom right of a frame, like a keystroke visualizer
+    pub fn draw_keystrokes(&self, image: &mut RgbImage, keys: &str) {
+        let Some(text_box) = self.text_box(image, keys) else {
+            return;
+        };
+        let padding = self.padding();
+        let (char_width, char_height) = self.cell_size();
+        
+        let left = image.width().saturating_sub(padding.right + char_width + text_box.width);
+        let top = image.height().saturating_sub(padding.bottom + char_height / 2 + text_box.height);
+        self.draw_text_box(image, &text_box, left, top);
+    }
+    
+    /// Lay out `text` in a box that fits the frame, cutting lines that don't
+    fn text_box(&self, image: &RgbImage, text: &str) -> Option<TextBox> {
+        let (char_width, char_height) = self.cell_size();
+        let (pad_x, pad_y) = (char_width, char_height / 2);
+        
+        let max_columns = (image.width().saturating_sub(2 * pad_x) / char_width) as usize;
+        let lines: Vec<Vec<(char, usize)>> = text
+            .lines()
+            .map(|line| {
+                let mut columns = 0;
+                line.chars()
+                    .filter_map(|ch| Some((ch, ch.width()?)))
+                    .take_while(|(_, width)| {
+                        columns += width;
+                        columns <= max_columns
+                    })
+                    .collect()
+            })
+            .collect();
+        let columns = lines
+            .iter()
+            .map(|line| line.iter().map(|(_, width)| width).sum::<usize>())
+            .max()
+            .unwrap_or(0) as u32;
+        if columns == 0 {
+            return None;
+        }
+        
+        Some(TextBox {
+            width: columns * char_width + 2 * pad_x,
+            height: lines.len() as u32 * char_height + 2 * pad_y,
+            lines,
+        })
     }
     
+    /// Fill `text_box` at (`left`, `top`), inverted against the theme, and write its text
+    fn draw_text_box(&self, image: &mut RgbImage, text_box: &TextBox, left: u32, top: u32) {
+        let (char_width, char_height) = self.cell_size();
+        let (pad_x, pad_y) = (char_width, char_height / 2);
+        
+        let fill = Rgb([self.theme.foreground.0, self.theme.foreground.1, self.theme.foreground.2]);
+        let text = Rgb([self.theme.background.0, self.theme.background.1, self.theme.background.2]);
+        fill_rect(image, left, top, text_box.width, text_box.height, fill);
+        
+        for (row, line) in text_box.lines.iter().enumerate() {
+            let y = top + pad_y + row as u32 * char_height;
+            let mut x = left + pad_x;
+            for &(ch, width) in line {
+                if !ch.is_whitespace() {
+                    self.draw_glyph(image, x, y, char_width * width as u32, char_height, ch, text, GlyphStyle::default());
+                }
+                x += char_width * width as u32;
+            }
+        }
+    }
+    
+    /// Draw `ch` from the first font in the chain that has it
+    ///
+    /// Installed fonts are antialiased over what is already in the cell; bold
+    /// text is drawn twice, a pixel apart. Italics only slant builtin glyphs.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph(
+        &self,
+        image: &mut RgbImage,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        ch: char,
+        color: Rgb<u8>,
+        style: GlyphStyle,
+    ) {
+        let ChainFont::Installed { font, .. } = self.fonts.font_for(ch) else {
+            self.draw_char_placeholder(image, x, y, width, height, ch, color, style);
+            return;
+        };
+        
+        let px = self.config.font_size as f32 * self.config.scale;
+        let offsets: &[u32] = if style.bold { &[0, 1] } else { &[0] };
+        for &offset in offsets {
+            rasterize(font, ch, px, x + offset, y, height, |px, py, coverage| {
+                // Keep each glyph inside its own cell
+                if px < x + width && py >= y && py < y + height && px < image.width() && py < image.height() {
+                    let pixel = image.get_pixel_mut(px, py);
+                    *pixel = blend(*pixel, color, coverage);
+                }
+            });
+        }
+    }
+    
+    #[allow(clippy::too_many_arguments)]
     fn draw_char_placeholder(
         &self,
         image: &mut RgbImage,
@@ -98,18 +474,110 @@ impl ScreenshotGenerator {
         y: u32,
         width: u32,
         height: u32,
+        ch: char,
         color: Rgb<u8>,
+        style: GlyphStyle,
     ) {
-        for dy in 0..height.min(4) { // Just draw a small rectangle as placeholder
-            for dx in 0..width.min(2) {
-                if x + dx < image.width() && y + dy < image.height() {
-                    image.put_pixel(x + dx, y + dy, color);
+        // Light a 2x4 bit pattern derived from the code point so that different
+        // characters produce different pixels; the first bit is always set.
+        // Each bit is a square dot, larger when rendering at a higher scale.
+        let code = ch as u32;
+        let pattern = ((code ^ (code >> 8) ^ (code >> 16)) as u8) | 1;
+        let dot = self.config.scaled(1);
+        
+        for bit in 0..8 {
+            if pattern & (1 << bit) == 0 {
+                continue;
+            }
+            let (dx, dy) = (bit % 2, bit / 2);
+            let dx = if style.italic && dy < 2 { dx + 1 } else { dx };
+            let strokes: &[u32] = if style.bold { &[dx, dx + 1] } else { &[dx] };
+            for &dx in strokes {
+                let (px, py) = (dx * dot, dy * dot);
+                if px < width && py < height {
+                    fill_rect(image, x + px, y + py, dot.min(width - px), dot.min(height - py), color);
                 }
             }
         }
     }
 }
 
+/// Text laid out for a caption or keystroke box, with the box's size in pixels
+struct TextBox {
+    lines: Vec<Vec<(char, usize)>>,
+    width: u32,
+    height: u32,
+}
+
+/// Fill a rectangle, clipped to the image bounds
+fn fill_rect(image: &mut RgbImage, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Interpret `content` as terminal output on a fresh screen of the given size
+///
+/// Bare `\n` line endings are treated as `\r\n`, so plain text lines up at the
+/// left edge as it would in a terminal with output post-processing.
+pub fn text_state(content: &str, width: u16, height: u16) -> TerminalState {
+    let mut capture = TerminalCapture::new(width, height);
+    let normalized = content.replace("\r\n", "\n").replace('\n', "\r\n");
+    capture.process_output(&normalized).ok();
+    capture.to_state()
+}
+
+/// How many rows `content` takes once the terminal wraps its lines at `width` columns
+fn wrapped_rows(content: &str, width: u16) -> u16 {
+    let width = usize::from(width.max(1));
+    let text = strip_ansi(content);
+    let lines: Vec<&str> = text.strip_suffix('\n').unwrap_or(&text).split('\n').collect();
+    let rows: usize = lines
+        .iter()
+        .map(|line| {
+            let columns: usize = line.chars().map(|ch| ch.width().unwrap_or(0)).sum();
+            columns.div_ceil(width).max(1)
+        })
+        .sum();
+    rows.min(usize::from(u16::MAX)) as u16
+}
+
+/// Crop `state` to the rows and columns its content and visible cursor use
+///
+/// A cell counts as content if it has text, a background or an underline.
+/// At least one cell is always kept.
+pub fn fit_to_content(state: &TerminalState) -> TerminalState {
+    let (mut width, mut height) = (1, 1);
+    for (y, row) in state.buffer.iter().enumerate() {
+        let used = row.iter().rposition(|cell| {
+            !cell.ch.is_whitespace()
+                || cell.continuation
+                || cell.attrs.bg_color.is_some()
+                || cell.attrs.reverse
+                || cell.attrs.underline
+        });
+        if let Some(x) = used {
+            width = width.max(x + 1);
+            height = y + 1;
+        }
+    }
+    if state.cursor_visible && state.cursor.x < state.size.width && state.cursor.y < state.size.height {
+        width = width.max(state.cursor.x as usize + 1);
+        height = height.max(state.cursor.y as usize + 1);
+    }
+    
+    let mut fitted = state.clone();
+    fitted.buffer.truncate(height);
+    for row in &mut fitted.buffer {
+        row.truncate(width);
+    }
+    fitted.size.width = width as u16;
+    fitted.size.height = height as u16;
+    fitted
+}
+
 impl MediaGenerator for ScreenshotGenerator {
     fn create_output(&self, content: &str, output_path: &Path) -> Result<()> {
         self.generate(content, 80, 24, output_path)
@@ -119,11 +587,12 @@ impl MediaGenerator for ScreenshotGenerator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terminal::{CursorPosition, TerminalChar, TerminalSize};
     use tempfile::NamedTempFile;
     
     #[test]
     fn test_screenshot_generation() {
-        let config = MediaConfig::default();
+        let config = MediaConfig::builtin_font().build();
         let theme = ThemeConfig::default_theme();
         let generator = ScreenshotGenerator::new(&config, &theme);
         
@@ -134,4 +603,391 @@ mod tests {
         
         assert!(temp_file.path().exists());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_generate_embeds_png_metadata() {
+        let theme = ThemeConfig::from_name("nord");
+        let metadata = PngMetadata {
+            commands: vec!["git status".to_string(), "ls -la".to_string()],
+            theme: theme.name.clone(),
+            timestamp: true,
+        };
+        let generator = ScreenshotGenerator::new(&MediaConfig::builtin_font().build(), &theme).with_metadata(metadata);
+        
+        let temp_file = NamedTempFile::with_suffix(".png").unwrap();
+        generator.generate("$ ls -la", 40, 4, temp_file.path()).unwrap();
+        
+        let reader = png::Decoder::new(std::fs::File::open(temp_file.path()).unwrap()).read_info().unwrap();
+        let text = |keyword: &str| {
+            reader.info().utf8_text.iter()
+                .find(|chunk| chunk.keyword == keyword)
+                .map(|chunk| chunk.get_text().unwrap())
+        };
+        assert_eq!(text("kla:commands").as_deref(), Some("git status\nls -la"));
+        assert_eq!(text("kla:theme").as_deref(), Some(theme.name.as_str()));
+        assert!(text("Software").unwrap().starts_with("KLA "));
+        assert!(text("Creation Time").unwrap().ends_with('Z'));
+    }
+    
+    #[test]
+    fn test_asymmetric_padding() {
+        let config = MediaConfig {
+            padding_top: Some(5),
+            padding_right: Some(10),
+            padding_bottom: Some(50),
+            padding_left: Some(30),
+            ..MediaConfig::builtin_font().build()
+        };
+        let theme = ThemeConfig::default_theme();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        
+        let image = generator.render("A", 10, 2).unwrap();
+        let char_width = config.font_size as u32 * 6 / 10;
+        let char_height = (config.font_size as f32 * config.line_height) as u32;
+        
+        assert_eq!(image.width(), 10 * char_width + 30 + 10);
+        assert_eq!(image.height(), 2 * char_height + 5 + 50);
+        
+        // The first glyph starts at the top-left content corner
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        assert_eq!(*image.get_pixel(30, 5), foreground);
+        assert_eq!(*image.get_pixel(29, 5), background);
+        assert_eq!(*image.get_pixel(30, 4), background);
+    }
+    
+    #[test]
+    fn test_panel_over_gradient_backdrop() {
+        let config = MediaConfig {
+            padding: 10,
+            background: Some(crate::media::Background::LinearGradient {
+                from: (255, 255, 255),
+                to: (0, 0, 0),
+                angle: 0.0,
+            }),
+            panel_opacity: 1.0,
+            ..MediaConfig::builtin_font().build()
+        };
+        let theme = ThemeConfig::default_theme();
+        let image = ScreenshotGenerator::new(&config, &theme).render("", 10, 2).unwrap();
+        
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        assert_eq!(*image.get_pixel(0, 0), Rgb([255, 255, 255]));
+        assert_eq!(*image.get_pixel(10, 10), background);
+        assert_ne!(*image.get_pixel(5, 5), background);
+    }
+    
+    #[test]
+    fn test_letter_spacing_widens_cells() {
+        let theme = ThemeConfig::default_theme();
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        
+        let tight = MediaConfig::builtin_font().build();
+        let loose = MediaConfig::builtin_font().letter_spacing(4.0).build();
+        let (tight_width, _) = tight.cell_size();
+        let (loose_width, _) = loose.cell_size();
+        assert_eq!(loose_width, tight_width + 4);
+        
+        let tight_image = ScreenshotGenerator::new(&tight, &theme).render("xyz", 10, 1).unwrap();
+        let loose_image = ScreenshotGenerator::new(&loose, &theme).render("xyz", 10, 1).unwrap();
+        assert_eq!(loose_image.width(), tight_image.width() + 10 * 4);
+        
+        // Each glyph's first pixel moves right by `letter_spacing` per preceding column
+        let padding = tight.padding as u32;
+        for column in 0..3 {
+            assert_eq!(*tight_image.get_pixel(padding + column * tight_width, padding), foreground);
+            assert_eq!(*loose_image.get_pixel(padding + column * loose_width, padding), foreground);
+        }
+        assert_eq!(*loose_image.get_pixel(padding + 2 * tight_width, padding), background);
+    }
+    
+    fn cursor_pixels(image: &RgbImage, theme: &ThemeConfig) -> Vec<(u32, u32)> {
+        let cursor = Rgb([theme.cursor.0, theme.cursor.1, theme.cursor.2]);
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == cursor)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+    
+    #[test]
+    fn test_cursor_drawn_only_when_visible() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        let (char_width, char_height) = config.cell_size();
+        let padding = config.padding as u32;
+        
+        let mut state = text_state("$ ", 10, 3);
+        assert_eq!(state.cursor, crate::terminal::CursorPosition::new(2, 0));
+        
+        let image = generator.render_state(&state).unwrap();
+        let pixels = cursor_pixels(&image, &theme);
+        let left = padding + 2 * char_width;
+        assert_eq!(pixels.len() as u32, char_width * char_height);
+        assert!(pixels.iter().all(|(x, y)| (left..left + char_width).contains(x)
+            && (padding..padding + char_height).contains(y)));
+        
+        state.cursor_visible = false;
+        let image = generator.render_state(&state).unwrap();
+        assert!(cursor_pixels(&image, &theme).is_empty());
+    }
+    
+    #[test]
+    fn test_bar_cursor_style() {
+        let config = MediaConfig::builtin_font().cursor_style(CursorStyle::Bar).build();
+        let theme = ThemeConfig::default_theme();
+        let (char_width, char_height) = config.cell_size();
+        
+        let image = ScreenshotGenerator::new(&config, &theme)
+            .render_state(&text_state("", 4, 1))
+            .unwrap();
+        let pixels = cursor_pixels(&image, &theme);
+        assert_eq!(pixels.len() as u32, (char_width / 4) * char_height);
+        assert!(pixels.iter().all(|(x, _)| *x < config.padding as u32 + char_width / 4));
+    }
+    
+    #[test]
+    fn test_underline_and_reverse_attributes() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        let (char_width, char_height) = config.cell_size();
+        let padding = config.padding as u32;
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        
+        let image = generator.render("\x1b[4mU\x1b[24mP\x1b[7mR\x1b[27m", 5, 1).unwrap();
+        let underline_y = padding + char_height - 2;
+        
+        // The underlined cell gets a full-width rule below the glyph; the plain cell doesn't
+        for x in padding..padding + char_width {
+            assert_eq!(*image.get_pixel(x, underline_y), foreground);
+        }
+        assert_eq!(*image.get_pixel(padding + char_width + 4, underline_y), background);
+        
+        // The reversed cell is filled with the foreground color
+        let reverse_left = padding + 2 * char_width;
+        assert_eq!(*image.get_pixel(reverse_left + char_width - 1, padding + char_height - 1), foreground);
+        assert_eq!(*image.get_pixel(reverse_left, padding), background);
+    }
+    
+    #[test]
+    fn test_bold_and_italic_change_glyph_pixels() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        
+        let plain = generator.render("A", 2, 1).unwrap();
+        let bold = generator.render("\x1b[1mA", 2, 1).unwrap();
+        let italic = generator.render("\x1b[3mA", 2, 1).unwrap();
+        
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        let lit = |image: &RgbImage| image.pixels().filter(|pixel| **pixel == foreground).count();
+        assert!(lit(&bold) > lit(&plain));
+        assert_ne!(italic, plain);
+        
+        // Italic shifts the top of the glyph right
+        let padding = config.padding as u32;
+        assert_eq!(*italic.get_pixel(padding + 1, padding), foreground);
+    }
+    
+    #[test]
+    fn test_caption_drawn_in_requested_region() {
+        let config = MediaConfig::builtin_font().build();
+        let theme = ThemeConfig::default_theme();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        
+        // With an empty screen, the only foreground pixels are the caption box
+        let caption_rows = |position| {
+            let mut image = generator.render("", 40, 10).unwrap();
+            generator.draw_caption(&mut image, &Caption::new("Step one: install", position));
+            let rows: Vec<u32> = image
+                .enumerate_pixels()
+                .filter(|(_, _, pixel)| **pixel == foreground)
+                .map(|(_, y, _)| y)
+                .collect();
+            (rows, image.height())
+        };
+        
+        let (top, height) = caption_rows(CaptionPosition::Top);
+        assert!(!top.is_empty());
+        assert!(top.iter().all(|y| *y < height / 3));
+        
+        let (bottom, height) = caption_rows(CaptionPosition::Bottom);
+        assert!(!bottom.is_empty());
+        assert!(bottom.iter().all(|y| *y > height * 2 / 3));
+        
+        let (center, height) = caption_rows(CaptionPosition::Center);
+        assert!(center.iter().all(|y| (height / 3..height * 2 / 3).contains(y)));
+    }
+    
+    #[test]
+    fn test_scale_doubles_dimensions() {
+        let theme = ThemeConfig::default_theme();
+        let normal = MediaConfig::builtin_font().build();
+        let retina = MediaConfig::builtin_font().scale(2.0).build();
+        
+        let small = ScreenshotGenerator::new(&normal, &theme).render("$ ls -la", 20, 4).unwrap();
+        let large = ScreenshotGenerator::new(&retina, &theme).render("$ ls -la", 20, 4).unwrap();
+        assert_eq!(large.width(), small.width() * 2);
+        assert_eq!(large.height(), small.height() * 2);
+        
+        // Glyph dots grow with the image, so twice the size lights four times the pixels
+        let foreground = Rgb([theme.foreground.0, theme.foreground.1, theme.foreground.2]);
+        let lit = |image: &RgbImage| image.pixels().filter(|pixel| **pixel == foreground).count();
+        assert_eq!(lit(&large), lit(&small) * 4);
+    }
+    
+    #[test]
+    fn test_syntax_highlighted_render() {
+        let config = MediaConfig::builtin_font().syntax_highlight("rust").build();
+        let theme = ThemeConfig::default_theme();
+        let image = ScreenshotGenerator::new(&config, &theme)
+            .render("let s = \"hi\";", 20, 1)
+            .unwrap();
+        
+        // Every glyph lights its cell's top-left pixel: compare `l` with `h` inside the string
+        let (char_width, _) = config.cell_size();
+        let padding = config.padding as u32;
+        let keyword = *image.get_pixel(padding, padding);
+        let string = *image.get_pixel(padding + 9 * char_width, padding);
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        
+        assert_ne!(keyword, background);
+        assert_ne!(string, background);
+        assert_ne!(keyword, string);
+    }
+    
+    #[test]
+    fn test_font_chain_covers_mixed_scripts() {
+        let config = MediaConfig::builder().font("DejaVu Sans Mono, 'Noto Sans CJK SC'").build();
+        assert_eq!(config.font_chain(), vec!["DejaVu Sans Mono", "Noto Sans CJK SC"]);
+        
+        // Without an emoji font in the chain, emoji get the builtin placeholder glyphs
+        let chain = FontChain::new(&config.font_chain());
+        assert_eq!(chain.names().last(), Some(&"builtin"));
+        assert_eq!(chain.font_for('🚀').name(), "builtin");
+        
+        let theme = ThemeConfig::default_theme();
+        let image = ScreenshotGenerator::new(&config, &theme).render("A中🚀", 6, 1).unwrap();
+        let (char_width, char_height) = config.cell_size();
+        let padding = config.padding as u32;
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        
+        // `A` takes one cell; the CJK character and the emoji take two each
+        for (column, cells) in [(0, 1), (1, 2), (3, 2)] {
+            let left = padding + column * char_width;
+            let lit = (left..left + cells * char_width)
+                .flat_map(|x| (padding..padding + char_height).map(move |y| (x, y)))
+                .filter(|&(x, y)| *image.get_pixel(x, y) != background)
+                .count();
+            assert!(lit > 0, "no glyph pixels in column {}", column);
+        }
+    }
+    
+    #[test]
+    fn test_line_number_gutter() {
+        let theme = ThemeConfig::default_theme();
+        let plain_config = MediaConfig::builtin_font().build();
+        let numbered_config = MediaConfig::builtin_font().line_numbers(true).build();
+        
+        let plain = ScreenshotGenerator::new(&plain_config, &theme).render("one\ntwo\nthree", 20, 12).unwrap();
+        let numbered = ScreenshotGenerator::new(&numbered_config, &theme).render("one\ntwo\nthree", 20, 12).unwrap();
+        
+        // Two digits for 12 rows, plus a blank separator cell
+        let (char_width, char_height) = numbered_config.cell_size();
+        let gutter = 3 * char_width;
+        assert_eq!(numbered.width(), plain.width() + gutter);
+        assert_eq!(numbered.height(), plain.height());
+        
+        // "1" sits in the gutter cell just before the separator on the first row
+        let padding = numbered_config.padding as u32;
+        let background = Rgb([theme.background.0, theme.background.1, theme.background.2]);
+        let one_left = padding + char_width;
+        let lit_in = |left: u32, top: u32| {
+            (left..left + char_width)
+                .flat_map(|x| (top..top + char_height).map(move |y| (x, y)))
+                .any(|(x, y)| *numbered.get_pixel(x, y) != background)
+        };
+        assert!(lit_in(one_left, padding));
+        assert!(!lit_in(padding, padding), "single digits are right-aligned");
+        assert!(!lit_in(padding + 2 * char_width, padding), "separator cell is blank");
+        
+        // Content moves right by the gutter width
+        let crop = |image: &RgbImage, left: u32| {
+            image::imageops::crop_imm(image, left, padding, 20 * char_width, 3 * char_height).to_image()
+        };
+        assert_eq!(crop(&numbered, padding + gutter), crop(&plain, padding));
+    }
+    
+    #[test]
+    fn test_wrap_lines_grows_height() {
+        let theme = ThemeConfig::default_theme();
+        let config = MediaConfig::builtin_font().wrap_lines(true).build();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        let line = format!("{}{}{}", "a".repeat(80), "b".repeat(80), "c".repeat(40));
+        
+        let state = generator.content_state(&line, 80, 2);
+        assert_eq!(state.size.height, 3);
+        assert_eq!(state.get_line_text(0), Some("a".repeat(80)));
+        assert_eq!(state.get_line_text(1), Some("b".repeat(80)));
+        assert_eq!(state.get_line_text(2), Some("c".repeat(40)));
+        
+        let (_, char_height) = config.cell_size();
+        let wrapped = generator.render(&line, 80, 2).unwrap();
+        let clipped = ScreenshotGenerator::new(&MediaConfig::builtin_font().build(), &theme).render(&line, 80, 2).unwrap();
+        assert_eq!(wrapped.height(), clipped.height() + char_height);
+    }
+    
+    #[test]
+    fn test_update_render_redraws_only_changed_rows() {
+        let theme = ThemeConfig::default_theme();
+        let config = MediaConfig::builtin_font().build();
+        let generator = ScreenshotGenerator::new(&config, &theme);
+        
+        // A full screen where each frame changes one cell and moves the cursor
+        let mut first = TerminalState::new(TerminalSize::new(80, 24));
+        for _ in 0..80 * 24 - 1 {
+            first.put_char('#');
+        }
+        let mut states = vec![first];
+        for frame in 1..30u16 {
+            let mut next = states[states.len() - 1].clone();
+            next.set_char(frame % 80, frame % 24, TerminalChar::new('.'));
+            next.cursor = CursorPosition::new(frame % 80, frame % 24);
+            states.push(next);
+        }
+        
+        let full: Vec<RgbImage> = states.iter().map(|state| generator.render_state(state).unwrap()).collect();
+        
+        let mut image = full[0].clone();
+        let mut incremental = vec![image.clone()];
+        for pair in states.windows(2) {
+            // The row with the new dot and the row the cursor left
+            assert_eq!(generator.update_render(&mut image, &pair[0], &pair[1]).unwrap(), 2);
+            incremental.push(image.clone());
+        }
+        
+        assert!(incremental == full, "incremental frames differ from full renders");
+    }
+    
+    #[test]
+    fn test_autofit_crops_to_output() {
+        let theme = ThemeConfig::default_theme();
+        let config = MediaConfig::builtin_font().autofit(true).build();
+        let mut state = text_state("$ echo hi\nhi\n$ ", 80, 24);
+        state.cursor_visible = true;
+        
+        let image = ScreenshotGenerator::new(&config, &theme).render_screenshot(&state).unwrap();
+        
+        // "$ echo hi" is the widest row; the cursor after the last prompt adds the third row
+        let (char_width, char_height) = config.cell_size();
+        let padding = config.padding as u32 * 2;
+        assert_eq!(image.width(), 9 * char_width + padding);
+        assert_eq!(image.height(), 3 * char_height + padding);
+        assert!(image.width() * image.height() * 20 < (80 * char_width + padding) * (24 * char_height + padding));
+    }
+}
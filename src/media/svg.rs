@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+use super::fonts::BUILTIN_FONT;
+use super::{MediaConfig, ThemeConfig};
+use crate::terminal::TerminalState;
+
+/// Text on one line of a frame, and the hyperlink it belongs to
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    text: String,
+    link: Option<String>,
+}
+
+/// Records terminal frames and renders them as an animated SVG
+///
+/// Frames are stacked vertically inside a clipped viewport and a stepped CSS
+/// animation scrolls through them, in the style of svg-term.
+pub struct SvgRecorder {
+    frames: Vec<Vec<Vec<Run>>>,
+    width: u16,
+    height: u16,
+    config: MediaConfig,
+    theme: ThemeConfig,
+}
+
+impl SvgRecorder {
+    pub fn new(config: &MediaConfig, theme: &ThemeConfig, width: u16, height: u16) -> Self {
+        Self {
+            frames: Vec::new(),
+            width,
+            height,
+            config: config.clone(),
+            theme: theme.clone(),
+        }
+    }
+
+    /// Capture a frame of the screen; hyperlinked cells become clickable links
+    pub fn capture_state(&mut self, state: &TerminalState) {
+        let lines = (0..state.size.height)
+            .map(|y| {
+                let mut line: Vec<Run> = Vec::new();
+                for x in 0..state.size.width {
+                    let Some(cell) = state.get_char(x, y).filter(|cell| !cell.continuation) else {
+                        continue;
+                    };
+                    let link = state.hyperlink_at(x, y).map(|link| link.uri.clone());
+                    match line.last_mut() {
+                        Some(run) if run.link == link => run.text.push(cell.ch),
+                        _ => line.push(Run { text: cell.ch.to_string(), link }),
+                    }
+                }
+                if let Some(run) = line.last_mut().filter(|run| run.link.is_none()) {
+                    run.text.truncate(run.text.trim_end().len());
+                }
+                line.retain(|run| !run.text.is_empty());
+                line
+            })
+            .collect();
+        self.frames.push(lines);
+    }
+
+    pub fn render(&self, frame_delay: Duration) -> Result<String> {
+        if self.frames.is_empty() {
+            return Err(anyhow::anyhow!("No frames to save"));
+        }
+
+        let char_width = self.config.font_size as f32 * 0.6 + self.config.letter_spacing;
+        let line_height = self.config.font_size as f32 * self.config.line_height;
+        let padding = self.config.padding_sides();
+
+        let screen_width = self.width as f32 * char_width;
+        let screen_height = self.height as f32 * line_height;
+        let image_width = screen_width + padding.horizontal() as f32;
+        let image_height = screen_height + padding.vertical() as f32;
+
+        let total = frame_delay.as_secs_f32() * self.frames.len() as f32;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{image_width}" height="{image_height}" viewBox="0 0 {image_width} {image_height}">"#
+        )?;
+        writeln!(svg, "<style>")?;
+        writeln!(
+            svg,
+            ".screen {{ font-family: {}monospace; font-size: {}px; letter-spacing: {}px; fill: {}; white-space: pre; }}",
+            self.config
+                .font_chain()
+                .iter()
+                .filter(|family| !family.eq_ignore_ascii_case(BUILTIN_FONT))
+                .map(|family| format!("'{}', ", escape_xml(family)))
+                .collect::<String>(),
+            self.config.font_size,
+            self.config.letter_spacing,
+            hex_color(self.theme.foreground),
+        )?;
+        if self.frames.len() > 1 {
+            writeln!(
+                svg,
+                ".frames {{ animation: roll {total:.3}s steps(1, end) infinite; }}"
+            )?;
+            writeln!(svg, "@keyframes roll {{")?;
+            for index in 0..self.frames.len() {
+                let percent = index as f32 / self.frames.len() as f32 * 100.0;
+                writeln!(
+                    svg,
+                    "  {percent:.3}% {{ transform: translateY({:.2}px); }}",
+                    -(index as f32) * screen_height
+                )?;
+            }
+            writeln!(svg, "}}")?;
+        }
+        writeln!(svg, "</style>")?;
+
+        writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            hex_color(self.theme.background)
+        )?;
+        writeln!(svg, "<defs><clipPath id=\"viewport\"><rect width=\"{screen_width}\" height=\"{screen_height}\"/></clipPath></defs>")?;
+        writeln!(
+            svg,
+            r#"<g transform="translate({} {})" clip-path="url(#viewport)">"#,
+            padding.left,
+            padding.top
+        )?;
+        writeln!(svg, r#"<g class="frames">"#)?;
+
+        for (index, lines) in self.frames.iter().enumerate() {
+            let frame_top = index as f32 * screen_height;
+            writeln!(svg, r#"<g class="screen">"#)?;
+            for (line_idx, line) in lines.iter().enumerate() {
+                if line.iter().all(|run| run.text.trim().is_empty()) {
+                    continue;
+                }
+                let baseline = frame_top + (line_idx as f32 + 1.0) * line_height;
+                let mut text = String::new();
+                for run in line {
+                    match &run.link {
+                        Some(uri) => write!(text, r#"<a href="{}">{}</a>"#, escape_xml(uri), escape_xml(&run.text))?,
+                        None => text.push_str(&escape_xml(&run.text)),
+                    }
+                }
+                writeln!(svg, r#"<text x="0" y="{baseline:.2}" xml:space="preserve">{}</text>"#, text)?;
+            }
+            writeln!(svg, "</g>")?;
+        }
+
+        writeln!(svg, "</g>")?;
+        writeln!(svg, "</g>")?;
+        writeln!(svg, "</svg>")?;
+
+        Ok(svg)
+    }
+
+    pub fn save_svg(&self, output_path: &Path, frame_delay: Duration) -> Result<()> {
+        let svg = self.render(frame_delay)?;
+        std::fs::write(output_path, svg)
+            .with_context(|| format!("Failed to write SVG file: {}", output_path.display()))?;
+
+        log::info!("SVG saved to: {}", output_path.display());
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+pub(super) fn hex_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::screenshot::text_state;
+
+    #[test]
+    fn test_svg_is_well_formed() {
+        let config = MediaConfig::default();
+        let theme = ThemeConfig::dracula_theme();
+        let mut recorder = SvgRecorder::new(&config, &theme, 80, 24);
+
+        recorder.capture_state(&text_state("$ echo <hello> & \x1b[32mbye\x1b[0m", 80, 24));
+        recorder.capture_state(&text_state("$ echo <hello> & bye\n<hello> & bye", 80, 24));
+
+        let svg = recorder.render(Duration::from_millis(500)).unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+
+        let texts: Vec<&str> = doc
+            .descendants()
+            .filter(|node| node.has_tag_name("text"))
+            .filter_map(|node| node.text())
+            .collect();
+        assert!(texts.contains(&"$ echo <hello> & bye"));
+        assert!(texts.contains(&"<hello> & bye"));
+        assert!(svg.contains("#282a36"));
+        assert!(svg.contains("@keyframes roll"));
+    }
+
+    #[test]
+    fn test_frames_show_the_screen_not_the_raw_stream() {
+        let config = MediaConfig::default();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = SvgRecorder::new(&config, &theme, 20, 3);
+
+        recorder.capture_state(&text_state("stale\x1b[2J\x1b[Hfresh\rF", 20, 3));
+
+        let svg = recorder.render(Duration::from_millis(500)).unwrap();
+        assert!(svg.contains(">Fresh</text>"), "{}", svg);
+        assert!(!svg.contains("stale"), "{}", svg);
+    }
+
+    #[test]
+    fn test_osc8_hyperlinks_become_anchors() {
+        let config = MediaConfig::default();
+        let theme = ThemeConfig::default_theme();
+        let mut recorder = SvgRecorder::new(&config, &theme, 80, 24);
+
+        recorder.capture_state(&text_state(
+            "see \x1b]8;;https://example.com/docs?a=1&b=2\x1b\\the docs\x1b]8;;\x1b\\ now",
+            80,
+            24,
+        ));
+
+        let svg = recorder.render(Duration::from_millis(500)).unwrap();
+        let doc = roxmltree::Document::parse(&svg).unwrap();
+        let anchor = doc.descendants().find(|node| node.has_tag_name("a")).unwrap();
+        assert_eq!(anchor.attribute("href"), Some("https://example.com/docs?a=1&b=2"));
+        assert_eq!(anchor.text(), Some("the docs"));
+
+        let line: String = anchor.parent().unwrap().descendants().filter(|node| node.is_text()).filter_map(|node| node.text()).collect();
+        assert_eq!(line, "see the docs now");
+    }
+}
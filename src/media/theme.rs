@@ -0,0 +1,560 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Names of all builtin themes, as accepted by `ThemeConfig::from_name`
+const BUILTIN_THEMES: &[&str] = &[
+    "default",
+    "dracula",
+    "nord",
+    "solarized-dark",
+    "gruvbox",
+    "monokai",
+    "tokyo-night",
+];
+
+#[derive(Clone)]
+pub struct ThemeConfig {
+    pub name: String,
+    pub background: (u8, u8, u8),
+    pub foreground: (u8, u8, u8),
+    pub cursor: (u8, u8, u8),
+    pub selection: (u8, u8, u8),
+    pub colors: Vec<(u8, u8, u8)>, // ANSI colors (16 colors)
+}
+
+impl ThemeConfig {
+    pub fn default_theme() -> Self {
+        Self {
+            name: "Default".to_string(),
+            background: (40, 44, 52),
+            foreground: (171, 178, 191),
+            cursor: (97, 175, 239),
+            selection: (75, 81, 96),
+            colors: vec![
+                (40, 44, 52),    // Black
+                (224, 108, 117), // Red
+                (152, 195, 121), // Green
+                (229, 192, 123), // Yellow
+                (97, 175, 239),  // Blue
+                (198, 120, 221), // Magenta
+                (86, 182, 194),  // Cyan
+                (171, 178, 191), // White
+                (92, 99, 112),   // Bright Black
+                (224, 108, 117), // Bright Red
+                (152, 195, 121), // Bright Green
+                (229, 192, 123), // Bright Yellow
+                (97, 175, 239),  // Bright Blue
+                (198, 120, 221), // Bright Magenta
+                (86, 182, 194),  // Bright Cyan
+                (255, 255, 255), // Bright White
+            ],
+        }
+    }
+    
+    pub fn dracula_theme() -> Self {
+        Self {
+            name: "Dracula".to_string(),
+            background: (40, 42, 54),
+            foreground: (248, 248, 242),
+            cursor: (248, 248, 242),
+            selection: (68, 71, 90),
+            colors: vec![
+                (40, 42, 54),    // Black
+                (255, 85, 85),   // Red
+                (80, 250, 123),  // Green
+                (241, 250, 140), // Yellow
+                (139, 233, 253), // Blue
+                (255, 121, 198), // Magenta
+                (139, 233, 253), // Cyan
+                (248, 248, 242), // White
+                (98, 114, 164),  // Bright Black
+                (255, 85, 85),   // Bright Red
+                (80, 250, 123),  // Bright Green
+                (241, 250, 140), // Bright Yellow
+                (139, 233, 253), // Bright Blue
+                (255, 121, 198), // Bright Magenta
+                (139, 233, 253), // Bright Cyan
+                (255, 255, 255), // Bright White
+            ],
+        }
+    }
+    
+    pub fn nord_theme() -> Self {
+        Self {
+            name: "Nord".to_string(),
+            background: (46, 52, 64),
+            foreground: (216, 222, 233),
+            cursor: (216, 222, 233),
+            selection: (67, 76, 94),
+            colors: vec![
+                (59, 66, 82),    // Black
+                (191, 97, 106),  // Red
+                (163, 190, 140), // Green
+                (235, 203, 139), // Yellow
+                (129, 161, 193), // Blue
+                (180, 142, 173), // Magenta
+                (136, 192, 208), // Cyan
+                (229, 233, 240), // White
+                (76, 86, 106),   // Bright Black
+                (191, 97, 106),  // Bright Red
+                (163, 190, 140), // Bright Green
+                (235, 203, 139), // Bright Yellow
+                (129, 161, 193), // Bright Blue
+                (180, 142, 173), // Bright Magenta
+                (143, 188, 187), // Bright Cyan
+                (236, 239, 244), // Bright White
+            ],
+        }
+    }
+    
+    pub fn solarized_dark_theme() -> Self {
+        Self {
+            name: "Solarized Dark".to_string(),
+            background: (0, 43, 54),
+            foreground: (131, 148, 150),
+            cursor: (147, 161, 161),
+            selection: (7, 54, 66),
+            colors: vec![
+                (7, 54, 66),     // Black
+                (220, 50, 47),   // Red
+                (133, 153, 0),   // Green
+                (181, 137, 0),   // Yellow
+                (38, 139, 210),  // Blue
+                (211, 54, 130),  // Magenta
+                (42, 161, 152),  // Cyan
+                (238, 232, 213), // White
+                (0, 43, 54),     // Bright Black
+                (203, 75, 22),   // Bright Red
+                (88, 110, 117),  // Bright Green
+                (101, 123, 131), // Bright Yellow
+                (131, 148, 150), // Bright Blue
+                (108, 113, 196), // Bright Magenta
+                (147, 161, 161), // Bright Cyan
+                (253, 246, 227), // Bright White
+            ],
+        }
+    }
+    
+    pub fn gruvbox_theme() -> Self {
+        Self {
+            name: "Gruvbox".to_string(),
+            background: (40, 40, 40),
+            foreground: (235, 219, 178),
+            cursor: (235, 219, 178),
+            selection: (80, 73, 69),
+            colors: vec![
+                (40, 40, 40),    // Black
+                (204, 36, 29),   // Red
+                (152, 151, 26),  // Green
+                (215, 153, 33),  // Yellow
+                (69, 133, 136),  // Blue
+                (177, 98, 134),  // Magenta
+                (104, 157, 106), // Cyan
+                (168, 153, 132), // White
+                (146, 131, 116), // Bright Black
+                (251, 73, 52),   // Bright Red
+                (184, 187, 38),  // Bright Green
+                (250, 189, 47),  // Bright Yellow
+                (131, 165, 152), // Bright Blue
+                (211, 134, 155), // Bright Magenta
+                (142, 192, 124), // Bright Cyan
+                (235, 219, 178), // Bright White
+            ],
+        }
+    }
+    
+    pub fn monokai_theme() -> Self {
+        Self {
+            name: "Monokai".to_string(),
+            background: (39, 40, 34),
+            foreground: (248, 248, 242),
+            cursor: (248, 248, 240),
+            selection: (73, 72, 62),
+            colors: vec![
+                (39, 40, 34),    // Black
+                (249, 38, 114),  // Red
+                (166, 226, 46),  // Green
+                (244, 191, 117), // Yellow
+                (102, 217, 239), // Blue
+                (174, 129, 255), // Magenta
+                (161, 239, 228), // Cyan
+                (248, 248, 242), // White
+                (117, 113, 94),  // Bright Black
+                (249, 38, 114),  // Bright Red
+                (166, 226, 46),  // Bright Green
+                (244, 191, 117), // Bright Yellow
+                (102, 217, 239), // Bright Blue
+                (174, 129, 255), // Bright Magenta
+                (161, 239, 228), // Bright Cyan
+                (249, 248, 245), // Bright White
+            ],
+        }
+    }
+    
+    pub fn tokyo_night_theme() -> Self {
+        Self {
+            name: "Tokyo Night".to_string(),
+            background: (26, 27, 38),
+            foreground: (192, 202, 245),
+            cursor: (192, 202, 245),
+            selection: (40, 52, 87),
+            colors: vec![
+                (21, 22, 30),    // Black
+                (247, 118, 142), // Red
+                (158, 206, 106), // Green
+                (224, 175, 104), // Yellow
+                (122, 162, 247), // Blue
+                (187, 154, 247), // Magenta
+                (125, 207, 255), // Cyan
+                (169, 177, 214), // White
+                (65, 72, 104),   // Bright Black
+                (247, 118, 142), // Bright Red
+                (158, 206, 106), // Bright Green
+                (224, 175, 104), // Bright Yellow
+                (122, 162, 247), // Bright Blue
+                (187, 154, 247), // Bright Magenta
+                (125, 207, 255), // Bright Cyan
+                (192, 202, 245), // Bright White
+            ],
+        }
+    }
+    
+    /// Resolve a theme name or theme file, falling back to the default theme
+    pub fn from_name(name: &str) -> Self {
+        Self::resolve(name).unwrap_or_else(|_| Self::default_theme())
+    }
+    
+    /// Look up a builtin theme, returning `None` for unknown names
+    ///
+    /// Names are case-insensitive and treat spaces, underscores and dashes alike,
+    /// so "Tokyo Night" and "tokyo_night" both match "tokyo-night".
+    pub fn try_from_name(name: &str) -> Option<Self> {
+        match normalize_name(name).as_str() {
+            "default" => Some(Self::default_theme()),
+            "dracula" => Some(Self::dracula_theme()),
+            "nord" => Some(Self::nord_theme()),
+            "solarized-dark" | "solarized" => Some(Self::solarized_dark_theme()),
+            "gruvbox" | "gruvbox-dark" => Some(Self::gruvbox_theme()),
+            "monokai" => Some(Self::monokai_theme()),
+            "tokyo-night" | "tokyonight" => Some(Self::tokyo_night_theme()),
+            _ => None,
+        }
+    }
+    
+    /// Look up a builtin theme, failing with a suggestion for unknown names
+    ///
+    /// Paths ending in `.toml` or `.itermcolors` are loaded as Alacritty and
+    /// iTerm2 color schemes instead.
+    pub fn resolve(name: &str) -> Result<Self> {
+        let path = Path::new(name);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => return Self::from_alacritty_toml(path),
+            Some("itermcolors") => return Self::from_iterm_colors(path),
+            _ => {}
+        }
+        
+        Self::try_from_name(name).ok_or_else(|| {
+            let mut message = format!("Unknown theme '{}'.", name);
+            if let Some(suggestion) = Self::closest_name(name) {
+                message.push_str(&format!(" Did you mean '{}'?", suggestion));
+            }
+            message.push_str(&format!(" Available themes: {}", BUILTIN_THEMES.join(", ")));
+            anyhow::anyhow!(message)
+        })
+    }
+    
+    /// Load a color scheme from an Alacritty TOML config
+    ///
+    /// Reads `colors.primary`, `colors.cursor`, `colors.selection`, `colors.normal`
+    /// and `colors.bright`; anything missing keeps the default theme's value.
+    pub fn from_alacritty_toml<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read Alacritty theme: {}", path.display()))?;
+        Self::parse_alacritty_toml(&content, &theme_name_from_path(path))
+            .with_context(|| format!("Failed to parse Alacritty theme: {}", path.display()))
+    }
+    
+    fn parse_alacritty_toml(content: &str, name: &str) -> Result<Self> {
+        let document: toml::Table = content.parse().context("Invalid TOML")?;
+        let colors = document
+            .get("colors")
+            .and_then(|value| value.as_table())
+            .ok_or_else(|| anyhow::anyhow!("No [colors] section found"))?;
+        
+        let lookup = |section: &str, key: &str| -> Result<Option<(u8, u8, u8)>> {
+            match colors.get(section).and_then(|table| table.get(key)) {
+                Some(toml::Value::String(value)) => parse_hex_color(value)
+                    .map(Some)
+                    .with_context(|| format!("Invalid color for colors.{}.{}", section, key)),
+                Some(_) => Err(anyhow::anyhow!("colors.{}.{} must be a string", section, key)),
+                None => Ok(None),
+            }
+        };
+        
+        let mut theme = Self::default_theme();
+        theme.name = name.to_string();
+        if let Some(color) = lookup("primary", "background")? {
+            theme.background = color;
+        }
+        if let Some(color) = lookup("primary", "foreground")? {
+            theme.foreground = color;
+        }
+        if let Some(color) = lookup("cursor", "cursor")? {
+            theme.cursor = color;
+        }
+        if let Some(color) = lookup("selection", "background")? {
+            theme.selection = color;
+        }
+        for (offset, section) in [(0, "normal"), (8, "bright")] {
+            for (index, key) in ANSI_COLOR_NAMES.iter().enumerate() {
+                if let Some(color) = lookup(section, key)? {
+                    theme.colors[offset + index] = color;
+                }
+            }
+        }
+        
+        Ok(theme)
+    }
+    
+    /// Load a color scheme from an iTerm2 `.itermcolors` property list
+    ///
+    /// Maps `Ansi 0 Color` through `Ansi 15 Color` onto the palette, plus the
+    /// background, foreground, cursor and selection colors.
+    pub fn from_iterm_colors<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read iTerm theme: {}", path.display()))?;
+        Self::parse_iterm_colors(&content, &theme_name_from_path(path))
+            .with_context(|| format!("Failed to parse iTerm theme: {}", path.display()))
+    }
+    
+    fn parse_iterm_colors(content: &[u8], name: &str) -> Result<Self> {
+        let entries: HashMap<String, ItermColor> =
+            plist::from_bytes(content).context("Invalid property list")?;
+        let lookup = |key: &str| entries.get(key).map(ItermColor::to_rgb);
+        
+        let mut theme = Self::default_theme();
+        theme.name = name.to_string();
+        if let Some(color) = lookup("Background Color") {
+            theme.background = color;
+        }
+        if let Some(color) = lookup("Foreground Color") {
+            theme.foreground = color;
+        }
+        if let Some(color) = lookup("Cursor Color") {
+            theme.cursor = color;
+        }
+        if let Some(color) = lookup("Selection Color") {
+            theme.selection = color;
+        }
+        for (index, slot) in theme.colors.iter_mut().enumerate() {
+            if let Some(color) = lookup(&format!("Ansi {} Color", index)) {
+                *slot = color;
+            }
+        }
+        
+        Ok(theme)
+    }
+    
+    /// RGB value of an indexed terminal color
+    ///
+    /// Indices 0-15 come from the theme palette; 16-231 are the xterm 6x6x6
+    /// color cube and 232-255 the grayscale ramp.
+    pub fn ansi_color(&self, index: u8) -> (u8, u8, u8) {
+        match index {
+            0..=15 => self.colors.get(index as usize).copied().unwrap_or(self.foreground),
+            16..=231 => {
+                let cube = index - 16;
+                let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+                (level(cube / 36), level((cube / 6) % 6), level(cube % 6))
+            }
+            232..=255 => {
+                let gray = 8 + (index - 232) * 10;
+                (gray, gray, gray)
+            }
+        }
+    }
+    
+    /// Names of all builtin themes
+    pub fn builtin_names() -> &'static [&'static str] {
+        BUILTIN_THEMES
+    }
+    
+    /// The builtin theme name most similar to `name`, if any is reasonably close
+    pub fn closest_name(name: &str) -> Option<&'static str> {
+        let name = normalize_name(name);
+        BUILTIN_THEMES
+            .iter()
+            .map(|candidate| (*candidate, strsim::jaro_winkler(&name, candidate)))
+            .filter(|(_, score)| *score >= 0.7)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(candidate, _)| candidate)
+    }
+}
+
+/// Key names of the eight base ANSI colors, in palette order
+const ANSI_COLOR_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// A color entry in an `.itermcolors` file, with components in 0.0..=1.0
+#[derive(serde::Deserialize)]
+struct ItermColor {
+    #[serde(rename = "Red Component")]
+    red: f64,
+    #[serde(rename = "Green Component")]
+    green: f64,
+    #[serde(rename = "Blue Component")]
+    blue: f64,
+}
+
+impl ItermColor {
+    fn to_rgb(&self) -> (u8, u8, u8) {
+        let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        (channel(self.red), channel(self.green), channel(self.blue))
+    }
+}
+
+/// Parse `#rrggbb` or `0xrrggbb`
+fn parse_hex_color(value: &str) -> Result<(u8, u8, u8)> {
+    let hex = value
+        .strip_prefix('#')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(anyhow::anyhow!("Expected a color like '#rrggbb', got '{}'", value));
+    }
+    let channel = |range: std::ops::Range<usize>| {
+        u8::from_str_radix(&hex[range], 16)
+            .map_err(|_| anyhow::anyhow!("Expected a color like '#rrggbb', got '{}'", value))
+    };
+    Ok((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+fn theme_name_from_path(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Custom".to_string())
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '_'], "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_registry_contains_builtin_themes() {
+        let names = ThemeConfig::builtin_names();
+        assert!(names.contains(&"default"));
+        assert!(names.contains(&"dracula"));
+        
+        for name in names {
+            assert!(ThemeConfig::try_from_name(name).is_some(), "{} is not resolvable", name);
+        }
+    }
+    
+    #[test]
+    fn test_unknown_theme_suggests_closest() {
+        let error = ThemeConfig::resolve("dracla").err().unwrap().to_string();
+        assert!(error.contains("Did you mean 'dracula'?"), "{}", error);
+        
+        assert_eq!(ThemeConfig::closest_name("zzzzzz"), None);
+        assert_eq!(ThemeConfig::resolve("Dracula").unwrap().name, "Dracula");
+    }
+    
+    #[test]
+    fn test_additional_builtin_themes() {
+        let themes = [
+            (ThemeConfig::nord_theme(), "Nord", "nord"),
+            (ThemeConfig::solarized_dark_theme(), "Solarized Dark", "solarized-dark"),
+            (ThemeConfig::gruvbox_theme(), "Gruvbox", "gruvbox"),
+            (ThemeConfig::monokai_theme(), "Monokai", "monokai"),
+            (ThemeConfig::tokyo_night_theme(), "Tokyo Night", "tokyo-night"),
+        ];
+        
+        for (theme, display_name, key) in themes {
+            assert_eq!(theme.name, display_name);
+            assert_eq!(theme.colors.len(), 16, "{} palette", display_name);
+            assert_eq!(ThemeConfig::from_name(key).name, display_name);
+            assert_eq!(ThemeConfig::from_name(display_name).name, display_name);
+        }
+    }
+    
+    #[test]
+    fn test_alacritty_theme_import() {
+        let theme = ThemeConfig::parse_alacritty_toml(r##"
+[colors.primary]
+background = "#1d1f21"
+foreground = "0xc5c8c6"
+
+[colors.cursor]
+cursor = "#ffffff"
+
+[colors.normal]
+black = "#000000"
+red = "#cc6666"
+
+[colors.bright]
+white = "#eaeaea"
+"##, "tomorrow").unwrap();
+        
+        assert_eq!(theme.name, "tomorrow");
+        assert_eq!(theme.background, (0x1d, 0x1f, 0x21));
+        assert_eq!(theme.foreground, (0xc5, 0xc8, 0xc6));
+        assert_eq!(theme.cursor, (255, 255, 255));
+        assert_eq!(theme.colors.len(), 16);
+        assert_eq!(theme.colors[0], (0, 0, 0));
+        assert_eq!(theme.colors[1], (0xcc, 0x66, 0x66));
+        assert_eq!(theme.colors[15], (0xea, 0xea, 0xea));
+        // Unspecified entries keep the default palette
+        assert_eq!(theme.colors[2], ThemeConfig::default_theme().colors[2]);
+        
+        assert!(ThemeConfig::parse_alacritty_toml("[colors.primary]\nbackground = \"#12\"", "bad").is_err());
+    }
+    
+    #[test]
+    fn test_iterm_theme_import_by_extension() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Ansi 1 Color</key>
+    <dict>
+        <key>Color Space</key><string>sRGB</string>
+        <key>Red Component</key><real>1</real>
+        <key>Green Component</key><real>0.5</real>
+        <key>Blue Component</key><real>0</real>
+    </dict>
+    <key>Background Color</key>
+    <dict>
+        <key>Red Component</key><real>0.1</real>
+        <key>Green Component</key><real>0.2</real>
+        <key>Blue Component</key><real>0.3</real>
+    </dict>
+</dict>
+</plist>"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Sunset.itermcolors");
+        std::fs::write(&path, plist).unwrap();
+        
+        let theme = ThemeConfig::resolve(path.to_str().unwrap()).unwrap();
+        assert_eq!(theme.name, "Sunset");
+        assert_eq!(theme.colors[1], (255, 128, 0));
+        assert_eq!(theme.background, (26, 51, 77));
+        assert_eq!(theme.foreground, ThemeConfig::default_theme().foreground);
+    }
+    
+    #[test]
+    fn test_ansi_color_lookup() {
+        let theme = ThemeConfig::dracula_theme();
+        assert_eq!(theme.ansi_color(1), theme.colors[1]);
+        assert_eq!(theme.ansi_color(16), (0, 0, 0));
+        assert_eq!(theme.ansi_color(196), (255, 0, 0));
+        assert_eq!(theme.ansi_color(231), (255, 255, 255));
+        assert_eq!(theme.ansi_color(232), (8, 8, 8));
+        assert_eq!(theme.ansi_color(255), (238, 238, 238));
+    }
+}
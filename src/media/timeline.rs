@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::pty::ansi::strip_ansi;
+
+/// When a command ran during a recording, for captioning it in a video editor
+///
+/// Times are seconds on the recorder's clock, which GIF frames and captions
+/// are timed by; deterministic recordings use their simulated clock.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Output that arrived while the command's step ran, without escape sequences
+    pub output: String,
+}
+
+impl TimelineEntry {
+    /// An entry for `text`, which printed `output` between `start` and `end`
+    pub fn new(text: &str, start: Duration, end: Duration, output: &str) -> Self {
+        Self {
+            start: start.as_secs_f64(),
+            end: end.as_secs_f64(),
+            text: text.to_string(),
+            output: strip_ansi(output),
+        }
+    }
+}
+
+/// Write `entries` as a JSON array to `output_path`
+pub fn save_timeline(entries: &[TimelineEntry], output_path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(entries)?;
+    std::fs::write(output_path, json)
+        .with_context(|| format!("Failed to write timeline: {}", output_path.display()))?;
+
+    log::info!("Timeline saved to: {}", output_path.display());
+    Ok(())
+}
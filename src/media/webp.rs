@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use webp::{AnimEncoder, AnimFrame, WebPConfig};
+
+use super::gif::{frame_delays, GifFrame};
+use super::MediaConfig;
+
+/// Encode captured frames as an animated WebP that plays `loop_count` times
+///
+/// Frames come from the same capture pipeline as GIFs, so their delays are in
+/// centiseconds, with `end_pause` added to the last. libwebp shows its final
+/// frame for the average duration of the others, so a copy of the last frame
+/// is added where that frame ends, holding it for its full delay and then some.
+pub fn encode_webp(frames: &[GifFrame], config: &MediaConfig) -> Result<Vec<u8>> {
+    let first = frames.first().ok_or_else(|| anyhow::anyhow!("No frames to save"))?;
+    let (width, height) = first.image.dimensions();
+
+    let mut webp_config = WebPConfig::new()
+        .map_err(|_| anyhow::anyhow!("Failed to initialize the WebP encoder"))?;
+    webp_config.lossless = i32::from(config.webp_lossless);
+    webp_config.quality = config.webp_quality.clamp(0.0, 100.0);
+
+    let mut encoder = AnimEncoder::new(width, height, &webp_config);
+    // Zero plays means loop forever
+    encoder.set_loop_count(i32::from(config.loop_count.unwrap_or(0)));
+
+    let mut timestamp_ms = 0i32;
+    for (frame, delay) in frames.iter().zip(frame_delays(frames, config)) {
+        encoder.add_frame(AnimFrame::from_rgb(&frame.image, width, height, timestamp_ms));
+        timestamp_ms += i32::from(delay) * 10;
+    }
+    let last = &frames[frames.len() - 1];
+    encoder.add_frame(AnimFrame::from_rgb(&last.image, width, height, timestamp_ms));
+
+    let data = encoder
+        .try_encode()
+        .map_err(|err| anyhow::anyhow!("Failed to encode WebP animation: {:?}", err))?;
+    Ok(data.to_vec())
+}
+
+/// Encode frames with `encode_webp` and write them to `output_path`
+pub fn save_webp(frames: &[GifFrame], config: &MediaConfig, output_path: &Path) -> Result<()> {
+    let data = encode_webp(frames, config)?;
+    std::fs::write(output_path, data)
+        .with_context(|| format!("Failed to write WebP file: {}", output_path.display()))?;
+
+    log::info!("WebP saved to: {}", output_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use std::time::Duration;
+    use webp::AnimDecoder;
+
+    #[test]
+    fn test_two_frame_animation_decodes() {
+        let frames = vec![
+            GifFrame { image: RgbImage::from_pixel(32, 16, Rgb([40, 44, 52])), delay: 50 },
+            GifFrame { image: RgbImage::from_pixel(32, 16, Rgb([171, 178, 191])), delay: 50 },
+        ];
+
+        for lossless in [true, false] {
+            let config = MediaConfig { webp_lossless: lossless, ..MediaConfig::builtin_font().build() };
+            let data = encode_webp(&frames, &config).unwrap();
+            assert_eq!(&data[..4], b"RIFF");
+            assert_eq!(&data[8..12], b"WEBP");
+
+            let decoded = AnimDecoder::new(&data).decode().unwrap();
+            assert!(decoded.has_animation());
+            assert_eq!(decoded.len(), 2);
+            // Decoded timestamps mark when each frame ends
+            assert_eq!(decoded.get_frame(0).unwrap().get_time_ms(), 500);
+        }
+    }
+
+    #[test]
+    fn test_last_frame_holds_its_delay_and_end_pause() {
+        let frames = vec![
+            GifFrame { image: RgbImage::from_pixel(32, 16, Rgb([40, 44, 52])), delay: 50 },
+            GifFrame { image: RgbImage::from_pixel(32, 16, Rgb([171, 178, 191])), delay: 50 },
+        ];
+        let config = MediaConfig { end_pause: Some(Duration::from_secs(2)), ..MediaConfig::builtin_font().build() };
+
+        let data = encode_webp(&frames, &config).unwrap();
+        let decoded = AnimDecoder::new(&data).decode().unwrap();
+        // The copy of the last frame merges into it
+        assert_eq!(decoded.len(), 2);
+        let first_end = decoded.get_frame(0).unwrap().get_time_ms();
+        let last_end = decoded.get_frame(1).unwrap().get_time_ms();
+        assert_eq!(first_end, 500);
+        assert!(last_end - first_end >= 2500, "last frame shown for {}ms", last_end - first_end);
+    }
+}
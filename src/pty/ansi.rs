@@ -0,0 +1,131 @@
+/// Remove terminal escape sequences and control characters from raw output
+///
+/// CSI (including SGR colors), OSC and two-byte escape sequences are dropped,
+/// as are C0 control characters other than newline and tab, so `\r\n` becomes
+/// `\n`.
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte in @..~
+                Some('[') => {
+                    for next in chars.by_ref() {
+                        if ('@'..='~').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: terminated by BEL or ST (ESC \)
+                Some(']') => {
+                    while let Some(next) = chars.next() {
+                        if next == '\x07' {
+                            break;
+                        }
+                        if next == '\x1b' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Character set designation and similar: ESC, intermediates, final
+                Some(next) if (' '..='/').contains(&next) => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\n' | '\t' => output.push(ch),
+            _ if ch.is_control() => {}
+            _ => output.push(ch),
+        }
+    }
+
+    output
+}
+
+/// Count the BEL characters in `input` that ring the bell, not those ending OSC sequences
+///
+/// Also returns how many bytes were scanned: all of `input` unless it ends
+/// inside an escape sequence, which is left to scan again once complete.
+pub fn count_bells(input: &str) -> (usize, usize) {
+    let mut bells = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        match ch {
+            '\x07' => bells += 1,
+            '\x1b' => match chars.next() {
+                None => return (bells, start),
+                Some((_, ']')) => {
+                    let mut finished = false;
+                    while let Some((_, next)) = chars.next() {
+                        if next == '\x07' || (next == '\x1b' && chars.next_if(|&(_, ch)| ch == '\\').is_some()) {
+                            finished = true;
+                            break;
+                        }
+                    }
+                    if !finished {
+                        return (bells, start);
+                    }
+                }
+                Some(_) => {}
+            },
+            _ => {}
+        }
+    }
+    (bells, input.len())
+}
+
+/// Split raw output into runs of text, each with the OSC 8 hyperlink it is in
+///
+/// The OSC 8 sequences themselves are removed; any other escape sequences
+/// are left in the text.
+pub fn split_hyperlinks(input: &str) -> Vec<(String, Option<String>)> {
+    let mut runs = Vec::new();
+    let mut link = None;
+    let mut rest = input;
+    
+    while let Some(start) = rest.find("\x1b]8;") {
+        let body = &rest[start + 4..];
+        let (params, after) = match (body.find('\x07'), body.find("\x1b\\")) {
+            (Some(bel), Some(st)) if st < bel => (&body[..st], &body[st + 2..]),
+            (Some(bel), _) => (&body[..bel], &body[bel + 1..]),
+            (None, Some(st)) => (&body[..st], &body[st + 2..]),
+            // Unterminated; leave it for the next chunk of output to finish
+            (None, None) => break,
+        };
+        if start > 0 {
+            runs.push((rest[..start].to_string(), link.clone()));
+        }
+        link = params
+            .split_once(';')
+            .map(|(_, uri)| uri)
+            .filter(|uri| !uri.is_empty())
+            .map(str::to_string);
+        rest = after;
+    }
+    if !rest.is_empty() {
+        runs.push((rest.to_string(), link));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[32mOK\x1b[0m"), "OK");
+        assert_eq!(strip_ansi("\x1b[1;38;5;208mwarn\x1b[m: disk\r\n"), "warn: disk\n");
+        assert_eq!(strip_ansi("\x1b]0;title\x07$ \x1b]2;other\x1b\\ls"), "$ ls");
+        assert_eq!(strip_ansi("\x1b(Bplain\ttext"), "plain\ttext");
+    }
+
+    #[test]
+    fn test_count_bells_skips_osc_terminators() {
+        assert_eq!(count_bells("\x1b]0;title\x07ding\x07"), (1, 15));
+        assert_eq!(count_bells("\x07\x1b]697;kla-exit=0"), (1, 1));
+    }
+}
@@ -1,18 +1,109 @@
 use anyhow::Result;
-use std::time::Duration;
 use vt100::Parser;
 
-use super::Terminal;
+use crate::terminal::{CharAttributes, CursorPosition, Hyperlink, TerminalChar, TerminalSize, TerminalState};
 
 pub struct TerminalCapture {
-    parser: Parser,
+    parser: Parser<OscTracker>,
     history: Vec<String>,
 }
 
+/// Notes the window title and the cells each OSC 8 hyperlink is printed to
+#[derive(Default)]
+struct OscTracker {
+    /// Target and starting cursor position of the link being printed
+    open: Option<(String, (u16, u16))>,
+    /// Each finished link
+    links: Vec<PrintedLink>,
+    /// Last title set with OSC 0 or 2
+    title: String,
+}
+
+/// A hyperlink's cells as printed, one span per row it covers
+#[derive(Debug, PartialEq)]
+struct PrintedLink {
+    uri: String,
+    /// Row, first column, column just past the link, and the text in between
+    spans: Vec<(u16, u16, u16, String)>,
+}
+
+impl PrintedLink {
+    /// Where the link is on `screen` now
+    ///
+    /// Output scrolls rows up without moving columns, so the link is followed
+    /// up the screen until its text is found. Once it has scrolled off or been
+    /// overwritten, it is gone.
+    fn locate(&self, screen: &vt100::Screen) -> Vec<Hyperlink> {
+        let top = self.spans.first().map_or(0, |span| span.0);
+        let shift = (0..=top).find(|&shift| {
+            self.spans
+                .iter()
+                .all(|(row, start, end, text)| screen.contents_between(row - shift, *start, row - shift, *end) == *text)
+        });
+        let Some(shift) = shift else {
+            return Vec::new();
+        };
+        self.spans
+            .iter()
+            .map(|(row, start, end, _)| Hyperlink {
+                row: row - shift,
+                start: *start,
+                end: *end,
+                uri: self.uri.clone(),
+            })
+            .collect()
+    }
+}
+
+impl vt100::Callbacks for OscTracker {
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        self.title = String::from_utf8_lossy(title).into_owned();
+    }
+    
+    fn unhandled_osc(&mut self, screen: &mut vt100::Screen, params: &[&[u8]]) {
+        // A title containing ';' arrives split up, which vt100 leaves to us
+        if let [b"0" | b"2", title @ ..] = params {
+            self.title = String::from_utf8_lossy(&title.join(&b';')).into_owned();
+            return;
+        }
+        // OSC 8 ; params ; URI, where a URI containing ';' arrives split up
+        let [b"8", _, uri @ ..] = params else {
+            return;
+        };
+        if let Some((uri, (row, col))) = self.open.take() {
+            // A link that wrapped onto later rows covers the rest of each row before its last
+            let (end_row, end_col) = screen.cursor_position();
+            let width = screen.size().1;
+            let spans: Vec<_> = (row..=end_row)
+                .map(|span_row| {
+                    let start = if span_row == row { col } else { 0 };
+                    let end = if span_row == end_row { end_col } else { width };
+                    (span_row, start, end, screen.contents_between(span_row, start, span_row, end))
+                })
+                .filter(|(_, start, end, text)| end > start && !text.trim().is_empty())
+                .collect();
+            let link = PrintedLink { uri, spans };
+            if !link.spans.is_empty() && !self.links.contains(&link) {
+                self.links.push(link);
+            }
+        }
+        
+        let uri = String::from_utf8_lossy(&uri.join(&b';')).into_owned();
+        if !uri.is_empty() {
+            self.open = Some((uri, screen.cursor_position()));
+        }
+    }
+}
+
 impl TerminalCapture {
     pub fn new(width: u16, height: u16) -> Self {
+        Self::with_scrollback(width, height, 0)
+    }
+    
+    /// Keep up to `scrollback_len` rows that scroll off the top of the screen
+    pub fn with_scrollback(width: u16, height: u16, scrollback_len: usize) -> Self {
         Self {
-            parser: Parser::new(height, width, 0),
+            parser: Parser::new_with_callbacks(height, width, scrollback_len, OscTracker::default()),
             history: Vec::new(),
         }
     }
@@ -30,8 +121,70 @@ impl TerminalCapture {
     pub fn get_formatted_contents(&self) -> Vec<String> {
         let screen = self.parser.screen();
         
-        // Use the rows iterator method properly
-        screen.rows(0, screen.size().0).map(|row| row.trim_end().to_string()).collect()
+        // Rows are read across the full column width
+        screen.rows(0, screen.size().1).map(|row| row.trim_end().to_string()).collect()
+    }
+    
+    /// Every row of scrollback followed by the visible screen, oldest first
+    ///
+    /// Trailing blank rows are dropped so short output does not pad the result.
+    pub fn get_scrollback_lines(&mut self) -> Vec<String> {
+        let width = self.parser.screen().size().1;
+        
+        // Scrolling back `offset` rows puts scrollback row `total - offset` at the top
+        self.parser.screen_mut().set_scrollback(usize::MAX);
+        let total = self.parser.screen().scrollback();
+        let mut lines = Vec::new();
+        for offset in (1..=total).rev() {
+            self.parser.screen_mut().set_scrollback(offset);
+            if let Some(row) = self.parser.screen().rows(0, width).next() {
+                lines.push(row.trim_end().to_string());
+            }
+        }
+        
+        self.parser.screen_mut().set_scrollback(0);
+        lines.extend(self.parser.screen().rows(0, width).map(|row| row.trim_end().to_string()));
+        
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+    
+    /// Snapshot of the visible screen, including attributes and the cursor
+    pub fn to_state(&self) -> TerminalState {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let mut state = TerminalState::new(TerminalSize::new(cols, rows));
+        
+        for row in 0..rows {
+            for col in 0..cols {
+                let Some(cell) = screen.cell(row, col) else {
+                    continue;
+                };
+                // The wide character to the left already claimed this cell
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                let attrs = CharAttributes {
+                    fg_color: indexed_color(cell.fgcolor()),
+                    bg_color: indexed_color(cell.bgcolor()),
+                    bold: cell.bold(),
+                    italic: cell.italic(),
+                    underline: cell.underline(),
+                    reverse: cell.inverse(),
+                };
+                let ch = cell.contents().chars().next().unwrap_or(' ');
+                state.set_char(col, row, TerminalChar::with_attrs(ch, attrs));
+            }
+        }
+        
+        let (row, col) = screen.cursor_position();
+        state.cursor = CursorPosition::new(col, row);
+        state.cursor_visible = !screen.hide_cursor();
+        state.hyperlinks = self.parser.callbacks().links.iter().flat_map(|link| link.locate(screen)).collect();
+        state.title = self.parser.callbacks().title.clone();
+        state
     }
     
     pub fn get_cursor_position(&self) -> (u16, u16) {
@@ -48,6 +201,13 @@ impl TerminalCapture {
     }
 }
 
+fn indexed_color(color: vt100::Color) -> Option<u8> {
+    match color {
+        vt100::Color::Idx(index) => Some(index),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,4 +232,99 @@ mod tests {
         assert!(lines[0].contains("Line 1"));
         assert!(lines[1].contains("Line 2"));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_scrollback_lines() {
+        let mut capture = TerminalCapture::with_scrollback(20, 5, 100);
+        
+        for i in 1..=30 {
+            capture.process_output(&format!("line {}\r\n", i)).unwrap();
+        }
+        let lines = capture.get_scrollback_lines();
+        
+        assert_eq!(lines.len(), 30);
+        assert_eq!(lines[0], "line 1");
+        assert_eq!(lines[29], "line 30");
+        // The visible screen is left where it was
+        assert!(capture.get_screen_contents().contains("line 30"));
+    }
+    
+    #[test]
+    fn test_state_snapshot() {
+        let mut capture = TerminalCapture::new(10, 3);
+        capture.process_output("\x1b[1;31mhi\x1b[0m 世\r\n$ ").unwrap();
+        let state = capture.to_state();
+        
+        let h = state.get_char(0, 0).unwrap();
+        assert_eq!(h.ch, 'h');
+        assert!(h.attrs.bold);
+        assert_eq!(h.attrs.fg_color, Some(1));
+        assert_eq!(state.get_line_text(0).unwrap(), "hi 世");
+        assert!(state.get_char(4, 0).unwrap().continuation);
+        assert_eq!(state.cursor, CursorPosition::new(2, 1));
+        assert!(state.cursor_visible);
+        
+        capture.process_output("\x1b[?25l").unwrap();
+        assert!(!capture.to_state().cursor_visible);
+    }
+    
+    #[test]
+    fn test_osc_title_sets_state_title() {
+        let mut capture = TerminalCapture::new(20, 2);
+        assert_eq!(capture.to_state().title, "");
+        
+        capture.process_output("\x1b]2;My App\x07$ ").unwrap();
+        assert_eq!(capture.to_state().title, "My App");
+        assert_eq!(capture.to_state().get_line_text(0).unwrap(), "$");
+        
+        capture.process_output("\x1b]0;vim; notes.txt\x1b\\").unwrap();
+        assert_eq!(capture.to_state().title, "vim; notes.txt");
+    }
+    
+    #[test]
+    fn test_sgr_attributes_set_and_clear() {
+        let mut capture = TerminalCapture::new(20, 2);
+        // Each letter follows one change: all on, reverse off, bold off,
+        // italic off, underline off, reverse on, then a full reset
+        capture
+            .process_output("\x1b[1;3;4;7;32mA\x1b[27mB\x1b[22mC\x1b[23mD\x1b[24mE\x1b[7mF\x1b[0mG")
+            .unwrap();
+        let state = capture.to_state();
+        let attrs = |x| state.get_char(x, 0).unwrap().attrs.clone();
+        let flags = |x| {
+            let attrs = attrs(x);
+            (attrs.bold, attrs.italic, attrs.underline, attrs.reverse)
+        };
+        
+        assert_eq!(flags(0), (true, true, true, true));
+        assert_eq!(flags(1), (true, true, true, false));
+        assert_eq!(flags(2), (false, true, true, false));
+        assert_eq!(flags(3), (false, false, true, false));
+        assert_eq!(flags(4), (false, false, false, false));
+        assert_eq!(flags(5), (false, false, false, true));
+        assert_eq!(attrs(5).fg_color, Some(2));
+        assert_eq!(attrs(6), CharAttributes::default());
+    }
+    
+    #[test]
+    fn test_links_keep_their_printed_cells() {
+        let mut capture = TerminalCapture::new(20, 3);
+        capture.process_output("docs \x1b]8;;https://a.example\x1b\\docs\x1b]8;;\x1b\\\r\n").unwrap();
+        
+        // The same text printed without a link stays plain
+        let links: Vec<_> = capture.to_state().hyperlinks.iter().map(|link| (link.row, link.start, link.end)).collect();
+        assert_eq!(links, vec![(0, 5, 9)]);
+    }
+    
+    #[test]
+    fn test_links_follow_output_that_scrolls() {
+        let mut capture = TerminalCapture::new(20, 3);
+        capture.process_output("a\r\n\x1b]8;;https://a.example\x1b\\docs\x1b]8;;\x1b\\\r\nb\r\nc").unwrap();
+        let state = capture.to_state();
+        assert_eq!(state.get_line_text(0).as_deref(), Some("docs"));
+        assert_eq!(state.hyperlink_at(0, 0).map(|link| link.uri.as_str()), Some("https://a.example"));
+        
+        capture.process_output("\x1b[2J\x1b[Hplain").unwrap();
+        assert!(capture.to_state().hyperlinks.is_empty());
+    }
+}
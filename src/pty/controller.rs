@@ -1,16 +1,17 @@
-use anyhow::Result;
+use std::path::Path;
 use std::time::Duration;
 
+use crate::error::Result;
 use crate::script::TerminalSettings;
-use super::Terminal;
+use super::{CommandRun, MouseEvent, OutputEvent, Terminal};
 
 pub struct TerminalController {
     terminal: Terminal,
 }
 
 impl TerminalController {
-    pub fn new(settings: &TerminalSettings) -> Result<Self> {
-        let terminal = Terminal::new(settings)?;
+    pub async fn new(settings: &TerminalSettings) -> Result<Self> {
+        let terminal = Terminal::new(settings).await?;
         Ok(Self { terminal })
     }
     
@@ -19,15 +20,107 @@ impl TerminalController {
         self.terminal.execute_command(command).await
     }
     
+    /// Execute a command with a file's contents as its stdin
+    pub async fn execute_command_with_stdin(&mut self, command: &str, stdin: &Path) -> Result<()> {
+        log::debug!("Executing command: {} < {}", command, stdin.display());
+        self.terminal.execute_command_with_stdin(command, stdin).await
+    }
+    
+    /// Execute a command and wait up to `timeout` for it to exit
+    pub async fn run_command(&mut self, command: &str, timeout: Duration) -> Result<CommandRun> {
+        log::debug!("Running command to completion: {}", command);
+        self.terminal.run_command(command, timeout).await
+    }
+    
+    /// Send raw input to the terminal without appending a newline
+    pub async fn send_input(&mut self, input: &str) -> Result<()> {
+        log::debug!("Sending input: {:?}", input);
+        self.terminal.send_input(input).await
+    }
+    
+    /// Send a file's contents as input followed by EOF
+    pub async fn send_file(&mut self, path: &Path) -> Result<()> {
+        log::debug!("Sending file as input: {}", path.display());
+        self.terminal.send_file(path).await
+    }
+    
+    /// Send a mouse event at column `x`, row `y`, counting from 1
+    pub async fn send_mouse(&mut self, event: MouseEvent, x: u16, y: u16) -> Result<()> {
+        log::debug!("Sending mouse {:?} at ({}, {})", event, x, y);
+        let sequence = event.sequence(x, y)?;
+        self.terminal.send_input(&sequence).await
+    }
+    
     pub async fn type_text(&mut self, text: &str, speed: Duration) -> Result<()> {
         log::debug!("Typing text: {} (speed: {:?})", text, speed);
         self.terminal.type_text(text, speed).await
     }
     
+    pub async fn type_text_with<F: FnMut(char)>(
+        &mut self,
+        text: &str,
+        speed: Duration,
+        on_char: F,
+    ) -> Result<()> {
+        log::debug!("Typing text: {} (speed: {:?})", text, speed);
+        self.terminal.type_text_with(text, speed, on_char).await
+    }
+    
+    pub async fn type_text_paced<F: FnMut(char) -> Duration>(&mut self, text: &str, delay: F) -> Result<()> {
+        log::debug!("Typing text: {}", text);
+        self.terminal.type_text_paced(text, delay).await
+    }
+    
     pub fn get_output(&self) -> String {
         self.terminal.get_output()
     }
     
+    /// The PTY's unmodified output, with `raw_capture` set; see `Terminal::get_raw_bytes`
+    pub fn get_raw_bytes(&self) -> Vec<u8> {
+        self.terminal.get_raw_bytes()
+    }
+    
+    /// Remember the current end of the output, to read what comes after it
+    ///
+    /// Unlike `clear_output_buffer`, this keeps the output seen so far.
+    pub fn mark(&self) -> usize {
+        self.terminal.mark()
+    }
+    
+    /// The earliest mark whose output hasn't been cleared
+    pub fn oldest_mark(&self) -> usize {
+        self.terminal.oldest_mark()
+    }
+    
+    /// Output that arrived since `mark` was taken, without draining the buffer
+    pub fn read_since(&self, mark: usize) -> String {
+        self.terminal.read_since(mark)
+    }
+    
+    /// Get output with escape sequences removed, for text assertions
+    pub fn get_plain_output(&self) -> String {
+        self.terminal.get_plain_output()
+    }
+    
+    /// Everything commands wrote to stderr, when `separate_stderr` is set
+    pub fn get_stderr(&self) -> String {
+        self.terminal.get_stderr()
+    }
+    
+    pub fn get_output_events(&self) -> Vec<OutputEvent> {
+        self.terminal.get_output_events()
+    }
+    
+    /// Time since the terminal started, on the clock output events use
+    pub fn elapsed(&self) -> Duration {
+        self.terminal.elapsed()
+    }
+    
+    /// Receive each chunk of output as it arrives
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.terminal.subscribe()
+    }
+    
     pub fn get_size(&self) -> (u16, u16) {
         self.terminal.get_size()
     }
@@ -36,6 +129,28 @@ impl TerminalController {
         self.terminal.wait_for_output(pattern, timeout).await
     }
     
+    /// Wait for output to pause, as deterministic recordings do before each capture
+    pub async fn wait_for_settled(&self) -> bool {
+        self.terminal.wait_for_settled().await
+    }
+    
+    /// Whether the shell or program in the terminal is still running
+    pub fn is_alive(&mut self) -> bool {
+        self.terminal.is_alive()
+    }
+    
+    /// Wait up to `timeout` for the shell or program to exit, returning its exit code
+    pub async fn wait_for_exit(&mut self, timeout: Duration) -> Option<i32> {
+        self.terminal.wait_for_exit(timeout).await
+    }
+    
+    /// Let the shell or program exit, killing it after `shutdown_grace`
+    ///
+    /// Returns whether it exited on its own.
+    pub async fn close(&mut self) -> bool {
+        self.terminal.close().await
+    }
+    
     pub fn clear_output_buffer(&self) {
         self.terminal.clear_buffer();
     }
@@ -43,4 +158,118 @@ impl TerminalController {
     pub fn get_terminal_ref(&self) -> &Terminal {
         &self.terminal
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn test_settings() -> TerminalSettings {
+        TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_type_text_callback_sees_every_char() {
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        let mut typed = String::new();
+        
+        controller
+            .type_text_with("echo héllo", Duration::from_millis(1), |ch| typed.push(ch))
+            .await
+            .unwrap();
+        
+        assert_eq!(typed, "echo héllo");
+    }
+    
+    #[tokio::test]
+    async fn test_wait_for_exit_reports_exit_code() {
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        assert!(controller.is_alive());
+        assert_eq!(controller.wait_for_exit(Duration::from_millis(50)).await, None);
+        
+        controller.execute_command("exit 3").await.unwrap();
+        assert_eq!(controller.wait_for_exit(Duration::from_secs(5)).await, Some(3));
+        assert!(!controller.is_alive());
+    }
+    
+    #[tokio::test]
+    async fn test_read_since_keeps_output_after_mark() {
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        let printed = |output: String| -> Vec<String> {
+            crate::pty::ansi::strip_ansi(&output).lines().map(str::to_string).collect()
+        };
+        
+        controller.wait_for_settled().await;
+        let mark = controller.mark();
+        controller.run_command("echo a", Duration::from_secs(5)).await.unwrap();
+        controller.wait_for_settled().await;
+        let since = printed(controller.read_since(mark));
+        assert!(since.contains(&"a".to_string()), "{:?}", since);
+        
+        controller.run_command("echo b", Duration::from_secs(5)).await.unwrap();
+        let since = printed(controller.read_since(mark));
+        assert!(since.contains(&"a".to_string()) && since.contains(&"b".to_string()), "{:?}", since);
+        assert!(controller.get_output().ends_with(&controller.read_since(mark)));
+        
+        let end = controller.mark();
+        controller.clear_output_buffer();
+        assert_eq!(controller.read_since(mark), "");
+        controller.run_command("echo c", Duration::from_secs(5)).await.unwrap();
+        assert!(controller.mark() > end);
+        assert_eq!(controller.read_since(end), controller.get_output());
+    }
+    
+    #[tokio::test]
+    async fn test_click_sends_sgr_mouse_report() {
+        assert_eq!(MouseEvent::Click.sequence(5, 5).unwrap(), "\x1b[<0;5;5M\x1b[<0;5;5m");
+        assert_eq!(MouseEvent::ScrollDown.sequence(5, 5).unwrap(), "\x1b[<65;5;5M");
+        
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        controller.execute_command("cat").await.unwrap();
+        controller.send_mouse(MouseEvent::Click, 5, 5).await.unwrap();
+        // The terminal echoes the escape bytes it received as `^[`
+        assert!(controller.wait_for_output("^[[<0;5;5M^[[<0;5;5m", Duration::from_secs(5)).await.unwrap());
+        assert!(controller.send_mouse(MouseEvent::Press, 0, 5).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_send_input_has_no_trailing_newline() {
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        
+        controller.send_input("echo in_put").await.unwrap();
+        assert!(controller.wait_for_output("echo in_put", Duration::from_secs(5)).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!controller.get_output().contains("in_put\r\n"));
+        
+        // A command step terminates the pending line, which runs it
+        controller.execute_command("").await.unwrap();
+        assert!(controller.wait_for_output("in_put\r\n", Duration::from_secs(5)).await.unwrap());
+    }
+    
+    #[tokio::test]
+    async fn test_wait_for_output_ignores_color_codes() {
+        let mut controller = TerminalController::new(&test_settings()).await.unwrap();
+        
+        controller.execute_command("printf 'Status: \\033[32mOK\\033[0m\\n'").await.unwrap();
+        
+        assert!(controller.wait_for_output("Status: OK", Duration::from_secs(5)).await.unwrap());
+        assert!(controller.get_plain_output().contains("Status: OK"));
+        assert!(!controller.get_output().contains("Status: OK"));
+    }
+    
+    #[tokio::test]
+    async fn test_raw_capture_keeps_escape_bytes() {
+        let settings = TerminalSettings { raw_capture: true, ..test_settings() };
+        let mut controller = TerminalController::new(&settings).await.unwrap();
+        
+        controller.run_command("printf '\\033[38;5;208mraw\\033[0m\\n'", Duration::from_secs(5)).await.unwrap();
+        
+        let raw = controller.get_raw_bytes();
+        let sequence = b"\x1b[38;5;208mraw\x1b[0m";
+        assert!(raw.windows(sequence.len()).any(|window| window == sequence), "{:?}", String::from_utf8_lossy(&raw));
+        assert!(TerminalController::new(&test_settings()).await.unwrap().get_raw_bytes().is_empty());
+    }
+}
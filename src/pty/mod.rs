@@ -1,27 +1,281 @@
-use anyhow::{Context, Result};
 use portable_pty::{CommandBuilder, PtySize};
+use regex::Regex;
 use std::io::{Read, Write};
+use std::path::Path;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::time::timeout;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot, Notify};
 
+use crate::error::{KlaError, Result};
 use crate::script::TerminalSettings;
 
 pub mod controller;
 pub mod capture;
+pub mod ansi;
+pub mod mouse;
 
 pub use controller::TerminalController;
+pub use mouse::MouseEvent;
+
+/// Output chunks a subscriber may fall behind by before it starts missing them
+const OUTPUT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Start of the exit sentinel `run_command` appends, followed by the exit status
+///
+/// It is an OSC sequence that terminals ignore, so it never shows on screen.
+const EXIT_SENTINEL: &str = "\x1b]697;kla-exit=";
+
+/// Shell code that prints the exit sentinel with the last command's status
+const EXIT_SENTINEL_CODE: &str = "printf '\\033]697;kla-exit=%d\\007' $?";
+
+/// How long a command may run before the prompt must reappear, with `prompt_pattern` set
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long `close` waits for a child to die after hanging up on it
+const HANGUP_GRACE: Duration = Duration::from_millis(250);
+
+/// How long startup commands may take before terminal creation fails
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long output must pause before the screen counts as settled
+const SETTLE_QUIET: Duration = Duration::from_millis(150);
+
+/// Longest `wait_for_settled` waits for output that never stops
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest `close` waits for the reader to stop once the child is gone
+const READER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A command run to completion with `run_command`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRun {
+    pub exit_code: i32,
+    /// Raw output from the echoed command line up to the command's exit
+    pub output: String,
+}
+
+/// Output received so far, with the shell's echo of KLA's own code taken out
+/// as it arrives, and the same output as timed events
+///
+/// Text is only ever appended, or dropped from the front, and marks count
+/// from the first byte kept, so a mark stays valid until the text after it is
+/// dropped.
+#[derive(Default)]
+struct OutputBuffer {
+    text: String,
+    /// How much output was dropped before `text`
+    dropped: usize,
+    /// Echoed text still to come that is left out of `text`, in order
+    hidden: VecDeque<String>,
+    /// The end of the output so far, held back while it may be the start of
+    /// the next hidden echo
+    held: String,
+    /// Each piece of text kept, and when it arrived, without exit sentinels
+    events: Vec<OutputEvent>,
+    /// The end of the text kept, held back from `events` while it may be an
+    /// exit sentinel still arriving
+    sentinel_held: String,
+}
+
+impl OutputBuffer {
+    /// Append a chunk of output that arrived at `elapsed`, leaving out any
+    /// hidden echo it completes, and return the text kept
+    fn push(&mut self, chunk: &str, elapsed: Duration) -> String {
+        let start = self.text.len();
+        let mut pending = std::mem::take(&mut self.held);
+        pending.push_str(chunk);
+        while let Some(hidden) = self.hidden.front() {
+            if let Some(at) = pending.find(hidden.as_str()) {
+                self.text.push_str(&pending[..at]);
+                pending.replace_range(..at + hidden.len(), "");
+                self.hidden.pop_front();
+                continue;
+            }
+            let partial = (1..hidden.len())
+                .rev()
+                .find(|&len| hidden.is_char_boundary(len) && pending.ends_with(&hidden[..len]))
+                .unwrap_or(0);
+            self.held = pending.split_off(pending.len() - partial);
+            break;
+        }
+        self.text.push_str(&pending);
+        self.record(start, elapsed)
+    }
+    
+    /// Record the text after `start` as an event at `elapsed`, and return it
+    fn record(&mut self, start: usize, elapsed: Duration) -> String {
+        let kept = self.text[start..].to_string();
+        let data = self.strip_sentinels(&kept);
+        if !data.is_empty() {
+            self.events.push(OutputEvent { elapsed, data });
+        }
+        kept
+    }
+    
+    /// `text` without the exit sentinels `run_command` prints
+    fn strip_sentinels(&mut self, text: &str) -> String {
+        let mut pending = std::mem::take(&mut self.sentinel_held);
+        pending.push_str(text);
+        let mut data = String::new();
+        while let Some(at) = pending.find(EXIT_SENTINEL) {
+            data.push_str(&pending[..at]);
+            match pending[at..].find('\x07') {
+                Some(end) => pending.replace_range(..at + end + 1, ""),
+                None => {
+                    self.sentinel_held = pending.split_off(at);
+                    return data;
+                }
+            }
+        }
+        let partial = (1..EXIT_SENTINEL.len())
+            .rev()
+            .find(|&len| pending.ends_with(&EXIT_SENTINEL[..len]))
+            .unwrap_or(0);
+        self.sentinel_held = pending.split_off(pending.len() - partial);
+        data.push_str(&pending);
+        data
+    }
+    
+    /// Leave `texts` out of the output when the shell echoes them next, in order
+    fn hide(&mut self, texts: impl IntoIterator<Item = String>) {
+        self.hidden.extend(texts);
+    }
+    
+    /// The mark of the end of the text
+    fn end(&self) -> usize {
+        self.dropped + self.text.len()
+    }
+    
+    /// The text after `mark`, or nothing for a mark whose text was dropped
+    fn since(&self, mark: usize) -> &str {
+        mark.checked_sub(self.dropped)
+            .and_then(|start| self.text.get(start..))
+            .unwrap_or_default()
+    }
+    
+    /// Drop the first `len` bytes of the text
+    fn drop_front(&mut self, len: usize) {
+        self.text.replace_range(..len, "");
+        self.dropped += len;
+    }
+    
+    /// Stop waiting for echoes that never arrived and keep what was held back,
+    /// returning it
+    fn expire(&mut self, elapsed: Duration) -> String {
+        self.hidden.clear();
+        let start = self.text.len();
+        let held = std::mem::take(&mut self.held);
+        self.text.push_str(&held);
+        self.record(start, elapsed)
+    }
+}
+
+/// Split `command` before a `# comment` that ends its last line
+fn split_comment(command: &str) -> (&str, &str) {
+    let mut comment = None;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut word_start = true;
+    for (at, c) in command.char_indices() {
+        if comment.is_some() {
+            if c == '\n' {
+                comment = None;
+                word_start = true;
+            }
+            continue;
+        }
+        if escaped {
+            escaped = false;
+            word_start = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') if word_start => {
+                comment = Some(at);
+                continue;
+            }
+            _ => {}
+        }
+        word_start = quote.is_none() && (c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')'));
+    }
+    match comment {
+        Some(at) => command.split_at(at),
+        None => (command, ""),
+    }
+}
+
+/// What goes between `code` and shell code run after it
+///
+/// Code ending in `;` or `&` is already terminated, and a `;` after it would
+/// be a syntax error.
+fn separator(code: &str) -> &'static str {
+    let code = code.trim_end();
+    if code.is_empty() || code.ends_with(';') || code.ends_with('&') {
+        " "
+    } else {
+        " ; "
+    }
+}
+
+/// A chunk of PTY output and when it arrived, relative to terminal start
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputEvent {
+    pub elapsed: Duration,
+    pub data: String,
+}
 
 pub struct Terminal {
-    pty_pair: portable_pty::PtyPair,
+    master: Box<dyn portable_pty::MasterPty + Send>,
     child: Box<dyn portable_pty::Child + Send + Sync>,
     writer: Box<dyn Write + Send>,
-    reader: Arc<std::sync::Mutex<Box<dyn Read + Send>>>,
-    buffer: Arc<std::sync::Mutex<String>>,
+    buffer: Arc<std::sync::Mutex<OutputBuffer>>,
+    /// Every byte read from the PTY, with `raw_capture` set
+    raw: Option<Arc<std::sync::Mutex<Vec<u8>>>>,
+    output_notify: Arc<Notify>,
+    output_tx: broadcast::Sender<String>,
+    shutdown_grace: Duration,
+    /// End of the shell prompt; commands wait for it when set
+    prompt_pattern: Option<Regex>,
+    /// Where commands' stderr goes, with `separate_stderr` set
+    stderr_file: Option<tempfile::NamedTempFile>,
+    /// Whether the PTY runs `run_program` rather than a shell
+    direct: bool,
+    /// The clock output events are timed against
+    started_at: Instant,
+    /// Disconnects once the reader has stopped; taken by `close`
+    reader_done: Option<oneshot::Receiver<()>>,
 }
 
 impl Terminal {
-    pub fn new(settings: &TerminalSettings) -> Result<Self> {
+    pub async fn new(settings: &TerminalSettings) -> Result<Self> {
+        let prompt_pattern = settings
+            .prompt_pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|err| KlaError::parse(format!("Invalid prompt_pattern: {}", err)))?;
+        
+        if let Some(program) = &settings.run_program {
+            if program.is_empty() {
+                return Err(KlaError::parse("run_program needs at least a program name"));
+            }
+            if !settings.startup_commands.is_empty() || settings.fake_prompt.is_some() || settings.separate_stderr {
+                return Err(KlaError::invalid_state(
+                    "startup_commands, fake_prompt and separate_stderr need a shell and cannot be combined with run_program",
+                ));
+            }
+        }
+        if settings.read_buffer_size == 0 {
+            return Err(KlaError::invalid_state("read_buffer_size must be nonzero"));
+        }
+        
         let pty_system = portable_pty::native_pty_system();
         
         let pty_pair = pty_system
@@ -31,88 +285,445 @@ impl Terminal {
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .context("Failed to open PTY")?;
+            .map_err(|err| KlaError::Pty(err.context("Failed to open PTY")))?;
+        
+        let stderr_file = if settings.separate_stderr {
+            let file = tempfile::Builder::new()
+                .prefix("kla-stderr-")
+                .tempfile()
+                .map_err(|err| KlaError::io_context(err, "Failed to create stderr file"))?;
+            Some(file)
+        } else {
+            None
+        };
         
-        let mut cmd = CommandBuilder::new(&settings.shell);
+        let mut cmd = match &settings.run_program {
+            Some(program) => CommandBuilder::from_argv(program.iter().map(Into::into).collect()),
+            None => CommandBuilder::new(&settings.shell),
+        };
         
         if let Some(working_dir) = &settings.working_dir {
+            if !working_dir.is_dir() {
+                if !settings.create_working_dir || working_dir.exists() {
+                    return Err(KlaError::terminal(format!(
+                        "working_dir {} is not an existing directory; create it or set create_working_dir",
+                        working_dir.display()
+                    )));
+                }
+                std::fs::create_dir_all(working_dir).map_err(|err| {
+                    KlaError::io_context(err, format_args!("Failed to create working_dir {}", working_dir.display()))
+                })?;
+            }
             cmd.cwd(working_dir);
         }
         
         let child = pty_pair
             .slave
             .spawn_command(cmd)
-            .context("Failed to spawn shell process")?;
+            .map_err(|err| KlaError::Pty(err.context(format!("Failed to spawn {}", cmd_name(settings)))))?;
         
-        let writer = pty_pair.master.take_writer()
-            .context("Failed to get PTY writer")?;
+        // Only the child keeps the slave open, so reads end once it exits
+        drop(pty_pair.slave);
+        let master = pty_pair.master;
         
-        let reader = Arc::new(std::sync::Mutex::new(
-            pty_pair.master.try_clone_reader()
-                .context("Failed to get PTY reader")?
-        ));
+        let writer = master.take_writer()
+            .map_err(|err| KlaError::Pty(err.context("Failed to get PTY writer")))?;
         
-        let buffer = Arc::new(std::sync::Mutex::new(String::new()));
+        let reader = master.try_clone_reader()
+            .map_err(|err| KlaError::Pty(err.context("Failed to get PTY reader")))?;
+        let read_buffer_size = settings.read_buffer_size;
         
-        // Start background thread to read output
-        let reader_clone = reader.clone();
+        let buffer = Arc::new(std::sync::Mutex::new(OutputBuffer::default()));
+        let raw = settings.raw_capture.then(|| Arc::new(std::sync::Mutex::new(Vec::new())));
+        let output_notify = Arc::new(Notify::new());
+        let (output_tx, _) = broadcast::channel(OUTPUT_CHANNEL_CAPACITY);
+        let started_at = Instant::now();
+        
+        // Read output in the background. Reads block until data or EOF, and
+        // EOF comes once the child exits, so the child's exit stops the reader
+        let (done_tx, reader_done) = oneshot::channel::<()>();
         let buffer_clone = buffer.clone();
-        std::thread::spawn(move || {
-            let mut buf = [0u8; 1024];
+        let raw_clone = raw.clone();
+        let notify_clone = output_notify.clone();
+        let tx_clone = output_tx.clone();
+        spawn_reader(move || {
+            // Disconnects last, once everything the reader holds is released,
+            // which `close` waits for
+            let _done = done_tx;
+            let (mut reader, buffer_clone, raw_clone, notify_clone, tx_clone) =
+                (reader, buffer_clone, raw_clone, notify_clone, tx_clone);
+            let mut buf = vec![0u8; read_buffer_size];
+            // Bytes of a UTF-8 character split across two reads
+            let mut partial = Vec::new();
             loop {
-                if let Ok(mut reader) = reader_clone.lock() {
-                    match reader.read(&mut buf) {
-                        Ok(0) => break, // EOF
-                        Ok(n) => {
-                            let text = String::from_utf8_lossy(&buf[..n]);
-                            if let Ok(mut buffer) = buffer_clone.lock() {
-                                buffer.push_str(&text);
-                            }
+                match reader.read(&mut buf) {
+                    Ok(0) => break, // EOF
+                    Ok(n) => {
+                        if let Some(Ok(mut raw)) = raw_clone.as_ref().map(|raw| raw.lock()) {
+                            raw.extend_from_slice(&buf[..n]);
+                        }
+                        partial.extend_from_slice(&buf[..n]);
+                        let text = take_decodable(&mut partial);
+                        if text.is_empty() {
+                            continue;
                         }
-                        Err(_) => break,
+                        let kept = match buffer_clone.lock() {
+                            Ok(mut buffer) => buffer.push(&text, started_at.elapsed()),
+                            Err(_) => continue,
+                        };
+                        // Sending only fails when nobody is subscribed
+                        if !kept.is_empty() {
+                            let _ = tx_clone.send(kept);
+                        }
+                        // Wake anyone waiting for new output
+                        notify_clone.notify_waiters();
                     }
+                    Err(_) => break,
                 }
-                std::thread::sleep(Duration::from_millis(10));
             }
         });
         
-        Ok(Terminal {
-            pty_pair,
+        let mut terminal = Terminal {
+            master,
             child,
             writer,
-            reader,
             buffer,
-        })
+            raw,
+            output_notify,
+            output_tx,
+            shutdown_grace: settings.shutdown_grace,
+            prompt_pattern,
+            stderr_file,
+            direct: settings.run_program.is_some(),
+            started_at,
+            reader_done: Some(reader_done),
+        };
+        let startup_commands: Vec<String> = settings
+            .fake_prompt
+            .iter()
+            .flat_map(|prompt| fake_prompt_commands(&settings.shell, prompt))
+            .chain(settings.startup_commands.iter().cloned())
+            .collect();
+        if !startup_commands.is_empty() {
+            terminal.run_startup_commands(&startup_commands).await?;
+        }
+        Ok(terminal)
+    }
+    
+    /// Run `commands` in order, then discard everything they printed
+    ///
+    /// This waits until an exit sentinel after the last command arrives and
+    /// the output settles, so the first step sees only the fresh prompt that
+    /// follows.
+    async fn run_startup_commands(&mut self, commands: &[String]) -> Result<()> {
+        let mut input = commands.join("\n");
+        input.push_str(&format!("\n{}\n", EXIT_SENTINEL_CODE));
+        self.writer.write_all(input.as_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(|err| KlaError::io_context(err, "Failed to write startup commands to PTY"))?;
+        
+        let sentinel_end = |text: &str| {
+            text.find(EXIT_SENTINEL)
+                .and_then(|sentinel| text[sentinel..].find('\x07').map(|end| sentinel + end + 1))
+        };
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            let notified = self.output_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            
+            if self.buffer.lock().is_ok_and(|buffer| sentinel_end(&buffer.text).is_some()) {
+                break;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(KlaError::timeout(format!("startup commands to finish after {:?}", STARTUP_TIMEOUT)));
+            }
+        }
+        
+        // The prompt after the sentinel is still on its way
+        self.wait_for_settled().await;
+        if let Ok(mut buffer) = self.buffer.lock() {
+            if let Some(end) = sentinel_end(&buffer.text) {
+                buffer.drop_front(end);
+            }
+            buffer.events.clear();
+            buffer.record(0, Duration::ZERO);
+        }
+        Ok(())
     }
     
+    /// Send a command followed by Enter
+    ///
+    /// With a `prompt_pattern`, this returns once the prompt reappears.
     pub async fn execute_command(&mut self, command: &str) -> Result<()> {
-        self.send_input(&format!("{}\n", command)).await
+        let start = self.buffer_len();
+        let line = self.command_line(command, false);
+        self.send_input(&format!("{}\n", line)).await?;
+        self.wait_for_prompt(start, command).await
+    }
+    
+    /// Execute a command and stream a file to its stdin, as `send_file` does
+    pub async fn execute_command_with_stdin(&mut self, command: &str, path: &Path) -> Result<()> {
+        let start = self.buffer_len();
+        let line = self.command_line(command, false);
+        self.send_input(&format!("{}\n", line)).await?;
+        self.send_file(path).await?;
+        self.wait_for_prompt(start, command).await
+    }
+    
+    /// Wait for output after `start` to end with the prompt, if a pattern is set
+    async fn wait_for_prompt(&self, start: usize, command: &str) -> Result<()> {
+        let Some(pattern) = &self.prompt_pattern else {
+            return Ok(());
+        };
+        let prompt_shown = || pattern.is_match(&ansi::strip_ansi(&self.read_since(start)));
+        
+        let deadline = tokio::time::Instant::now() + PROMPT_TIMEOUT;
+        loop {
+            let notified = self.output_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            
+            if prompt_shown() {
+                self.expire_hidden_echoes();
+                return Ok(());
+            }
+            
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                if prompt_shown() {
+                    return Ok(());
+                }
+                return Err(KlaError::timeout(format!(
+                    "the prompt after `{}` within {:?}",
+                    command, PROMPT_TIMEOUT
+                )));
+            }
+        }
+    }
+    
+    /// The line that runs `command` with its stderr going to the stderr file,
+    /// if there is one, followed by the exit sentinel if `sentinel` is set
+    ///
+    /// The added code is hidden from the output buffer and output events when
+    /// the shell echoes it, so screenshots and casts show the command as
+    /// written, without the stderr file's path. It goes before a
+    /// trailing `# comment`, and a command ending in `&` still runs in the
+    /// background. A heredoc started on the last line isn't supported.
+    fn command_line(&self, command: &str, sentinel: bool) -> String {
+        let (code, comment) = split_comment(command);
+        let mut prefix = String::new();
+        let mut suffix = String::new();
+        if let Some(file) = self.stderr_file.as_ref().filter(|_| !code.trim().is_empty()) {
+            prefix.push_str("{ ");
+            suffix = format!("{}}} 2>>'{}'", separator(code), file.path().display());
+        }
+        if sentinel {
+            suffix.push_str(if suffix.is_empty() { separator(code) } else { " ; " });
+            suffix.push_str(EXIT_SENTINEL_CODE);
+        }
+        if !suffix.is_empty() && !comment.is_empty() {
+            suffix.push(' ');
+        }
+        let line = format!("{}{}{}{}", prefix, code, suffix, comment);
+        
+        self.expire_hidden_echoes();
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.hide([prefix, suffix].into_iter().filter(|text| !text.is_empty()));
+        }
+        line
+    }
+    
+    /// Stop hiding echoes of the last command line that never arrived
+    fn expire_hidden_echoes(&self) {
+        let released = match self.buffer.lock() {
+            Ok(mut buffer) => buffer.expire(self.started_at.elapsed()),
+            Err(_) => return,
+        };
+        if !released.is_empty() {
+            let _ = self.output_tx.send(released);
+        }
+    }
+    
+    /// Everything commands wrote to stderr, or nothing without `separate_stderr`
+    pub fn get_stderr(&self) -> String {
+        self.stderr_file
+            .as_ref()
+            .and_then(|file| std::fs::read(file.path()).ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default()
+    }
+    
+    fn buffer_len(&self) -> usize {
+        self.buffer.lock().map(|buffer| buffer.end()).unwrap_or(0)
+    }
+    
+    /// Execute a command and wait for it to exit, detected by an exit sentinel
+    ///
+    /// The sentinel code is left out of the echoed command line in the output
+    /// buffer and output events, so later screenshots and casts show the
+    /// command as written.
+    pub async fn run_command(&mut self, command: &str, timeout_duration: Duration) -> Result<CommandRun> {
+        if self.direct {
+            return Err(KlaError::invalid_state(format!(
+                "cannot wait for `{}` to exit without a shell; run_program is set",
+                command
+            )));
+        }
+        let start = self.buffer_len();
+        let line = self.command_line(command, true);
+        self.send_input(&format!("{}\n", line)).await?;
+        
+        let deadline = tokio::time::Instant::now() + timeout_duration;
+        loop {
+            let notified = self.output_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            
+            if let Some(run) = self.take_command_run(start) {
+                self.expire_hidden_echoes();
+                return Ok(run);
+            }
+            
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return self.take_command_run(start).ok_or_else(|| {
+                    KlaError::timeout(format!("command `{}` to exit after {:?}", command, timeout_duration))
+                });
+            }
+        }
+    }
+    
+    /// The finished command's run if its sentinel arrived after `start`
+    fn take_command_run(&self, start: usize) -> Option<CommandRun> {
+        let output = self.read_since(start);
+        let sentinel = output.find(EXIT_SENTINEL)?;
+        let status = &output[sentinel + EXIT_SENTINEL.len()..];
+        let exit_code = status[..status.find('\x07')?].parse().ok()?;
+        Some(CommandRun { exit_code, output: output[..sentinel].to_string() })
     }
     
     pub async fn send_input(&mut self, input: &str) -> Result<()> {
         self.writer.write_all(input.as_bytes())
-            .context("Failed to write to PTY")?;
+            .map_err(|err| KlaError::io_context(err, "Failed to write to PTY"))?;
         self.writer.flush()
-            .context("Failed to flush PTY writer")?;
+            .map_err(|err| KlaError::io_context(err, "Failed to flush PTY writer"))?;
         Ok(())
     }
     
+    /// Stream a file's contents as input, then signal end of file with Ctrl-D
+    ///
+    /// The file is read before anything is sent, so a missing file leaves the
+    /// terminal untouched.
+    pub async fn send_file(&mut self, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| KlaError::io_context(err, format_args!("Failed to read stdin file {}", path.display())))?;
+        
+        // Line by line keeps each write under the tty's canonical line limit
+        for line in contents.split_inclusive('\n') {
+            self.send_input(line).await?;
+        }
+        // Ctrl-D only means EOF at the start of a line; otherwise it just
+        // flushes the pending partial line and a second one is needed
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            self.send_input("\x04").await?;
+        }
+        self.send_input("\x04").await
+    }
+    
     pub async fn type_text(&mut self, text: &str, delay_per_char: Duration) -> Result<()> {
+        self.type_text_with(text, delay_per_char, |_| {}).await
+    }
+    
+    /// Type text like `type_text`, invoking `on_char` after each character is sent
+    pub async fn type_text_with<F: FnMut(char)>(
+        &mut self,
+        text: &str,
+        delay_per_char: Duration,
+        mut on_char: F,
+    ) -> Result<()> {
+        self.type_text_paced(text, |ch| {
+            on_char(ch);
+            delay_per_char
+        })
+        .await
+    }
+    
+    /// Type text one character at a time, waiting however long `delay` returns after each one
+    pub async fn type_text_paced<F: FnMut(char) -> Duration>(&mut self, text: &str, mut delay: F) -> Result<()> {
         for ch in text.chars() {
             self.send_input(&ch.to_string()).await?;
-            tokio::time::sleep(delay_per_char).await;
+            tokio::time::sleep(delay(ch)).await;
         }
         Ok(())
     }
     
     pub fn get_output(&self) -> String {
         self.buffer.lock()
-            .map(|buffer| buffer.clone())
+            .map(|buffer| buffer.text.clone())
+            .unwrap_or_default()
+    }
+    
+    /// The current end of the output buffer, for `read_since`
+    pub fn mark(&self) -> usize {
+        self.buffer_len()
+    }
+    
+    /// The earliest mark `read_since` can still read from
+    pub fn oldest_mark(&self) -> usize {
+        self.buffer.lock().map(|buffer| buffer.dropped).unwrap_or(0)
+    }
+    
+    /// Output that arrived after `mark`, leaving the buffer as it is
+    ///
+    /// A mark taken before `clear_buffer` is stale and reads nothing.
+    pub fn read_since(&self, mark: usize) -> String {
+        self.buffer.lock()
+            .map(|buffer| buffer.since(mark).to_string())
+            .unwrap_or_default()
+    }
+    
+    /// Get output with escape sequences and control characters removed
+    pub fn get_plain_output(&self) -> String {
+        ansi::strip_ansi(&self.get_output())
+    }
+    
+    /// Time since the terminal started, on the clock output events use
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+    
+    /// Every byte the PTY has sent, exactly as read, with `raw_capture` set
+    ///
+    /// Unlike `get_output`, nothing is decoded or removed, including the
+    /// output of startup commands. Empty without `raw_capture`.
+    pub fn get_raw_bytes(&self) -> Vec<u8> {
+        self.raw
+            .as_ref()
+            .and_then(|raw| raw.lock().ok().map(|raw| raw.clone()))
+            .unwrap_or_default()
+    }
+    
+    /// Get every output chunk received so far with its arrival time
+    ///
+    /// Like `get_output`, events leave out KLA's echoed code, and they also
+    /// leave out the exit sentinels `run_command` prints, so a recording of
+    /// them shows only what a user would.
+    pub fn get_output_events(&self) -> Vec<OutputEvent> {
+        self.buffer.lock()
+            .map(|buffer| buffer.events.clone())
             .unwrap_or_default()
     }
     
+    /// Receive each chunk of output as it arrives, from now on
+    ///
+    /// Chunks are the same text appended to the buffer returned by
+    /// `get_output`. A receiver that falls too far behind gets
+    /// `RecvError::Lagged` and skips ahead.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.output_tx.subscribe()
+    }
+    
     pub fn get_size(&self) -> (u16, u16) {
-        let size = self.pty_pair.master.get_size()
+        let size = self.master.get_size()
             .unwrap_or(PtySize {
                 rows: 24,
                 cols: 80,
@@ -123,29 +734,571 @@ impl Terminal {
     }
     
     pub async fn wait_for_output(&self, pattern: &str, timeout_duration: Duration) -> Result<bool> {
-        let start = std::time::Instant::now();
+        let deadline = tokio::time::Instant::now() + timeout_duration;
         
-        while start.elapsed() < timeout_duration {
-            let output = self.get_output();
-            if output.contains(pattern) {
+        loop {
+            // Register for the next notification before checking, so output
+            // arriving between the check and the wait isn't missed
+            let notified = self.output_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            
+            if self.output_matches(pattern) {
                 return Ok(true);
             }
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Ok(self.output_matches(pattern));
+            }
         }
-        
-        Ok(false)
+    }
+    
+    /// Wait until no output has arrived for a short quiet period
+    ///
+    /// Gives up after a few seconds of continuous output. Returns whether the
+    /// output settled.
+    pub async fn wait_for_settled(&self) -> bool {
+        let deadline = tokio::time::Instant::now() + SETTLE_TIMEOUT;
+        loop {
+            let notified = self.output_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let seen = self.event_count();
+            
+            if tokio::time::timeout(SETTLE_QUIET, notified).await.is_err() && self.event_count() == seen {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+        }
+    }
+    
+    fn event_count(&self) -> usize {
+        self.buffer.lock().map(|buffer| buffer.events.len()).unwrap_or(0)
+    }
+    
+    /// Match the raw stream or the text with escape codes removed
+    fn output_matches(&self, pattern: &str) -> bool {
+        let output = self.get_output();
+        output.contains(pattern) || ansi::strip_ansi(&output).contains(pattern)
     }
     
     pub fn clear_buffer(&self) {
         if let Ok(mut buffer) = self.buffer.lock() {
-            buffer.clear();
+            let len = buffer.text.len();
+            buffer.drop_front(len);
+        }
+    }
+}
+
+/// What the PTY runs, for error messages
+fn cmd_name(settings: &TerminalSettings) -> String {
+    match settings.run_program.as_ref().and_then(|program| program.first()) {
+        Some(program) => format!("program `{}`", program),
+        None => format!("shell `{}`", settings.shell),
+    }
+}
+
+/// Commands that make `shell` show `prompt`, and the same for continuation lines
+///
+/// zsh expands `%` in prompts and bash expands `\`, so those are escaped,
+/// and both lose the hooks that would redraw their usual prompt.
+fn fake_prompt_commands(shell: &str, prompt: &str) -> Vec<String> {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "'\\''"));
+    let name = Path::new(shell).file_name().and_then(|name| name.to_str()).unwrap_or(shell);
+    match name {
+        "zsh" => {
+            let prompt = quote(&prompt.replace('%', "%%"));
+            vec![format!("precmd_functions=(); RPROMPT=''; PS1={0}; PS2={0}", prompt)]
+        }
+        "bash" => {
+            let prompt = quote(&prompt.replace('\\', "\\\\"));
+            vec![format!("PROMPT_COMMAND=''; PS1={0}; PS2={0}", prompt)]
+        }
+        _ => vec![format!("PS1={0}; PS2={0}", quote(prompt))],
+    }
+}
+
+impl Terminal {
+    /// Whether the shell (or `run_program`) is still running
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+    
+    /// Wait up to `timeout` for the shell or program to exit, returning its exit code
+    ///
+    /// Returns `None` if it is still running when the timeout passes.
+    pub async fn wait_for_exit(&mut self, timeout: Duration) -> Option<i32> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => return Some(status.exit_code() as i32),
+                Ok(None) if tokio::time::Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                _ => return None,
+            }
         }
     }
+    
+    /// Ask the shell to exit, wait up to the grace period for it, then kill it
+    ///
+    /// Returns whether the child exited on its own; it is reaped either way,
+    /// and the reader has stopped. A terminal dropped without closing only
+    /// hangs up on its child, and its reader stops at EOF in the background.
+    pub async fn close(&mut self) -> bool {
+        let exited = self.stop_child().await;
+        // With the child gone the reader sees EOF; wait for it so nothing
+        // outlives the terminal
+        if let Some(reader_done) = self.reader_done.take() {
+            let _ = tokio::time::timeout(READER_SHUTDOWN_TIMEOUT, reader_done).await;
+        }
+        exited
+    }
+    
+    async fn stop_child(&mut self) -> bool {
+        if let Ok(Some(_)) = self.child.try_wait() {
+            return true;
+        }
+        
+        // `exit` lets the shell run its EXIT traps and flush output; a hangup
+        // would skip them in most shells. A program gets EOF instead.
+        let goodbye: &[u8] = if self.direct { b"\x04" } else { b"exit\n" };
+        let sent = self.writer.write_all(goodbye).and_then(|_| self.writer.flush()).is_ok();
+        if sent && self.wait_for_exit(self.shutdown_grace).await.is_some() {
+            return true;
+        }
+        
+        let _ = self.child.clone_killer().kill();
+        if self.wait_for_exit(HANGUP_GRACE).await.is_none() {
+            // Escalates to SIGKILL, as the child ignored the hangup
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        false
+    }
+}
+
+/// Decode and remove everything in `bytes` except an incomplete trailing character
+///
+/// Invalid sequences decode lossily, as replacement characters.
+fn take_decodable(bytes: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        // `error_len` is `None` only when the input ends mid-character
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => bytes.len(),
+    };
+    let text = String::from_utf8_lossy(&bytes[..complete]).into_owned();
+    bytes.drain(..complete);
+    text
 }
 
 impl Drop for Terminal {
     fn drop(&mut self) {
-        let _ = self.child.kill();
-        let _ = self.child.wait();
+        // Without `close` the child is only hung up on, as waiting here would
+        // block whichever thread drops the terminal
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.clone_killer().kill();
+            let _ = self.child.try_wait();
+        }
+    }
+}
+
+/// Run the blocking PTY read loop on tokio's blocking pool, or on its own
+/// thread when no runtime is running
+fn spawn_reader(read_loop: impl FnOnce() + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn_blocking(read_loop);
+        }
+        Err(_) => {
+            std::thread::spawn(read_loop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_split_utf8_character_waits_for_its_last_byte() {
+        let mut bytes = "hé".as_bytes()[..2].to_vec();
+        assert_eq!(take_decodable(&mut bytes), "h");
+        bytes.push("é".as_bytes()[1]);
+        assert_eq!(take_decodable(&mut bytes), "é");
+        assert!(bytes.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_wait_for_output_wakes_on_new_output() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let terminal = Terminal::new(&settings).await.unwrap();
+        
+        let buffer = terminal.buffer.clone();
+        let notify = terminal.output_notify.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            buffer.lock().unwrap().push("needle", Duration::ZERO);
+            notify.notify_waiters();
+        });
+        
+        // Only `false` if the timeout, far longer than the push takes, passes first
+        assert!(terminal.wait_for_output("needle", Duration::from_secs(5)).await.unwrap());
+    }
+    
+    #[tokio::test]
+    async fn test_close_and_drop_stop_reader() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        let buffer = Arc::downgrade(&terminal.buffer);
+        
+        terminal.close().await;
+        drop(terminal);
+        // The reader held the only other handle to the output buffer
+        assert!(buffer.upgrade().is_none());
+        
+        // Dropping alone leaves the reader to stop at EOF once the shell hangs up
+        let terminal = Terminal::new(&settings).await.unwrap();
+        let buffer = Arc::downgrade(&terminal.buffer);
+        drop(terminal);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while buffer.upgrade().is_some() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(buffer.upgrade().is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_startup_commands_run_before_first_screen() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            width: 40,
+            height: 10,
+            startup_commands: vec![
+                "export KLA_GREETING=hello".to_string(),
+                "PS1='$ '".to_string(),
+            ],
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        assert!(terminal.wait_for_output("$ ", Duration::from_secs(5)).await.unwrap());
+        
+        let mut screen = capture::TerminalCapture::new(40, 10);
+        screen.process_output(&terminal.get_output()).unwrap();
+        let first_screen = screen.get_screen_contents();
+        assert!(first_screen.starts_with("$ "), "{:?}", first_screen);
+        assert!(!first_screen.contains("KLA_GREETING"), "{:?}", first_screen);
+        assert!(!first_screen.contains("PS1"), "{:?}", first_screen);
+        
+        let run = terminal.run_command("echo \"[$KLA_GREETING]\"", Duration::from_secs(5)).await.unwrap();
+        assert!(ansi::strip_ansi(&run.output).contains("[hello]"), "{:?}", run.output);
+    }
+    
+    #[tokio::test]
+    async fn test_prompt_pattern_waits_for_command_to_finish() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            startup_commands: vec!["PS1='$ '".to_string()],
+            prompt_pattern: Some("\\$ $".to_string()),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        
+        let started = Instant::now();
+        terminal.execute_command("true").await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+        
+        let started = Instant::now();
+        terminal.execute_command("sleep 1").await.unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(950), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+    }
+    
+    #[tokio::test]
+    async fn test_run_command_reports_exit_status() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        
+        let run = terminal.run_command("echo do''ne", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        assert!(ansi::strip_ansi(&run.output).contains("done"));
+        assert!(!terminal.get_output().contains("kla-exit=%d"));
+        
+        let run = terminal.run_command("false", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 1);
+        
+        let error = terminal.run_command("sleep 5", Duration::from_millis(200)).await.unwrap_err();
+        assert!(matches!(error, KlaError::Timeout(_)), "{:?}", error);
+        
+        // Recordings of the session show neither the sentinel code nor its output
+        let events: String = terminal.get_output_events().into_iter().map(|event| event.data).collect();
+        assert!(events.contains("echo do''ne"), "{:?}", events);
+        assert!(!events.contains("kla-exit"), "{:?}", events);
+    }
+    
+    #[tokio::test]
+    async fn test_fake_prompt_replaces_shell_prompt() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            fake_prompt: Some("› ".to_string()),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        assert!(terminal.wait_for_output("› ", Duration::from_secs(5)).await.unwrap());
+        
+        let run = terminal.run_command("echo one; echo two", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        terminal.execute_command("echo 'unfinished").await.unwrap();
+        terminal.execute_command("quote'").await.unwrap();
+        assert!(terminal.wait_for_output("quote\n› ", Duration::from_secs(5)).await.unwrap());
+        
+        let screen = terminal.get_plain_output();
+        assert!(screen.starts_with("› echo one; echo two\none\ntwo\n› "), "{:?}", screen);
+        // Continuation lines get the same prompt
+        assert!(screen.contains("› echo 'unfinished\n› quote'"), "{:?}", screen);
+        
+        assert_eq!(
+            fake_prompt_commands("/usr/bin/zsh", "100% ready $ "),
+            vec!["precmd_functions=(); RPROMPT=''; PS1='100%% ready $ '; PS2='100%% ready $ '"]
+        );
+        assert_eq!(
+            fake_prompt_commands("bash", "it's \\ $ "),
+            vec!["PROMPT_COMMAND=''; PS1='it'\\''s \\\\ $ '; PS2='it'\\''s \\\\ $ '"]
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_separate_stderr_splits_the_streams() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            startup_commands: vec!["PS1='$ '".to_string()],
+            separate_stderr: true,
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        assert!(terminal.wait_for_output("$ ", Duration::from_secs(5)).await.unwrap());
+        
+        let run = terminal.run_command("echo out; echo err >&2", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        assert_eq!(terminal.get_stderr(), "err\n");
+        
+        let screen = terminal.get_plain_output();
+        assert!(screen.contains("$ echo out; echo err >&2\nout\n"), "{:?}", screen);
+        assert!(!screen.contains("err\n$"), "{:?}", screen);
+        assert!(!screen.contains("kla-stderr"), "{:?}", screen);
+    }
+    
+    #[tokio::test]
+    async fn test_separate_stderr_keeps_comments_and_background_jobs() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            startup_commands: vec!["PS1='$ '".to_string()],
+            separate_stderr: true,
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        assert!(terminal.wait_for_output("$ ", Duration::from_secs(5)).await.unwrap());
+        
+        let run = terminal.run_command("echo 'a # b' >&2 # note", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        let run = terminal.run_command("sleep 0 &", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(run.exit_code, 0);
+        assert_eq!(terminal.get_stderr(), "a # b\n");
+        
+        let screen = terminal.get_plain_output();
+        assert!(screen.contains("$ echo 'a # b' >&2 # note\n$ sleep 0 &\n"), "{:?}", screen);
+        assert!(!screen.contains("kla-"), "{:?}", screen);
+    }
+    
+    #[tokio::test]
+    async fn test_subscribe_receives_output_chunks() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        let mut output = terminal.subscribe();
+        
+        // The quotes keep the echoed command line itself from matching
+        terminal.execute_command("echo h''i").await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let chunk = output.recv().await.unwrap();
+                if chunk.contains("hi") {
+                    return chunk;
+                }
+            }
+        })
+        .await
+        .expect("no chunk containing output arrived");
+        
+        assert!(terminal.get_output().contains(&received));
+    }
+    
+    #[tokio::test]
+    async fn test_high_volume_output_is_captured_quickly() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        
+        let start = Instant::now();
+        let run = terminal
+            .run_command("head -c 1048576 /dev/zero | tr '\\0' x", Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+        // Everything after the echoed command line is the program's output
+        let (_, printed) = run.output.split_once('\n').unwrap();
+        assert_eq!(printed.matches('x').count(), 1 << 20);
+    }
+    
+    #[test]
+    fn test_hidden_echo_split_across_chunks_is_left_out() {
+        let mut buffer = OutputBuffer::default();
+        buffer.hide(["{ ".to_string(), " ; } 2>>'f'".to_string()]);
+        for chunk in ["$ {", " echo hi ;", " } 2", ">>'f'\r\nhi\r\n"] {
+            buffer.push(chunk, Duration::ZERO);
+        }
+        assert_eq!(buffer.text, "$ echo hi\r\nhi\r\n");
+        assert!(buffer.hidden.is_empty());
+        let events: String = buffer.events.iter().map(|event| event.data.as_str()).collect();
+        assert_eq!(events, buffer.text);
+    }
+    
+    #[test]
+    fn test_expired_hidden_echo_releases_held_text() {
+        let mut buffer = OutputBuffer::default();
+        buffer.hide([" ; printf".to_string()]);
+        buffer.push("$ echo hi ;", Duration::ZERO);
+        assert_eq!(buffer.text, "$ echo hi");
+        assert_eq!(buffer.expire(Duration::ZERO), " ;");
+        buffer.push(" printf", Duration::ZERO);
+        assert_eq!(buffer.text, "$ echo hi ; printf");
+    }
+    
+    #[test]
+    fn test_events_leave_out_split_exit_sentinels() {
+        let mut buffer = OutputBuffer::default();
+        for chunk in ["hi\x1b]6", "97;kla-", "exit=0\x07$ "] {
+            buffer.push(chunk, Duration::ZERO);
+        }
+        assert!(buffer.text.contains("kla-exit=0"));
+        let events: Vec<&str> = buffer.events.iter().map(|event| event.data.as_str()).collect();
+        assert_eq!(events, ["hi", "$ "]);
+    }
+
+    #[tokio::test]
+    async fn test_startup_commands_leave_the_runtime_free() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            startup_commands: vec!["sleep 0.3".to_string()],
+            ..TerminalSettings::default()
+        };
+        
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        
+        // The test runtime has a single thread, which a blocking wait would hold
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let _terminal = Terminal::new(&settings).await.unwrap();
+        ticker.abort();
+        
+        assert!(ticks.load(Ordering::Relaxed) >= 5, "{} ticks", ticks.load(Ordering::Relaxed));
+    }
+    
+    #[tokio::test]
+    async fn test_missing_working_dir_fails_or_is_created() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let missing = temp_dir.path().join("not").join("yet");
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            working_dir: Some(missing.clone()),
+            startup_commands: vec!["PS1='$ '".to_string()],
+            ..TerminalSettings::default()
+        };
+        
+        let error = Terminal::new(&settings).await.err().unwrap();
+        assert!(error.to_string().contains(&missing.display().to_string()), "{}", error);
+        assert!(!missing.exists());
+        
+        let mut terminal = Terminal::new(&TerminalSettings { create_working_dir: true, ..settings }).await.unwrap();
+        assert!(missing.is_dir());
+        let run = terminal.run_command("pwd", Duration::from_secs(5)).await.unwrap();
+        assert!(run.output.contains(&missing.display().to_string()), "{:?}", run.output);
+    }
+    
+    #[tokio::test]
+    async fn test_run_program_without_a_shell() {
+        let settings = TerminalSettings {
+            run_program: Some(vec!["cat".to_string()]),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        terminal.execute_command("hello from kla").await.unwrap();
+        
+        // The line is echoed by the PTY, then again by cat itself
+        assert!(terminal.wait_for_output("hello from kla\r\nhello from kla", Duration::from_secs(5)).await.unwrap());
+        assert!(terminal.run_command("true", Duration::from_secs(1)).await.is_err());
+        
+        let pid = terminal.child.process_id().unwrap();
+        assert!(terminal.close().await, "cat exits at EOF");
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_drop_does_not_wait_for_shutdown_grace() {
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            shutdown_grace: Duration::from_secs(5),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        // A busy shell would only read `exit` once the sleep ends
+        terminal.execute_command("sleep 30").await.unwrap();
+        
+        let started = Instant::now();
+        drop(terminal);
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    }
+    
+    #[tokio::test]
+    async fn test_close_lets_shell_exit_and_run_traps() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let marker = temp_dir.path().join("cleaned-up");
+        let settings = TerminalSettings {
+            shell: "/bin/sh".to_string(),
+            ..TerminalSettings::default()
+        };
+        let mut terminal = Terminal::new(&settings).await.unwrap();
+        terminal.execute_command(&format!("trap 'touch {}' EXIT; echo ar''med", marker.display())).await.unwrap();
+        assert!(terminal.wait_for_output("armed", Duration::from_secs(5)).await.unwrap());
+        
+        let pid = terminal.child.process_id().unwrap();
+        assert!(terminal.close().await);
+        
+        assert!(marker.exists(), "EXIT trap did not run");
+        // A reaped child leaves no /proc entry behind, zombie or otherwise
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::error::{KlaError, Result};
+
+/// A mouse action sent to the terminal as an SGR (mode 1006) report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEvent {
+    /// Left button down
+    Press,
+    /// Left button up
+    Release,
+    /// A press followed by a release
+    Click,
+    ScrollUp,
+    ScrollDown,
+}
+
+/// Event names accepted by `mouse` steps
+pub const MOUSE_EVENTS: &[&str] = &["press", "release", "click", "scroll_up", "scroll_down"];
+
+impl MouseEvent {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().replace('-', "_").as_str() {
+            "press" => Ok(MouseEvent::Press),
+            "release" => Ok(MouseEvent::Release),
+            "click" => Ok(MouseEvent::Click),
+            "scroll_up" => Ok(MouseEvent::ScrollUp),
+            "scroll_down" => Ok(MouseEvent::ScrollDown),
+            other => Err(KlaError::parse(format!(
+                "Unknown mouse event '{}'. Expected one of: {}",
+                other,
+                MOUSE_EVENTS.join(", ")
+            ))),
+        }
+    }
+
+    /// The bytes a terminal reports for this event at column `x`, row `y`
+    ///
+    /// Coordinates are 1-based, with (1, 1) the top-left cell.
+    pub fn sequence(self, x: u16, y: u16) -> Result<String> {
+        if x == 0 || y == 0 {
+            return Err(KlaError::parse(format!(
+                "Mouse position ({}, {}) is outside the screen; coordinates start at 1",
+                x, y
+            )));
+        }
+        let report = |button: u8, end: char| format!("\x1b[<{};{};{}{}", button, x, y, end);
+        Ok(match self {
+            MouseEvent::Press => report(0, 'M'),
+            MouseEvent::Release => report(0, 'm'),
+            MouseEvent::Click => format!("{}{}", report(0, 'M'), report(0, 'm')),
+            MouseEvent::ScrollUp => report(64, 'M'),
+            MouseEvent::ScrollDown => report(65, 'M'),
+        })
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use super::Script;
+use crate::media::svg::escape_xml;
+
+/// Width of a label character in the flowchart, in pixels
+const CHAR_WIDTH: usize = 8;
+const NODE_HEIGHT: usize = 36;
+/// Vertical space between nodes, where the arrows go
+const NODE_GAP: usize = 28;
+const MARGIN: usize = 20;
+/// Labels longer than this are cut off with an ellipsis
+const MAX_LABEL_CHARS: usize = 60;
+
+impl Script {
+    /// Draw the script's steps as a top-to-bottom flowchart at `path`
+    ///
+    /// `.svg` paths get an SVG image and `.dot` paths a Graphviz graph, one
+    /// node per step labelled with its type and text.
+    pub fn render_flowchart(&self, path: &Path) -> Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let contents = match extension.as_deref() {
+            Some("svg") => self.flowchart_svg(),
+            Some("dot") => self.flowchart_dot(),
+            _ => anyhow::bail!(
+                "Can't draw a flowchart as {}; use a .svg or .dot file",
+                path.display()
+            ),
+        };
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write flowchart: {}", path.display()))?;
+
+        log::info!("Flowchart saved to: {}", path.display());
+        Ok(())
+    }
+
+    /// The flowchart as an SVG document
+    pub fn flowchart_svg(&self) -> String {
+        let labels = self.flowchart_labels();
+        let longest = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+        let node_width = (longest + 4) * CHAR_WIDTH;
+        let width = node_width + 2 * MARGIN;
+        let height = 2 * MARGIN + labels.len() * NODE_HEIGHT + labels.len().saturating_sub(1) * NODE_GAP;
+        let center = MARGIN + node_width / 2;
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        svg.push_str(
+            r##"<defs><marker id="arrow" markerWidth="10" markerHeight="10" refX="8" refY="5" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="#555"/></marker></defs>"##,
+        );
+        let _ = write!(svg, r##"<rect width="{width}" height="{height}" fill="#ffffff"/>"##);
+        for (index, label) in labels.iter().enumerate() {
+            let top = MARGIN + index * (NODE_HEIGHT + NODE_GAP);
+            if index > 0 {
+                let _ = write!(
+                    svg,
+                    r##"<line x1="{center}" y1="{}" x2="{center}" y2="{}" stroke="#555" stroke-width="2" marker-end="url(#arrow)"/>"##,
+                    top - NODE_GAP,
+                    top - 2
+                );
+            }
+            let _ = write!(
+                svg,
+                r##"<rect x="{MARGIN}" y="{top}" width="{node_width}" height="{NODE_HEIGHT}" rx="6" fill="#eef3fb" stroke="#3465a4" stroke-width="1.5"/>"##
+            );
+            let _ = write!(
+                svg,
+                r##"<text x="{center}" y="{}" font-family="monospace" font-size="13" fill="#1e1e1e" text-anchor="middle" dominant-baseline="middle">{}</text>"##,
+                top + NODE_HEIGHT / 2,
+                escape_xml(label)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The flowchart as a Graphviz `digraph`
+    pub fn flowchart_dot(&self) -> String {
+        let mut dot = format!("digraph {:?} {{\n    node [shape=box, style=rounded];\n", self.name);
+        for (index, label) in self.flowchart_labels().iter().enumerate() {
+            let _ = writeln!(dot, "    step{} [label={:?}];", index + 1, label);
+            if index > 0 {
+                let _ = writeln!(dot, "    step{} -> step{};", index, index + 1);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// One label per step, e.g. `2. command "ls -la"`
+    fn flowchart_labels(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let label = format!("{}. {}", index + 1, step.step_type.summary());
+                if label.chars().count() > MAX_LABEL_CHARS {
+                    let cut: String = label.chars().take(MAX_LABEL_CHARS - 1).collect();
+                    format!("{}…", cut)
+                } else {
+                    label
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flowchart_svg_has_node_per_step() {
+        let script: Script = serde_yaml::from_str(
+            r#"
+name: Flow
+settings: {}
+steps:
+  - type: command
+    text: ls -la
+  - type: type
+    text: echo <hi>
+  - type: screenshot
+    name: result
+"#,
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("kla-flowchart-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flow.svg");
+        script.render_flowchart(&path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("1. command &quot;ls -la&quot;"), "{}", svg);
+        assert!(svg.contains("2. type &quot;echo &lt;hi&gt;&quot;"), "{}", svg);
+        assert!(svg.contains("3. screenshot &apos;result&apos;"), "{}", svg);
+        assert_eq!(svg.matches("<line").count(), 2);
+    }
+}
@@ -1,40 +1,77 @@
+use std::io::Read;
 use std::path::Path;
-use anyhow::{Context, Result};
-use crate::script::Script;
+use crate::error::{KlaError, Result};
+use crate::script::{Script, StepType};
+use crate::script::validate::{flatten, record_gif_problem};
 
 pub struct ScriptLoader;
 
 impl ScriptLoader {
+    /// Read a YAML script from `path`, failing as `load_from_string` would on bad content
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Script> {
         let path = path.as_ref();
-        let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read script file: {}", path.display()))?;
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            KlaError::io_context(err, format_args!("Failed to read script file {}", path.display()))
+        })?;
         
         Self::load_from_string(&content)
-            .with_context(|| format!("Failed to parse script file: {}", path.display()))
     }
     
     pub fn load_from_string(content: &str) -> Result<Script> {
-        serde_yaml::from_str(content)
-            .context("Failed to parse YAML script")
+        checked(serde_yaml::from_str(content)?)
+    }
+    
+    /// Read a whole script from `reader`, such as stdin
+    ///
+    /// With no file extension to go by, content starting with `{` is parsed
+    /// as JSON and anything else as YAML.
+    pub fn load_from_reader<R: Read>(mut reader: R) -> Result<Script> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|err| KlaError::io_context(err, "Failed to read script"))?;
+        
+        if content.trim_start().starts_with('{') {
+            checked(serde_json::from_str(&content)?)
+        } else {
+            Self::load_from_string(&content)
+        }
+    }
+    
+    /// JSON Schema describing the script format, for editor completion and validation
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Script))
+            .expect("script schema is always serializable")
     }
     
     pub fn save_to_file<P: AsRef<Path>>(script: &Script, path: P) -> Result<()> {
         let path = path.as_ref();
-        let content = serde_yaml::to_string(script)
-            .context("Failed to serialize script to YAML")?;
+        let content = serde_yaml::to_string(script)?;
         
-        std::fs::write(path, content)
-            .with_context(|| format!("Failed to write script file: {}", path.display()))?;
+        std::fs::write(path, content).map_err(|err| {
+            KlaError::io_context(err, format_args!("Failed to write script file {}", path.display()))
+        })?;
         
         Ok(())
     }
 }
 
+/// Reject steps that parse but could never work, like a `record_gif` lasting no time
+fn checked(script: Script) -> Result<Script> {
+    for (index, step, _) in flatten(&script.steps) {
+        if let StepType::RecordGif { duration, name, fps, .. } = &step.step_type {
+            if let Some(problem) = record_gif_problem(name, *duration, *fps) {
+                return Err(KlaError::parse(format!("step {}: {}", index + 1, problem)));
+            }
+        }
+    }
+    Ok(script)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::script::{ScriptStep, StepType, TerminalSettings};
+    use crate::script::{ScriptStep, TerminalSettings};
     use std::time::Duration;
     
     #[test]
@@ -63,30 +100,302 @@ steps:
         assert_eq!(script.steps.len(), 3);
     }
     
+    #[test]
+    fn test_load_annotate_step() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Annotated"
+settings: {}
+steps:
+  - type: annotate
+    text: "Now we install the package"
+    duration: "2s"
+    position: "top"
+  - type: annotate
+    text: "Defaults to the bottom"
+    duration: "500ms"
+"#).unwrap();
+        
+        match &script.steps[0].step_type {
+            StepType::Annotate { text, duration, position } => {
+                assert_eq!(text, "Now we install the package");
+                assert_eq!(*duration, Duration::from_secs(2));
+                assert_eq!(position.as_deref(), Some("top"));
+            }
+            other => panic!("Expected Annotate step, got {:?}", other),
+        }
+        match &script.steps[1].step_type {
+            StepType::Annotate { duration, position, .. } => {
+                assert_eq!(*duration, Duration::from_millis(500));
+                assert_eq!(*position, None);
+            }
+            other => panic!("Expected Annotate step, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_load_per_step_theme() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Themed"
+settings:
+  theme: "default"
+steps:
+  - type: screenshot
+    name: "dark"
+    theme: "dracula"
+  - type: record_gif
+    name: "plain"
+    duration: "1s"
+"#).unwrap();
+        
+        match &script.steps[0].step_type {
+            StepType::Screenshot { theme, .. } => assert_eq!(theme.as_deref(), Some("dracula")),
+            other => panic!("Expected Screenshot step, got {:?}", other),
+        }
+        match &script.steps[1].step_type {
+            StepType::RecordGif { theme, .. } => assert_eq!(*theme, None),
+            other => panic!("Expected RecordGif step, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_load_two_pane_layout() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Client and server"
+settings: {}
+layout:
+  panes: ["server", "client"]
+  direction: "vertical"
+  gap: 8
+steps:
+  - type: command
+    pane: "server"
+    text: "python3 -m http.server"
+  - type: command
+    text: "curl localhost:8000"
+"#).unwrap();
+        
+        let layout = script.layout.as_ref().unwrap();
+        assert_eq!(layout.panes, vec!["server", "client"]);
+        assert_eq!(layout.direction.as_deref(), Some("vertical"));
+        assert_eq!(layout.gap, 8);
+        assert_eq!(script.steps[0].pane.as_deref(), Some("server"));
+        assert_eq!(script.pane_index(&script.steps[0]), Some(0));
+        assert_eq!(script.steps[1].pane, None);
+        assert_eq!(script.pane_index(&script.steps[1]), Some(0));
+    }
+    
+    #[test]
+    fn test_load_from_reader_sniffs_format() {
+        let yaml = "name: \"Piped\"\nsettings:\n  width: 60\nsteps:\n  - type: command\n    text: \"echo piped\"\n";
+        let script = ScriptLoader::load_from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(script.name, "Piped");
+        assert_eq!(script.settings.width, 60);
+        match &script.steps[0].step_type {
+            StepType::Command { text, .. } => assert_eq!(text, "echo piped"),
+            other => panic!("Expected Command step, got {:?}", other),
+        }
+        
+        let json = serde_json::to_string(&script).unwrap();
+        let from_json = ScriptLoader::load_from_reader(std::io::Cursor::new(json)).unwrap();
+        assert_eq!(serde_yaml::to_string(&from_json).unwrap(), serde_yaml::to_string(&script).unwrap());
+        
+        let bad_json = ScriptLoader::load_from_reader(&b"{\"name\": "[..]).unwrap_err();
+        assert!(matches!(bad_json, KlaError::Json(_)), "{:?}", bad_json);
+    }
+    
+    #[test]
+    fn test_load_errors_are_typed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        
+        let missing = ScriptLoader::load_from_file(temp_dir.path().join("missing.kla.yaml")).unwrap_err();
+        match missing {
+            KlaError::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("Expected KlaError::Io, got {:?}", other),
+        }
+        
+        let bad_yaml = "name: [unclosed\nsteps: {";
+        assert!(matches!(ScriptLoader::load_from_string(bad_yaml), Err(KlaError::Yaml(_))));
+        
+        // Files fail the same way, keeping the YAML error as the source
+        let path = temp_dir.path().join("bad.kla.yaml");
+        std::fs::write(&path, bad_yaml).unwrap();
+        let error = ScriptLoader::load_from_file(&path).unwrap_err();
+        assert!(matches!(error, KlaError::Yaml(_)), "{:?}", error);
+        assert!(std::error::Error::source(&error).is_some_and(|source| source.is::<serde_yaml::Error>()));
+    }
+    
+    #[test]
+    fn test_json_schema_validates_scripts() {
+        let schema = ScriptLoader::json_schema();
+        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let as_json = |yaml: &str| -> serde_json::Value { serde_yaml::from_str(yaml).unwrap() };
+        
+        let example = std::fs::read_to_string(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/examples/git-workflow.kla.yaml"),
+        ).unwrap();
+        assert!(validator.is_valid(&as_json(&example)));
+        
+        let unknown_step = as_json(r#"
+name: "Bad"
+settings: {}
+steps:
+  - type: teleport
+    text: "elsewhere"
+"#);
+        assert!(!validator.is_valid(&unknown_step));
+    }
+    
+    #[test]
+    fn test_description_and_notes_survive_save() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Documented"
+description: "Shows how to list files"
+settings: {}
+steps:
+  - type: command
+    text: "ls"
+    note: "Plain listing first"
+  - type: command
+    text: "ls -la"
+"#).unwrap();
+        
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("documented.kla.yaml");
+        ScriptLoader::save_to_file(&script, &path).unwrap();
+        let loaded = ScriptLoader::load_from_file(&path).unwrap();
+        
+        assert_eq!(loaded.description.as_deref(), Some("Shows how to list files"));
+        assert_eq!(loaded.steps[0].note.as_deref(), Some("Plain listing first"));
+        assert_eq!(loaded.steps[1].note, None);
+    }
+    
+    #[test]
+    fn test_when_condition_loads() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Conditional"
+settings: {}
+steps:
+  - type: command
+    text: "service start"
+    when: "service is (stopped|missing)"
+  - type: screenshot
+    name: "status"
+"#).unwrap();
+        
+        assert_eq!(script.steps[0].when.as_deref(), Some("service is (stopped|missing)"));
+        assert_eq!(script.steps[1].when, None);
+        assert!(script.validate().is_empty());
+        
+        let yaml = serde_yaml::to_string(&script).unwrap();
+        assert_eq!(yaml.matches("when:").count(), 1, "{}", yaml);
+    }
+    
+    #[test]
+    fn test_nested_repeat_loads() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Polling"
+settings: {}
+steps:
+  - type: repeat
+    count: 3
+    delay: "500ms"
+    steps:
+      - type: command
+        text: "curl -s localhost:8080/health"
+      - type: repeat
+        count: 2
+        steps:
+          - type: screenshot
+            name: "health"
+"#).unwrap();
+        
+        let StepType::Repeat { count, steps, delay } = &script.steps[0].step_type else {
+            panic!("expected a repeat, got {:?}", script.steps[0].step_type);
+        };
+        assert_eq!(*count, 3);
+        assert_eq!(*delay, Some(Duration::from_millis(500)));
+        assert!(matches!(&steps[0].step_type, StepType::Command { text, .. } if text == "curl -s localhost:8080/health"));
+        assert!(matches!(&steps[1].step_type, StepType::Repeat { count: 2, steps, .. } if steps.len() == 1));
+        assert!(script.validate().is_empty());
+    }
+    
+    #[test]
+    fn test_record_gif_duration_checked_on_load() {
+        let record = |duration: &str, fps: u16| format!(r#"
+name: "Recording"
+settings: {{}}
+steps:
+  - type: record_gif
+    duration: "{}"
+    name: "session"
+    fps: {}
+"#, duration, fps);
+        
+        let script = ScriptLoader::load_from_string(&record("1500ms", 30)).unwrap();
+        assert!(matches!(
+            &script.steps[0].step_type,
+            StepType::RecordGif { duration, fps: 30, .. } if *duration == Duration::from_millis(1500)
+        ));
+        // Sub-second durations survive a save
+        let yaml = serde_yaml::to_string(&script).unwrap();
+        ScriptLoader::load_from_string(&yaml).unwrap();
+        
+        let error = ScriptLoader::load_from_string(&record("0s", 10)).unwrap_err();
+        assert!(error.to_string().contains("record_gif 'session' has a zero duration"), "{}", error);
+        for fps in [0, 61] {
+            let error = ScriptLoader::load_from_string(&record("2s", fps)).unwrap_err();
+            assert!(error.to_string().contains("from 1 to 60"), "{}", error);
+        }
+    }
+    
     #[test]
     fn test_roundtrip_serialization() {
         let script = Script {
             name: "Roundtrip Test".to_string(),
+            description: None,
             settings: TerminalSettings {
                 width: 120,
                 height: 30,
                 shell: "zsh".to_string(),
                 theme: "default".to_string(),
                 working_dir: None,
+                create_working_dir: true,
+                step_timeout: Some(Duration::from_secs(5)),
+                shutdown_grace: Duration::from_secs(2),
+                startup_commands: vec!["PS1='$ '".to_string()],
+                fake_prompt: Some("› ".to_string()),
+                prompt_pattern: Some("\\$ $".to_string()),
+                separate_stderr: true,
+                prompt_replace: Some(("/Users/[^/]+".to_string(), "~".to_string())),
+                run_program: Some(vec!["vim".to_string(), "notes.txt".to_string()]),
+                read_buffer_size: 4096,
+                raw_capture: true,
             },
             steps: vec![
                 ScriptStep {
                     step_type: StepType::Command {
                         text: "pwd".to_string(),
+                        stdin: None,
+                        screenshot: None,
                         wait: Some(Duration::from_millis(500)),
+                        retries: 2,
+                        retry_on: Some("timed out".to_string()),
                     },
+                    pane: None,
+                    note: None,
+                    when: None,
                 },
                 ScriptStep {
                     step_type: StepType::Screenshot {
                         name: "current-dir".to_string(),
+                        theme: Some("nord".to_string()),
                     },
+                    pane: None,
+                    note: None,
+                    when: None,
                 },
             ],
+            layout: None,
         };
         
         let yaml = serde_yaml::to_string(&script).unwrap();
@@ -94,5 +403,10 @@ steps:
         
         assert_eq!(script.name, loaded.name);
         assert_eq!(script.steps.len(), loaded.steps.len());
+        assert_eq!(loaded.settings.step_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(loaded.settings.shutdown_grace, Duration::from_secs(2));
+        assert_eq!(loaded.settings.startup_commands, script.settings.startup_commands);
+        assert_eq!(loaded.settings.fake_prompt, script.settings.fake_prompt);
+        assert!(loaded.settings.raw_capture);
     }
 }
\ No newline at end of file
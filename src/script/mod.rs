@@ -1,22 +1,32 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::time::Duration;
 use anyhow::{Context, Result};
 
+pub mod flowchart;
 pub mod loader;
 pub mod types;
+pub mod validate;
 
 pub use loader::ScriptLoader;
+pub use validate::{Severity, ValidationIssue};
 // pub use types::*; // Not needed since types just re-exports from this module
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Script {
     pub name: String,
+    /// What the script demonstrates; shown when it runs, never executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     pub settings: TerminalSettings,
     pub steps: Vec<ScriptStep>,
+    /// Split the terminal into named panes that steps pick with `pane`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TerminalSettings {
     #[serde(default = "default_width")]
     pub width: u16,
@@ -32,50 +42,335 @@ pub struct TerminalSettings {
     
     #[serde(default)]
     pub working_dir: Option<PathBuf>,
+    
+    /// Create `working_dir` if it doesn't exist, instead of failing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub create_working_dir: bool,
+    
+    /// Longest any single step may take before the run is aborted
+    #[serde(default, with = "duration_option", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub step_timeout: Option<Duration>,
+    
+    /// How long the shell gets to exit on its own before it is killed
+    #[serde(default = "default_shutdown_grace", with = "duration_ms")]
+    #[schemars(with = "String")]
+    pub shutdown_grace: Duration,
+    
+    /// Run when the shell starts, before any step; their output is discarded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub startup_commands: Vec<String>,
+    
+    /// Prompt the shell shows instead of its own, e.g. `"$ "`; set before
+    /// `startup_commands` run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fake_prompt: Option<String>,
+    
+    /// Regex matching the end of the shell prompt; commands wait for it to reappear
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_pattern: Option<String>,
+    
+    /// Send each command's stderr to a separate buffer instead of the screen
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub separate_stderr: bool,
+    
+    /// Regex and replacement applied to each rendered line, e.g. to hide
+    /// usernames in prompts; the shell itself is unaffected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_replace: Option<(String, String)>,
+    
+    /// Program and arguments spawned in the PTY instead of `shell`, e.g.
+    /// `["htop"]`; command steps then send their text to it as input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_program: Option<Vec<String>>,
+    
+    /// Bytes read from the PTY at a time; larger reads keep up with chatty programs
+    #[serde(default = "default_read_buffer_size")]
+    pub read_buffer_size: usize,
+    
+    /// Also keep every byte the PTY sends, unparsed and undecoded, for
+    /// `TerminalController::get_raw_bytes`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_capture: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ScriptStep {
     #[serde(flatten)]
     pub step_type: StepType,
+    /// Id of the layout pane the step runs in; the first pane if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pane: Option<String>,
+    /// The author's remark on the step; shown in progress output, never executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Regex tested against the text on the step's pane's screen; the step
+    /// is skipped unless it matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+}
+
+/// Terminal panes shown together in every screenshot and frame, each running its own shell
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Layout {
+    /// Pane ids, in the order they are drawn; at least one
+    pub panes: Vec<String>,
+    /// `horizontal` (side by side, the default) or `vertical` (stacked)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+    /// Pixels between neighbouring panes
+    #[serde(default)]
+    pub gap: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StepType {
     Command {
         text: String,
+        /// File whose contents are sent to the command's stdin, followed by EOF
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stdin: Option<PathBuf>,
+        /// Run to completion and save a screenshot of just this command and its output
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        screenshot: Option<String>,
         #[serde(default, with = "duration_option")]
+        #[schemars(with = "Option<String>")]
         wait: Option<Duration>,
+        /// Run again up to this many times while the command fails
+        #[serde(default, skip_serializing_if = "is_zero")]
+        retries: u32,
+        /// Regex that marks the output as failed even when the command exits 0
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_on: Option<String>,
     },
     Type {
         text: String,
         #[serde(default = "default_typing_speed", with = "duration_ms")]
+        #[schemars(with = "String")]
         speed: Duration,
+        /// Turn `\t`, `\n`, `\x1b` and the like in `text` into the characters
+        /// they name before typing, instead of typing the backslashes
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        interpret_escapes: bool,
+    },
+    /// Send text to the terminal verbatim, without a trailing newline
+    Input {
+        text: String,
     },
     Screenshot {
         name: String,
+        /// Theme for this screenshot only, by name or path to a theme file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        theme: Option<String>,
+    },
+    /// Compare the screen's text with the golden file `<name>.txt`, writing it if missing
+    Snapshot {
+        name: String,
     },
     RecordGif {
         #[serde(with = "duration_secs")]
+        #[schemars(with = "String")]
         duration: Duration,
         name: String,
+        /// Frames sampled per second while recording
+        #[serde(default = "default_fps")]
+        fps: u16,
+        /// Theme for this recording only, by name or path to a theme file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        theme: Option<String>,
+    },
+    /// Overlay a caption on captured frames without sending anything to the shell
+    Annotate {
+        text: String,
+        /// How long the caption stays up; the step itself does not wait
+        #[serde(with = "duration_ms")]
+        #[schemars(with = "String")]
+        duration: Duration,
+        /// `top`, `bottom` (default) or `center`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        position: Option<String>,
+    },
+    /// Send a mouse event at a 1-based cell, for programs with mouse reporting on
+    Mouse {
+        /// `press`, `release`, `click`, `scroll_up` or `scroll_down`
+        event: String,
+        x: u16,
+        y: u16,
+    },
+    /// Run `steps` `count` times in this step's pane
+    ///
+    /// Names of screenshots and recordings taken inside get `-<iteration>`
+    /// appended, counting from 1, so each iteration writes its own files.
+    Repeat {
+        count: u32,
+        steps: Vec<ScriptStep>,
+        /// Pause between iterations
+        #[serde(default, with = "duration_option")]
+        #[schemars(with = "Option<String>")]
+        delay: Option<Duration>,
     },
 }
 
+impl StepType {
+    /// The step's `type` tag as written in scripts
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StepType::Command { .. } => "command",
+            StepType::Type { .. } => "type",
+            StepType::Input { .. } => "input",
+            StepType::Screenshot { .. } => "screenshot",
+            StepType::Snapshot { .. } => "snapshot",
+            StepType::RecordGif { .. } => "record_gif",
+            StepType::Annotate { .. } => "annotate",
+            StepType::Mouse { .. } => "mouse",
+            StepType::Repeat { .. } => "repeat",
+        }
+    }
+    
+    /// Short one-line description, e.g. `command "ls -la"`
+    pub fn summary(&self) -> String {
+        match self {
+            StepType::Command { text, .. }
+            | StepType::Type { text, .. }
+            | StepType::Input { text }
+            | StepType::Annotate { text, .. } => {
+                format!("{} {:?}", self.kind(), text)
+            }
+            StepType::Screenshot { name, .. } | StepType::Snapshot { name } | StepType::RecordGif { name, .. } => {
+                format!("{} '{}'", self.kind(), name)
+            }
+            StepType::Mouse { event, x, y } => format!("{} {} at ({}, {})", self.kind(), event, x, y),
+            StepType::Repeat { count, steps, .. } => {
+                format!("{} {} step(s) {} times", self.kind(), steps.len(), count)
+            }
+        }
+    }
+    
+    /// A copy of the step for iteration `iteration` of a `repeat`, with the
+    /// names of the files it writes suffixed by the iteration
+    pub fn for_iteration(&self, iteration: u32) -> StepType {
+        let suffixed = |name: &String| format!("{}-{}", name, iteration);
+        let mut step_type = self.clone();
+        match &mut step_type {
+            StepType::Command { screenshot: Some(name), .. }
+            | StepType::Screenshot { name, .. }
+            | StepType::Snapshot { name }
+            | StepType::RecordGif { name, .. } => *name = suffixed(name),
+            StepType::Repeat { steps, .. } => {
+                for step in steps {
+                    step.step_type = step.step_type.for_iteration(iteration);
+                }
+            }
+            _ => {}
+        }
+        step_type
+    }
+}
+
+/// Replace backslash escapes in typed text with the characters they stand for
+///
+/// Understands `\n`, `\r`, `\t`, `\b`, `\e` (escape), `\\`, `\0` and `\xHH`
+/// for any byte up to `\x7f`.
+pub fn interpret_escapes(text: &str) -> crate::error::Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\x08',
+            Some('e') => '\x1b',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => byte as char,
+                    _ => {
+                        return Err(crate::error::KlaError::parse(format!(
+                            "invalid escape '\\x{}' in {:?}; expected two hex digits from 00 to 7f",
+                            digits, text
+                        )))
+                    }
+                }
+            }
+            Some(other) => {
+                return Err(crate::error::KlaError::parse(format!(
+                    "unknown escape '\\{}' in {:?}; expected \\n, \\r, \\t, \\b, \\e, \\0, \\\\ or \\xHH",
+                    other, text
+                )))
+            }
+            None => return Err(crate::error::KlaError::parse(format!("{:?} ends in a lone backslash", text))),
+        };
+        result.push(escaped);
+    }
+    Ok(result)
+}
+
 impl Script {
-    pub fn single_command(command: &str) -> Result<Self> {
+    pub fn single_command(command: &str) -> crate::error::Result<Self> {
         Ok(Script {
             name: format!("Single command: {}", command),
+            description: None,
             settings: TerminalSettings::default(),
             steps: vec![ScriptStep {
                 step_type: StepType::Command {
                     text: command.to_string(),
+                    stdin: None,
+                    screenshot: None,
                     wait: Some(Duration::from_millis(500)),
+                    retries: 0,
+                    retry_on: None,
                 },
+                pane: None,
+                note: None,
+                when: None,
             }],
+            layout: None,
         })
     }
+    
+    /// Ids of the panes the script runs in; one unnamed pane without a layout
+    pub fn pane_ids(&self) -> Vec<Option<&str>> {
+        match &self.layout {
+            Some(layout) => layout.panes.iter().map(|id| Some(id.as_str())).collect(),
+            None => vec![None],
+        }
+    }
+    
+    /// Position of the pane `step` runs in, or `None` if it names a pane the layout lacks
+    pub fn pane_index(&self, step: &ScriptStep) -> Option<usize> {
+        match &step.pane {
+            Some(id) => self.pane_ids().iter().position(|pane| *pane == Some(id.as_str())),
+            None => Some(0),
+        }
+    }
+    
+    /// File-system friendly version of the script name, used for whole-session artifacts
+    pub fn file_name(&self) -> String {
+        let slug = self.name
+            .to_lowercase()
+            .chars()
+            .map(|ch| if ch.is_alphanumeric() { ch } else { '-' })
+            .collect::<String>();
+        let slug = slug
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        
+        if slug.is_empty() {
+            "recording".to_string()
+        } else {
+            slug
+        }
+    }
 }
 
 impl Default for TerminalSettings {
@@ -86,6 +381,17 @@ impl Default for TerminalSettings {
             shell: default_shell(),
             theme: default_theme(),
             working_dir: None,
+            create_working_dir: false,
+            step_timeout: None,
+            shutdown_grace: default_shutdown_grace(),
+            startup_commands: Vec::new(),
+            fake_prompt: None,
+            prompt_pattern: None,
+            separate_stderr: false,
+            prompt_replace: None,
+            run_program: None,
+            read_buffer_size: default_read_buffer_size(),
+            raw_capture: false,
         }
     }
 }
@@ -97,7 +403,12 @@ fn default_shell() -> String {
     std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string())
 }
 fn default_theme() -> String { "default".to_string() }
+fn default_read_buffer_size() -> usize { 64 * 1024 }
 fn default_typing_speed() -> Duration { Duration::from_millis(50) }
+fn default_fps() -> u16 { 10 }
+fn default_shutdown_grace() -> Duration { Duration::from_millis(500) }
+
+fn is_zero(value: &u32) -> bool { *value == 0 }
 
 // Serde duration helpers
 mod duration_option {
@@ -154,7 +465,12 @@ mod duration_secs {
     where
         S: Serializer,
     {
-        format!("{}s", duration.as_secs()).serialize(serializer)
+        // Whole seconds read best, but a fraction must not round away
+        match duration.subsec_nanos() {
+            0 => format!("{}s", duration.as_secs()),
+            _ => format!("{}ms", duration.as_millis()),
+        }
+        .serialize(serializer)
     }
     
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
@@ -166,16 +482,74 @@ mod duration_secs {
     }
 }
 
+/// Parse a duration such as `500ms`, `2s` or `1m30s`
+///
+/// Units are `us`, `ms`, `s`, `m` and `h`, and compound forms add up their
+/// parts, so `1h5m` is 65 minutes.
 fn parse_duration(s: &str) -> Result<Duration> {
-    if s.ends_with("ms") {
-        let ms: u64 = s.trim_end_matches("ms").parse()
-            .context("Invalid milliseconds value")?;
-        Ok(Duration::from_millis(ms))
-    } else if s.ends_with('s') {
-        let secs: u64 = s.trim_end_matches('s').parse()
-            .context("Invalid seconds value")?;
-        Ok(Duration::from_secs(secs))
-    } else {
-        Err(anyhow::anyhow!("Duration must end with 'ms' or 's'"))
-    }
-}
\ No newline at end of file
+    let input = s.trim();
+    if input.is_empty() {
+        anyhow::bail!("Duration is empty; expected e.g. '500ms', '2s' or '1m30s'");
+    }
+    
+    let mut total = Duration::ZERO;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let digits = rest.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            anyhow::bail!("Invalid duration '{}': expected a number at '{}'", input, rest);
+        }
+        let value: u64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("Invalid duration '{}': {} is too large", input, &rest[..digits]))?;
+        rest = &rest[digits..];
+        
+        let unit_len = rest.find(|ch: char| ch.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        rest = &rest[unit_len..];
+        let part = match unit {
+            "us" => Some(Duration::from_micros(value)),
+            "ms" => Some(Duration::from_millis(value)),
+            "s" => Some(Duration::from_secs(value)),
+            "m" => value.checked_mul(60).map(Duration::from_secs),
+            "h" => value.checked_mul(3600).map(Duration::from_secs),
+            "" => anyhow::bail!("Invalid duration '{}': {} needs a unit (us, ms, s, m or h)", input, value),
+            other => anyhow::bail!("Invalid duration '{}': unknown unit '{}'; expected us, ms, s, m or h", input, other),
+        };
+        total = part
+            .and_then(|part| total.checked_add(part))
+            .with_context(|| format!("Invalid duration '{}': too long", input))?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_interpret_escapes() {
+        assert_eq!(interpret_escapes("a\\tb").unwrap(), "a\tb");
+        assert_eq!(interpret_escapes("\\x1b[1m\\\\n\\n").unwrap(), "\x1b[1m\\n\n");
+        assert!(interpret_escapes("\\q").is_err());
+        assert!(interpret_escapes("\\x9").is_err());
+        assert!(interpret_escapes("trailing\\").is_err());
+    }
+    
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("1m").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("1m30s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("500us").unwrap(), Duration::from_micros(500));
+        assert_eq!(parse_duration("1h5m250ms").unwrap(), Duration::from_millis(3_900_250));
+        
+        let error = parse_duration("abc").unwrap_err().to_string();
+        assert!(error.contains("expected a number"), "{}", error);
+        let error = parse_duration("5").unwrap_err().to_string();
+        assert!(error.contains("needs a unit"), "{}", error);
+        let error = parse_duration("3d").unwrap_err().to_string();
+        assert!(error.contains("unknown unit 'd'"), "{}", error);
+    }
+}
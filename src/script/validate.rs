@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::media::{CaptionPosition, PaneDirection, ThemeConfig};
+use crate::pty::MouseEvent;
+use super::{Script, ScriptStep, StepType};
+
+/// How serious a validation finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A single problem found while validating a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Index of the offending step, if the issue is tied to one
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.step {
+            Some(index) => write!(f, "{} (step {}): {}", label, index + 1, self.message),
+            None => write!(f, "{}: {}", label, self.message),
+        }
+    }
+}
+
+/// Largest terminal dimension accepted without a warning
+const MAX_REASONABLE_SIZE: u16 = 1000;
+
+/// Highest frame rate a `record_gif` step may sample at
+pub const MAX_FPS: u16 = 60;
+
+impl Script {
+    /// Check the script for mistakes that parse fine but would break a recording
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut error = |step: Option<usize>, message: String| {
+            issues.push(ValidationIssue { severity: Severity::Error, step, message });
+        };
+
+        let settings = &self.settings;
+        if settings.width == 0 || settings.height == 0 {
+            error(None, format!("terminal size {}x{} must be nonzero", settings.width, settings.height));
+        }
+        if settings.read_buffer_size == 0 {
+            error(None, "read_buffer_size must be nonzero".to_string());
+        }
+        if let Err(err) = ThemeConfig::resolve(&settings.theme) {
+            error(None, format!("{:#}", err));
+        }
+        if let Some(pattern) = &settings.prompt_pattern {
+            if let Err(err) = regex::Regex::new(pattern) {
+                error(None, format!("invalid prompt_pattern: {}", err));
+            }
+        }
+        if let Some((pattern, _)) = &settings.prompt_replace {
+            if let Err(err) = regex::Regex::new(pattern) {
+                error(None, format!("invalid prompt_replace: {}", err));
+            }
+        }
+
+        let direct = settings.run_program.is_some();
+        if let Some(program) = &settings.run_program {
+            if program.is_empty() {
+                error(None, "run_program needs at least a program name".to_string());
+            }
+            if !settings.startup_commands.is_empty() {
+                error(None, "startup_commands need a shell and cannot be combined with run_program".to_string());
+            }
+            if settings.fake_prompt.is_some() {
+                error(None, "fake_prompt needs a shell and cannot be combined with run_program".to_string());
+            }
+            if settings.separate_stderr {
+                error(None, "separate_stderr needs a shell and cannot be combined with run_program".to_string());
+            }
+        }
+
+        if let Some(layout) = &self.layout {
+            if layout.panes.is_empty() {
+                error(None, "layout needs at least one pane".to_string());
+            }
+            for (position, id) in layout.panes.iter().enumerate() {
+                if layout.panes[..position].contains(id) {
+                    error(None, format!("layout pane '{}' is declared twice", id));
+                }
+            }
+            if let Err(err) = PaneDirection::from_name(layout.direction.as_deref()) {
+                error(None, err.to_string());
+            }
+        }
+
+        let steps = flatten(&self.steps);
+        let mut artifact_names: HashMap<String, usize> = HashMap::new();
+        for &(index, step, nested) in &steps {
+            if let (true, Some(_)) = (nested, &step.pane) {
+                error(Some(index), "steps inside a repeat run in its pane; set pane on the repeat instead".to_string());
+            } else if let (Some(pane), None) = (&step.pane, self.pane_index(step)) {
+                match &self.layout {
+                    Some(layout) => error(
+                        Some(index),
+                        format!("unknown pane '{}'; the layout declares: {}", pane, layout.panes.join(", ")),
+                    ),
+                    None => error(Some(index), format!("pane '{}' needs a layout declaring it", pane)),
+                }
+            }
+            if let Some(Err(err)) = step.when.as_deref().map(regex::Regex::new) {
+                error(Some(index), format!("invalid when: {}", err));
+            }
+            let artifact = match &step.step_type {
+                StepType::Screenshot { name, theme } => {
+                    if let Some(Err(err)) = theme.as_deref().map(ThemeConfig::resolve) {
+                        error(Some(index), format!("{:#}", err));
+                    }
+                    Some(("screenshot", name))
+                }
+                StepType::Snapshot { name } => Some(("snapshot", name)),
+                StepType::Command { stdin, screenshot, retries, retry_on, .. } => {
+                    if direct && (*retries > 0 || screenshot.is_some()) {
+                        error(Some(index), "with run_program, a command cannot wait for its exit for retries or a screenshot".to_string());
+                    }
+                    if *retries > 0 && stdin.is_some() {
+                        error(Some(index), "a command with retries cannot take stdin".to_string());
+                    }
+                    if let Some(pattern) = retry_on {
+                        if let Err(err) = regex::Regex::new(pattern) {
+                            error(Some(index), format!("invalid retry_on: {}", err));
+                        }
+                    }
+                    if let (Some(name), Some(_)) = (screenshot, stdin) {
+                        error(Some(index), format!("command screenshot '{}' cannot be combined with stdin", name));
+                    }
+                    screenshot.as_ref().map(|name| ("screenshot", name))
+                }
+                StepType::RecordGif { duration, name, fps, theme } => {
+                    if let Some(Err(err)) = theme.as_deref().map(ThemeConfig::resolve) {
+                        error(Some(index), format!("{:#}", err));
+                    }
+                    if let Some(problem) = record_gif_problem(name, *duration, *fps) {
+                        error(Some(index), problem);
+                    }
+                    Some(("record_gif", name))
+                }
+                StepType::Annotate { position, .. } => {
+                    if let Err(err) = CaptionPosition::from_name(position.as_deref()) {
+                        error(Some(index), err.to_string());
+                    }
+                    None
+                }
+                StepType::Repeat { count, steps, .. } => {
+                    if *count == 0 {
+                        error(Some(index), "repeat has a zero count".to_string());
+                    }
+                    if steps.is_empty() {
+                        error(Some(index), "repeat has no steps".to_string());
+                    }
+                    None
+                }
+                StepType::Type { text, interpret_escapes: true, .. } => {
+                    if let Err(err) = super::interpret_escapes(text) {
+                        error(Some(index), err.to_string());
+                    }
+                    None
+                }
+                StepType::Mouse { event, x, y } => {
+                    if let Err(err) = MouseEvent::from_name(event) {
+                        error(Some(index), err.to_string());
+                    }
+                    if *x == 0 || *y == 0 || *x > settings.width || *y > settings.height {
+                        error(
+                            Some(index),
+                            format!("mouse position ({}, {}) is outside the {}x{} terminal; coordinates start at 1", x, y, settings.width, settings.height),
+                        );
+                    }
+                    None
+                }
+                _ => None,
+            };
+
+            // Iterations suffix the names inside a repeat, so they never collide
+            if let (false, Some((kind, name))) = (nested, artifact) {
+                let key = format!("{}:{}", kind, name);
+                if let Some(first) = artifact_names.insert(key, index) {
+                    error(
+                        Some(index),
+                        format!("duplicate {} name '{}' (first used in step {})", kind, name, first + 1),
+                    );
+                }
+            }
+        }
+
+        if settings.width > MAX_REASONABLE_SIZE || settings.height > MAX_REASONABLE_SIZE {
+            issues.push(ValidationIssue {
+                severity: Severity::Warning,
+                step: None,
+                message: format!("terminal size {}x{} is unusually large", settings.width, settings.height),
+            });
+        }
+        if self.steps.is_empty() {
+            issues.push(ValidationIssue {
+                severity: Severity::Warning,
+                step: None,
+                message: "script has no steps".to_string(),
+            });
+        }
+
+        // KLA performs no template substitution, so placeholders reach the shell verbatim
+        for &(index, step, _) in &steps {
+            let text = match &step.step_type {
+                StepType::Command { text, .. } | StepType::Type { text, .. } | StepType::Input { text } => text,
+                _ => continue,
+            };
+            if let Some(start) = text.find("{{") {
+                if let Some(len) = text[start..].find("}}") {
+                    issues.push(ValidationIssue {
+                        severity: Severity::Warning,
+                        step: Some(index),
+                        message: format!("unresolved variable '{}'", &text[start..start + len + 2]),
+                    });
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+/// What is wrong with a `record_gif` step's duration or frame rate, if anything
+///
+/// Checked when a script loads as well, since such a step can only produce a
+/// broken animation.
+pub fn record_gif_problem(name: &str, duration: std::time::Duration, fps: u16) -> Option<String> {
+    if duration.is_zero() {
+        return Some(format!("record_gif '{}' has a zero duration", name));
+    }
+    if !(1..=MAX_FPS).contains(&fps) {
+        return Some(format!("record_gif '{}' has fps {}; it must be from 1 to {}", name, fps, MAX_FPS));
+    }
+    None
+}
+
+/// Every step with the index of the top-level step it belongs to, and
+/// whether it sits inside a repeat
+pub(crate) fn flatten(steps: &[ScriptStep]) -> Vec<(usize, &ScriptStep, bool)> {
+    fn visit<'a>(steps: &'a [ScriptStep], parent: Option<usize>, out: &mut Vec<(usize, &'a ScriptStep, bool)>) {
+        for (index, step) in steps.iter().enumerate() {
+            let index = parent.unwrap_or(index);
+            out.push((index, step, parent.is_some()));
+            if let StepType::Repeat { steps, .. } = &step.step_type {
+                visit(steps, Some(index), out);
+            }
+        }
+    }
+    let mut out = Vec::new();
+    visit(steps, None, &mut out);
+    out
+}
+
+/// Whether any issue in the list is an error
+pub fn has_errors(issues: &[ValidationIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::ScriptLoader;
+
+    #[test]
+    fn test_clean_script_passes() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Clean"
+settings:
+  theme: "dracula"
+steps:
+  - type: command
+    text: "echo hi"
+  - type: screenshot
+    name: "first"
+  - type: record_gif
+    duration: "2s"
+    name: "first"
+"#).unwrap();
+
+        let issues = script.validate();
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_duplicate_screenshot_name_fails() {
+        let script = ScriptLoader::load_from_string(r#"
+name: "Duplicate"
+settings: {}
+steps:
+  - type: screenshot
+    name: "shot"
+  - type: command
+    text: "echo {{greeting}}"
+  - type: screenshot
+    name: "shot"
+"#).unwrap();
+
+        let issues = script.validate();
+        assert!(has_errors(&issues));
+
+        let messages: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+        assert!(messages.contains(
+            &"error (step 3): duplicate screenshot name 'shot' (first used in step 1)".to_string()
+        ));
+        assert!(messages.contains(&"warning (step 2): unresolved variable '{{greeting}}'".to_string()));
+    }
+}
@@ -1,5 +1,6 @@
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use unicode_width::UnicodeWidthChar;
 
 /// Terminal dimensions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -21,7 +22,7 @@ impl Default for TerminalSize {
 }
 
 /// Terminal character attributes
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CharAttributes {
     pub fg_color: Option<u8>,
     pub bg_color: Option<u8>,
@@ -31,36 +32,33 @@ pub struct CharAttributes {
     pub reverse: bool,
 }
 
-impl Default for CharAttributes {
-    fn default() -> Self {
-        Self {
-            fg_color: None,
-            bg_color: None,
-            bold: false,
-            italic: false,
-            underline: false,
-            reverse: false,
-        }
-    }
-}
-
 /// Terminal character with attributes
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TerminalChar {
     pub ch: char,
     pub attrs: CharAttributes,
+    /// Second cell of a wide character; holds no text of its own
+    #[serde(default)]
+    pub continuation: bool,
 }
 
 impl TerminalChar {
     pub fn new(ch: char) -> Self {
-        Self {
-            ch,
-            attrs: CharAttributes::default(),
-        }
+        Self::with_attrs(ch, CharAttributes::default())
     }
 
     pub fn with_attrs(ch: char, attrs: CharAttributes) -> Self {
-        Self { ch, attrs }
+        Self { ch, attrs, continuation: false }
+    }
+
+    /// The trailing cell reserved by a wide character
+    pub fn continuation(attrs: CharAttributes) -> Self {
+        Self { ch: ' ', attrs, continuation: true }
+    }
+
+    /// Number of columns this character occupies: 2 for wide characters, else 1
+    pub fn width(&self) -> u16 {
+        if self.ch.width() == Some(2) { 2 } else { 1 }
     }
 }
 
@@ -93,6 +91,28 @@ impl Default for CursorPosition {
     }
 }
 
+/// Cells of one row that an OSC 8 hyperlink points somewhere
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hyperlink {
+    pub row: u16,
+    /// First linked column
+    pub start: u16,
+    /// Column just past the link
+    pub end: u16,
+    pub uri: String,
+}
+
+/// Which blanks at the end of a line `TerminalState::get_text_with` drops
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineTrim {
+    /// Every trailing whitespace character, whatever it looks like
+    #[default]
+    Whitespace,
+    /// Only trailing blanks drawn on the default background; colored spaces,
+    /// such as the end of a status bar, are kept
+    DefaultBackground,
+}
+
 /// Complete terminal state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerminalState {
@@ -101,6 +121,9 @@ pub struct TerminalState {
     pub buffer: Vec<Vec<TerminalChar>>,
     pub title: String,
     pub cursor_visible: bool,
+    /// Hyperlinked spans of the screen
+    #[serde(default)]
+    pub hyperlinks: Vec<Hyperlink>,
 }
 
 impl TerminalState {
@@ -116,6 +139,7 @@ impl TerminalState {
             buffer,
             title: String::new(),
             cursor_visible: true,
+            hyperlinks: Vec::new(),
         }
     }
 
@@ -130,46 +154,97 @@ impl TerminalState {
         }
     }
 
+    /// The hyperlink covering the cell at `(x, y)`, if any
+    pub fn hyperlink_at(&self, x: u16, y: u16) -> Option<&Hyperlink> {
+        self.hyperlinks
+            .iter()
+            .find(|link| link.row == y && (link.start..link.end).contains(&x))
+    }
+
     /// Set character at position
+    ///
+    /// Wide characters also claim the next cell as a continuation. A wide
+    /// character that would not fit in the last column is replaced by a space,
+    /// and overwriting half of an existing wide character blanks the other half.
     pub fn set_char(&mut self, x: u16, y: u16, ch: TerminalChar) {
-        if x < self.size.width && y < self.size.height {
-            if let Some(row) = self.buffer.get_mut(y as usize) {
-                if let Some(cell) = row.get_mut(x as usize) {
-                    *cell = ch;
-                }
+        if x >= self.size.width || y >= self.size.height {
+            return;
+        }
+        let Some(row) = self.buffer.get_mut(y as usize) else {
+            return;
+        };
+        let x = x as usize;
+
+        let ch = if ch.width() == 2 && x + 1 >= row.len() {
+            TerminalChar::with_attrs(' ', ch.attrs)
+        } else {
+            ch
+        };
+        let width = ch.width() as usize;
+
+        // Don't leave orphaned halves of a wide character being overwritten
+        if row[x].continuation && x > 0 {
+            row[x - 1] = TerminalChar::default();
+        }
+        if let Some(next) = row.get_mut(x + width) {
+            if next.continuation {
+                *next = TerminalChar::default();
             }
         }
+
+        if width == 2 {
+            row[x + 1] = TerminalChar::continuation(ch.attrs.clone());
+        }
+        row[x] = ch;
+    }
+
+    /// Write a character at the cursor and advance it by the character's width
+    ///
+    /// Wraps to the start of the next line when the character does not fit.
+    /// Zero-width and control characters are ignored.
+    pub fn put_char(&mut self, ch: char) {
+        if ch.width().unwrap_or(0) == 0 {
+            return;
+        }
+        let cell = TerminalChar::new(ch);
+        let width = cell.width();
+
+        if self.cursor.x + width > self.size.width && self.cursor.y + 1 < self.size.height {
+            self.cursor = CursorPosition::new(0, self.cursor.y + 1);
+        }
+        self.set_char(self.cursor.x, self.cursor.y, cell);
+        self.cursor.x = (self.cursor.x + width).min(self.size.width);
+    }
+
+    /// Write each character of `text` at the cursor
+    pub fn put_str(&mut self, text: &str) {
+        for ch in text.chars() {
+            self.put_char(ch);
+        }
     }
 
     /// Get text content as string
     pub fn get_text(&self) -> String {
+        self.get_text_with(LineTrim::Whitespace)
+    }
+
+    /// Get text content, trimming each line's end as `trim` says
+    pub fn get_text_with(&self, trim: LineTrim) -> String {
         self.buffer
             .iter()
-            .map(|row| {
-                row.iter()
-                    .map(|cell| cell.ch)
-                    .collect::<String>()
-                    .trim_end()
-                    .to_string()
-            })
+            .map(|row| row_text(row, trim))
             .collect::<Vec<_>>()
             .join("\n")
     }
 
     /// Get text content of a specific line
     pub fn get_line_text(&self, y: u16) -> Option<String> {
-        if y < self.size.height {
-            Some(
-                self.buffer[y as usize]
-                    .iter()
-                    .map(|cell| cell.ch)
-                    .collect::<String>()
-                    .trim_end()
-                    .to_string()
-            )
-        } else {
-            None
-        }
+        self.get_line_text_with(y, LineTrim::Whitespace)
+    }
+
+    /// Get text content of a specific line, trimming its end as `trim` says
+    pub fn get_line_text_with(&self, y: u16, trim: LineTrim) -> Option<String> {
+        self.buffer.get(y as usize).map(|row| row_text(row, trim))
     }
 
     /// Check if text exists in terminal
@@ -178,22 +253,134 @@ impl TerminalState {
     }
 
     /// Find text position in terminal
+    ///
+    /// Searches the same text as `get_text`, so a match may span lines. The
+    /// returned position is the cell holding the first matched character.
     pub fn find_text(&self, text: &str) -> Option<CursorPosition> {
-        let content = self.get_text();
-        if let Some(pos) = content.find(text) {
-            // Convert byte position to line/column
-            let lines: Vec<&str> = content.lines().collect();
-            let mut char_count = 0;
+        // Build the searchable text alongside the cell each of its chars came from
+        let mut content = String::new();
+        let mut cells = Vec::new();
+        for (y, row) in self.buffer.iter().enumerate() {
+            let y = y as u16;
+            let mut line: Vec<(char, u16)> = row
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| !cell.continuation)
+                .map(|(x, cell)| (cell.ch, x as u16))
+                .collect();
+            while line.last().is_some_and(|(ch, _)| ch.is_whitespace()) {
+                line.pop();
+            }
+            
+            for (ch, x) in &line {
+                content.push(*ch);
+                cells.push(CursorPosition::new(*x, y));
+            }
+            // The newline sits just past the end of the line
+            let line_end = line.last().map_or(0, |(_, x)| x + 1);
+            content.push('\n');
+            cells.push(CursorPosition::new(line_end, y));
+        }
+        content.pop();
+        
+        let byte_offset = content.find(text)?;
+        let char_index = content[..byte_offset].chars().count();
+        cells.get(char_index).copied()
+    }
+
+    /// Replace matches of `pattern` within each line, as `Regex::replace_all` would
+    ///
+    /// Replacement text takes the attributes of the first matched cell, and
+    /// the rest of the line shifts to make room, along with the cursor if it
+    /// sits after a match. Trailing blanks are not matched, so `\$ $` finds a
+    /// prompt at the end of a line.
+    pub fn replace_text(&mut self, pattern: &Regex, replacement: &str) {
+        let width = self.size.width as usize;
+        for (y, row) in self.buffer.iter_mut().enumerate() {
+            // Byte offset of each character in the line's text, and its cell
+            let mut text = String::new();
+            let mut offsets = Vec::new();
+            for (x, cell) in row.iter().enumerate().filter(|(_, cell)| !cell.continuation) {
+                offsets.push((text.len(), x));
+                text.push(cell.ch);
+            }
+            let text = text.trim_end();
+            let cell_at = |byte: usize| {
+                offsets.iter().find(|(offset, _)| *offset >= byte).map_or(row.len(), |(_, x)| *x)
+            };
+            
+            let mut rewritten = Vec::with_capacity(row.len());
+            let mut copied = 0;
+            let mut shift = 0isize;
+            for captures in pattern.captures_iter(text) {
+                let matched = captures.get(0).expect("group 0 always matches");
+                if matched.is_empty() {
+                    continue;
+                }
+                let (start, end) = (cell_at(matched.start()), cell_at(matched.end()));
+                let attrs = row[start].attrs.clone();
+                rewritten.extend_from_slice(&row[copied..start]);
+                
+                let mut expanded = String::new();
+                captures.expand(replacement, &mut expanded);
+                let before = rewritten.len();
+                for ch in expanded.chars().filter(|ch| ch.width().unwrap_or(0) > 0) {
+                    let cell = TerminalChar::with_attrs(ch, attrs.clone());
+                    if cell.width() == 2 {
+                        rewritten.push(cell);
+                        rewritten.push(TerminalChar::continuation(attrs.clone()));
+                    } else {
+                        rewritten.push(cell);
+                    }
+                }
+                if self.cursor.y as usize == y && self.cursor.x as usize >= end {
+                    shift += (rewritten.len() - before) as isize - (end - start) as isize;
+                }
+                copied = end;
+            }
+            if copied == 0 {
+                continue;
+            }
             
-            for (line_idx, line) in lines.iter().enumerate() {
-                if char_count + line.len() >= pos {
-                    let col = pos - char_count;
-                    return Some(CursorPosition::new(col as u16, line_idx as u16));
+            rewritten.extend_from_slice(&row[copied..]);
+            rewritten.resize(width, TerminalChar::default());
+            if let Some(last) = rewritten.last_mut() {
+                if !last.continuation && last.width() == 2 {
+                    *last = TerminalChar::default();
                 }
-                char_count += line.len() + 1; // +1 for newline
             }
+            *row = rewritten;
+            // Links on a rewritten line no longer line up with its text
+            self.hyperlinks.retain(|link| link.row as usize != y);
+            if self.cursor.y as usize == y {
+                self.cursor.x = (self.cursor.x as isize + shift).clamp(0, width as isize) as u16;
+            }
+        }
+    }
+
+    /// Rows that look different from `previous`, in order
+    ///
+    /// A row differs when any of its cells or hyperlinks do, or when the
+    /// cursor moves onto, off or within it or shows or hides there. Returns
+    /// `None` if the screens aren't the same size.
+    pub fn changed_rows(&self, previous: &TerminalState) -> Option<Vec<u16>> {
+        if self.size != previous.size {
+            return None;
         }
-        None
+        let links = |state: &TerminalState, y: u16| -> Vec<Hyperlink> {
+            state.hyperlinks.iter().filter(|link| link.row == y).cloned().collect()
+        };
+        let cursor_moved = self.cursor != previous.cursor || self.cursor_visible != previous.cursor_visible;
+        
+        let rows = (0..self.size.height)
+            .filter(|&y| {
+                let cursor_row = cursor_moved && (y == self.cursor.y || y == previous.cursor.y);
+                cursor_row
+                    || self.buffer.get(y as usize) != previous.buffer.get(y as usize)
+                    || links(self, y) != links(previous, y)
+            })
+            .collect();
+        Some(rows)
     }
 
     /// Resize terminal
@@ -217,6 +404,13 @@ impl TerminalState {
                     new_buffer[y as usize][x as usize] = ch.clone();
                 }
             }
+
+            // A wide character whose continuation was cut off no longer fits
+            if let Some(last) = new_buffer[y as usize].last_mut() {
+                if !last.continuation && last.width() == 2 {
+                    *last = TerminalChar::default();
+                }
+            }
         }
 
         self.buffer = new_buffer;
@@ -232,8 +426,102 @@ impl TerminalState {
     }
 }
 
+/// Text of one buffer row, skipping wide-character continuations
+fn row_text(row: &[TerminalChar], trim: LineTrim) -> String {
+    let mut cells: Vec<&TerminalChar> = row.iter().filter(|cell| !cell.continuation).collect();
+    while let Some(cell) = cells.last() {
+        // Reverse video paints the foreground color behind the cell
+        let colored = cell.attrs.bg_color.is_some() || cell.attrs.reverse;
+        if !cell.ch.is_whitespace() || (trim == LineTrim::DefaultBackground && colored) {
+            break;
+        }
+        cells.pop();
+    }
+    cells.iter().map(|cell| cell.ch).collect()
+}
+
 impl Default for TerminalState {
     fn default() -> Self {
         Self::new(TerminalSize::default())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(state: &mut TerminalState, y: u16, cells: &[(u16, char)]) {
+        for (x, ch) in cells {
+            state.set_char(*x, y, TerminalChar::new(*ch));
+        }
+    }
+
+    #[test]
+    fn test_find_text_after_multibyte_chars() {
+        let mut state = TerminalState::new(TerminalSize::new(20, 3));
+        // An emoji and CJK characters each cover two columns, as a terminal lays them out
+        put(&mut state, 0, &[(0, '🚀'), (2, 'o'), (3, 'k')]);
+        put(&mut state, 1, &[(0, '世'), (2, '界'), (5, 'h'), (6, 'i')]);
+
+        assert_eq!(state.find_text("ok"), Some(CursorPosition::new(2, 0)));
+        assert_eq!(state.find_text("界"), Some(CursorPosition::new(2, 1)));
+        assert_eq!(state.find_text("hi"), Some(CursorPosition::new(5, 1)));
+        assert_eq!(state.find_text("nope"), None);
+    }
+
+    #[test]
+    fn test_find_text_across_lines() {
+        let mut state = TerminalState::new(TerminalSize::new(10, 3));
+        put(&mut state, 0, &[(0, 'a'), (1, 'b')]);
+        put(&mut state, 1, &[(0, 'c')]);
+
+        assert_eq!(state.find_text("b\nc"), Some(CursorPosition::new(1, 0)));
+        assert_eq!(state.find_text("\nc"), Some(CursorPosition::new(2, 0)));
+        assert_eq!(state.find_text("c"), Some(CursorPosition::new(0, 1)));
+    }
+
+    #[test]
+    fn test_wide_chars_take_two_cells() {
+        let mut state = TerminalState::new(TerminalSize::new(10, 2));
+        state.put_str("世界");
+        assert_eq!(state.cursor, CursorPosition::new(4, 0));
+        state.put_char('!');
+
+        assert_eq!(state.get_char(0, 0).unwrap().ch, '世');
+        assert!(state.get_char(1, 0).unwrap().continuation);
+        assert_eq!(state.get_char(2, 0).unwrap().ch, '界');
+        assert_eq!(state.get_char(4, 0).unwrap().ch, '!');
+        assert_eq!(state.cursor, CursorPosition::new(5, 0));
+        assert_eq!(state.get_line_text(0).unwrap(), "世界!");
+        assert_eq!(state.get_text(), "世界!\n");
+
+        // Overwriting the right half of a wide char blanks the left half
+        state.set_char(1, 0, TerminalChar::new('x'));
+        assert_eq!(state.get_line_text(0).unwrap(), " x界!");
+    }
+
+    #[test]
+    fn test_colored_trailing_spaces_survive_trimming() {
+        let mut state = TerminalState::new(TerminalSize::new(12, 2));
+        let bar = CharAttributes { bg_color: Some(4), ..CharAttributes::default() };
+        for (x, ch) in "NORMAL    ".chars().enumerate() {
+            state.set_char(x as u16, 0, TerminalChar::with_attrs(ch, bar.clone()));
+        }
+        put(&mut state, 1, &[(0, 'o'), (1, 'k'), (2, ' ')]);
+
+        assert_eq!(state.get_line_text(0).unwrap(), "NORMAL");
+        assert_eq!(state.get_line_text_with(0, LineTrim::DefaultBackground).unwrap(), "NORMAL    ");
+        assert_eq!(state.get_line_text_with(1, LineTrim::DefaultBackground).unwrap(), "ok");
+        assert_eq!(state.get_text_with(LineTrim::DefaultBackground), "NORMAL    \nok");
+    }
+
+    #[test]
+    fn test_wide_char_wraps_at_line_end() {
+        let mut state = TerminalState::new(TerminalSize::new(5, 2));
+        state.put_str("abcd世");
+
+        assert_eq!(state.get_line_text(0).unwrap(), "abcd");
+        assert_eq!(state.get_line_text(1).unwrap(), "世");
+        assert_eq!(state.cursor, CursorPosition::new(2, 1));
+    }
+}